@@ -0,0 +1,1587 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crossterm::cursor::{MoveTo, SetCursorStyle};
+use crossterm::event::{KeyCode, KeyModifiers};
+use crossterm::terminal::{Clear, ClearType};
+use crossterm::QueueableCommand;
+use regex::RegexBuilder;
+
+use crate::buffer::{BufferStats, SelectionMode, TextBuffer};
+use crate::clipboard::Clipboard;
+use crate::command::{self, Command};
+use crate::config::EditorConfig;
+use crate::editorconfig;
+use crate::export;
+use crossterm::event::KeyEvent;
+
+use crate::keyboard::{Action, Input, Keyboard, Mode};
+use crate::printer::{cursor_style_for, Printer, Rect, StatusInfo};
+use crate::recovery;
+use crate::registry::CommandRegistry;
+use crate::session::Session;
+use crate::syntax;
+
+/// A file location parsed from a command-line argument, with optional
+/// 1-based line and column as produced by compilers and grep.
+#[derive(Debug, PartialEq, Eq)]
+struct FileTarget {
+    path: PathBuf,
+    line: Option<usize>,
+    col: Option<usize>,
+}
+
+/// Split a trailing `:line` or `:line:col` suffix off `arg`.
+///
+/// Only trailing groups of digits count, so paths that legitimately contain
+/// colons (Windows drive letters, timestamps in filenames) survive intact.
+fn parse_file_target(arg: &str) -> FileTarget {
+    let mut path = arg;
+    let mut line = None;
+    let mut col = None;
+
+    for _ in 0..2 {
+        if let Some((head, tail)) = path.rsplit_once(':') {
+            // A bare drive letter ("C:") is a path prefix, not a position.
+            if head.is_empty() || (head.len() == 1 && head.chars().all(|c| c.is_ascii_alphabetic()))
+            {
+                break;
+            }
+            if !tail.is_empty() && tail.chars().all(|c| c.is_ascii_digit()) {
+                col = line;
+                line = tail.parse().ok();
+                path = head;
+                continue;
+            }
+        }
+        break;
+    }
+
+    FileTarget {
+        path: PathBuf::from(path),
+        line,
+        col,
+    }
+}
+
+/// Screen region (top row, height) of each pane when `panes` panes split
+/// `height` rows horizontally, top to bottom. The bottom pane absorbs the
+/// odd row when the height doesn't divide evenly.
+fn split_regions(height: u16, panes: usize) -> Vec<(u16, u16)> {
+    if panes < 2 {
+        return vec![(0, height)];
+    }
+    let top = height / 2;
+    vec![(0, top), (top, height - top)]
+}
+
+/// Parse a 1-based `line` or `line:col` jump target.
+fn parse_goto(input: &str) -> Option<(usize, Option<usize>)> {
+    match input.split_once(':') {
+        Some((line, col)) => Some((line.trim().parse().ok()?, Some(col.trim().parse().ok()?))),
+        None => Some((input.trim().parse().ok()?, None)),
+    }
+}
+
+/// Jump positions kept before going back is impossible.
+const MAX_JUMPS: usize = 100;
+
+/// How long the editor may sit idle before modified buffers are written to
+/// their swap files.
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Files at or past this size open as read-only paged buffers that fault
+/// lines in from disk as the view moves, instead of being loaded whole.
+const PAGED_OPEN_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Open `path` the right way for its size; see [`PAGED_OPEN_BYTES`].
+fn open_buffer(path: &Path) -> io::Result<TextBuffer> {
+    let large = fs::metadata(path).is_ok_and(|m| m.len() >= PAGED_OPEN_BYTES);
+    if large {
+        TextBuffer::open_paged(path)
+    } else {
+        TextBuffer::from_file(path)
+    }
+}
+
+/// Cursor positions the user jumped away from, navigable like a browser
+/// history: going back and then jumping somewhere new discards the
+/// forward entries.
+struct JumpList {
+    positions: Vec<(usize, usize)>,
+    /// Index of the entry the cursor sits on while browsing backwards, or
+    /// `positions.len()` when at the live end.
+    index: usize,
+}
+
+impl JumpList {
+    fn new() -> Self {
+        JumpList {
+            positions: Vec::new(),
+            index: 0,
+        }
+    }
+
+    /// Remember `from` as the spot a jump left. Anything forward of the
+    /// browsing position is discarded, as is the oldest entry once the
+    /// list is full.
+    fn record(&mut self, from: (usize, usize)) {
+        self.positions.truncate(self.index);
+        self.positions.push(from);
+        if self.positions.len() > MAX_JUMPS {
+            self.positions.remove(0);
+        }
+        self.index = self.positions.len();
+    }
+
+    /// Step back one jump. `current` is remembered the first time so
+    /// [`forward`](Self::forward) can return to where browsing started.
+    fn back(&mut self, current: (usize, usize)) -> Option<(usize, usize)> {
+        if self.index == 0 {
+            return None;
+        }
+        if self.index == self.positions.len() {
+            self.positions.push(current);
+        }
+        self.index -= 1;
+        Some(self.positions[self.index])
+    }
+
+    fn forward(&mut self) -> Option<(usize, usize)> {
+        if self.index + 1 >= self.positions.len() {
+            return None;
+        }
+        self.index += 1;
+        Some(self.positions[self.index])
+    }
+}
+
+/// The editor: one buffer, the keyboard, and the screen, glued together by
+/// the main event loop.
+pub struct App {
+    /// All open buffers; each keeps its own path, cursor and scroll state.
+    buffers: Vec<TextBuffer>,
+    /// Index into `buffers` of the one being edited.
+    active: usize,
+    /// The buffer shown in each pane, top to bottom. A single entry when the
+    /// screen isn't split.
+    panes: Vec<usize>,
+    /// Index into `panes` of the pane holding the cursor. Its buffer is
+    /// always `active`.
+    focused_pane: usize,
+    keyboard: Keyboard,
+    printer: Printer,
+    clipboard: Clipboard,
+    /// Startup settings, kept around so buffers opened later inherit the
+    /// same per-buffer options.
+    config: EditorConfig,
+    /// Message shown on the status line until the next key press.
+    /// Transient status-line message (save results, search prompts,
+    /// errors). Cleared on the next main-loop keypress.
+    status_message: Option<String>,
+    /// The last recorded macro: the raw keys, replayed through the same
+    /// mapping as live input.
+    macro_keys: Vec<KeyEvent>,
+    /// True while keystrokes are being captured into `macro_keys`.
+    macro_recording: bool,
+    /// Set after the first quit attempt with unsaved changes; the next quit
+    /// goes through.
+    quit_pending: bool,
+    /// Positions left behind by search, goto and bracket jumps.
+    jumps: JumpList,
+    /// The last buffer-mutating action, replayed by repeat-last-edit.
+    last_edit: Option<Action>,
+    /// Remembered per-file cursor positions, restored on open and written
+    /// back on exit. Empty (and never written) with `save_session` off.
+    session: Session,
+    /// Commands invokable from command mode by bare name; see
+    /// [`CommandRegistry`]. Argument-less built-ins live here too.
+    registry: CommandRegistry,
+    /// True while a command or search prompt owns the keyboard, switching
+    /// the cursor to an underline instead of the writing mode's shape.
+    prompt_cursor: bool,
+    /// App-wide read-only mode (`-R` or `:set readonly`): navigation and
+    /// search work, anything that would change a buffer flashes a message.
+    read_only: bool,
+    running: bool,
+}
+
+impl App {
+    /// Build the editor from command-line arguments.
+    ///
+    /// The first argument, if present, names a file to open. A trailing
+    /// `:line` or `:line:col` positions the cursor there (clamped to the
+    /// file), unless the argument as a whole names an existing file. A `-R`
+    /// anywhere starts the editor in read-only mode, for viewing logs.
+    pub fn from_args(args: &[String]) -> io::Result<App> {
+        let read_only = args.iter().any(|a| a == "-R");
+        let args: Vec<String> = args
+            .iter()
+            .filter(|a| a.as_str() != "-R")
+            .cloned()
+            .collect();
+        let (config, warnings) = EditorConfig::load();
+        // An explicit `:line` argument beats any remembered position.
+        let mut positioned = false;
+        let mut buffer = match args.first() {
+            None => TextBuffer::new(),
+            Some(arg) => {
+                let path = Path::new(arg);
+                if path.exists() {
+                    open_buffer(path)?
+                } else {
+                    let target = parse_file_target(arg);
+                    if target.line.is_some() && target.path.exists() {
+                        let mut buf = open_buffer(&target.path)?;
+                        let line = target.line.unwrap_or(1).saturating_sub(1);
+                        let col = target.col.unwrap_or(1).saturating_sub(1);
+                        buf.set_cursor(line, col);
+                        positioned = true;
+                        buf
+                    } else {
+                        // No match either way: treat the whole argument as a
+                        // (possibly not yet existing) filename.
+                        TextBuffer::from_file(path)?
+                    }
+                }
+            }
+        };
+
+        let session = if config.save_session {
+            Session::load()
+        } else {
+            Session::default()
+        };
+        if !positioned {
+            if let Some((line, col, top)) = buffer.filename().and_then(|p| session.get(p)) {
+                buffer.set_cursor(line, col);
+                buffer.scroll_top = top.min(buffer.lines.len().saturating_sub(1));
+            }
+        }
+
+        let mut resolved = config.for_path(buffer.filename());
+        if let Some(path) = buffer.filename().map(Path::to_path_buf) {
+            editorconfig::lookup(&path).apply(&mut resolved, &mut buffer);
+        }
+        buffer.auto_indent = resolved.auto_indent;
+        buffer.auto_pairs = resolved.auto_pairs;
+        buffer.set_indent_style(resolved.indent_style);
+        buffer.set_max_undo(resolved.max_undo);
+        buffer.tab_width = resolved.tab_width;
+
+        // Set the terminal up before the printer switches screens, so a
+        // refusal (stdin is a pipe, not a TTY) prints a readable error to
+        // the normal screen instead of panicking mid-setup.
+        let keyboard = Keyboard::try_new().map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("cannot set up the terminal (is this a TTY?): {e}"),
+            )
+        })?;
+        let mut printer = Printer::new()?;
+        printer.set_tab_width(resolved.tab_width);
+        printer.show_line_numbers = config.show_line_numbers;
+        printer.set_number_mode(config.number_mode);
+        printer.show_whitespace = config.show_whitespace;
+        printer.show_trailing_whitespace = config.show_trailing_whitespace;
+        printer.cursor_line_highlight = config.cursor_line_highlight;
+        printer.set_rulers(config.rulers.clone());
+        printer.set_scroll_off(config.scroll_off);
+        printer.set_wrap(config.wrap);
+        if let Some(path) = buffer.filename() {
+            printer.set_highlighter(syntax::for_path(path));
+        }
+        Ok(App {
+            buffers: vec![buffer],
+            active: 0,
+            panes: vec![0],
+            focused_pane: 0,
+            keyboard,
+            printer,
+            clipboard: Clipboard::new(),
+            config,
+            status_message: (!warnings.is_empty()).then(|| warnings.join("; ")),
+            macro_keys: Vec::new(),
+            macro_recording: false,
+            quit_pending: false,
+            jumps: JumpList::new(),
+            last_edit: None,
+            session,
+            registry: Self::builtin_registry(),
+            prompt_cursor: false,
+            read_only,
+            running: true,
+        })
+    }
+
+    pub fn run(&mut self) -> io::Result<()> {
+        self.offer_recovery()?;
+        let mut last_autosave = Instant::now();
+        while self.running {
+            self.redraw()?;
+            if last_autosave.elapsed() >= AUTOSAVE_INTERVAL {
+                self.autosave();
+                last_autosave = Instant::now();
+            }
+            let remaining = AUTOSAVE_INTERVAL.saturating_sub(last_autosave.elapsed());
+            let Some(input) = self.keyboard.read_timeout(remaining)? else {
+                continue;
+            };
+            match input {
+                Input::Key(key) => self.handle_key(key)?,
+                Input::Action(action) => {
+                    self.status_message = None;
+                    self.quit_pending = false;
+                    self.apply(action)?;
+                }
+            }
+        }
+        self.cleanup()
+    }
+
+    /// Write every modified named buffer to its swap file, so a crash loses
+    /// at most one autosave interval of typing.
+    fn autosave(&mut self) {
+        for buffer in &self.buffers {
+            if let Some(path) = buffer.filename() {
+                if buffer.is_modified() {
+                    let _ = recovery::write_swap(path, &buffer.content());
+                }
+            }
+        }
+    }
+
+    /// If a newer swap file exists for the active buffer, offer to restore
+    /// its contents. Recovered text replaces the buffer as one undoable
+    /// edit and leaves it modified, so the user still decides whether to
+    /// keep it.
+    fn offer_recovery(&mut self) -> io::Result<()> {
+        let Some(path) = self.buffers[self.active].filename().map(Path::to_path_buf) else {
+            return Ok(());
+        };
+        let Some(swap) = recovery::check(&path) else {
+            return Ok(());
+        };
+        match self.prompt("Swap file found - recover unsaved changes? (y/n): ")? {
+            Some(answer) if answer.eq_ignore_ascii_case("y") => match fs::read_to_string(&swap) {
+                Ok(text) => {
+                    let text = text.replace("\r\n", "\n");
+                    self.buffers[self.active]
+                        .replace_contents(text.strip_suffix('\n').unwrap_or(&text));
+                    self.set_status(format!("Recovered {}", swap.display()));
+                }
+                Err(e) => self.set_status(format!("Cannot read {}: {e}", swap.display())),
+            },
+            _ => self.set_status("Swap file kept (save or exit cleanly to remove it)"),
+        }
+        Ok(())
+    }
+
+    /// Map and apply one key press, both for live input and macro playback.
+    /// While recording, the key is captured — except the macro keys
+    /// themselves, so a macro can't stop its own recording or recurse.
+    fn handle_key(&mut self, key: KeyEvent) -> io::Result<()> {
+        let action = self.keyboard.map_key(key);
+        self.status_message = None;
+        if !matches!(action, Action::Quit) {
+            self.quit_pending = false;
+        }
+        if self.macro_recording && !matches!(action, Action::RecordMacro | Action::PlayMacro) {
+            self.macro_keys.push(key);
+        }
+        self.apply(action)
+    }
+
+    /// Draw every pane, the focused one last so the terminal cursor ends up
+    /// inside it. The printer has one highlighter, pointed at the active
+    /// buffer's language; a split across two languages colors both panes
+    /// with the focused file's rules, which the per-line tokenizers shrug
+    /// off.
+    fn redraw(&mut self) -> io::Result<()> {
+        let style = if self.prompt_cursor {
+            SetCursorStyle::BlinkingUnderScore
+        } else {
+            cursor_style_for(
+                self.keyboard.mode(),
+                self.config.insert_cursor,
+                self.config.overwrite_cursor,
+            )
+        };
+        self.printer.set_cursor_style(style)?;
+        let regions = self.pane_regions();
+        for (pane, &(top, height)) in regions.iter().enumerate() {
+            if pane == self.focused_pane {
+                continue;
+            }
+            let idx = self.panes[pane];
+            self.buffers[idx].page_in((height as usize).saturating_sub(1));
+            self.buffers[idx].refresh_change_marks();
+            let info = self.status_info_for(idx, false);
+            self.printer
+                .draw_region(&mut self.buffers[idx], &info, top, height, false)?;
+        }
+        let (top, height) = regions[self.focused_pane];
+        self.buffers[self.active].page_in((height as usize).saturating_sub(1));
+        self.buffers[self.active].refresh_change_marks();
+        let info = self.status_info_for(self.active, true);
+        self.printer
+            .draw_region(&mut self.buffers[self.active], &info, top, height, true)
+    }
+
+    /// The screen region of every pane for the current terminal size.
+    fn pane_regions(&self) -> Vec<(u16, u16)> {
+        split_regions(self.printer.height, self.panes.len())
+    }
+
+    /// Text rows in the focused pane, which is what paging and scrolling
+    /// should move by.
+    fn focused_text_rows(&self) -> usize {
+        let (_, height) = self.pane_regions()[self.focused_pane];
+        (height as usize).saturating_sub(1)
+    }
+
+    /// True when the active buffer has a rectangular selection that edits
+    /// should apply to row by row.
+    fn block_selection_active(&self) -> bool {
+        let buffer = &self.buffers[self.active];
+        buffer.selection_mode() == SelectionMode::Block && buffer.block_selection().is_some()
+    }
+
+    /// The pane containing screen row `y` and that pane's top row.
+    fn pane_at(&self, y: u16) -> (usize, u16) {
+        self.pane_regions()
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, &(top, _))| y >= top)
+            .map(|(pane, &(top, _))| (pane, top))
+            .unwrap_or((0, 0))
+    }
+
+    /// Make buffer `idx` active. Scroll and cursor live on the buffer, so
+    /// switching back restores them; only the highlighter and the frame
+    /// cache belong to the printer and must be repointed.
+    fn switch_to(&mut self, idx: usize) {
+        self.active = idx;
+        self.panes[self.focused_pane] = idx;
+        let highlighter = self.buffers[idx].filename().and_then(syntax::for_path);
+        self.printer.set_highlighter(highlighter);
+        // Tab width lives on the printer, so a per-filetype override has to
+        // be re-applied whenever the shown file changes. The buffer keeps a
+        // copy for its visual-column movement math.
+        let mut resolved = self.config.for_path(self.buffers[idx].filename());
+        if let Some(path) = self.buffers[idx].filename().map(Path::to_path_buf) {
+            editorconfig::lookup(&path).apply(&mut resolved, &mut self.buffers[idx]);
+        }
+        self.printer.set_tab_width(resolved.tab_width);
+        self.buffers[idx].tab_width = resolved.tab_width;
+    }
+
+    /// Open `path` in a new buffer, or switch to it if it is already open.
+    fn open_file(&mut self, path: &str) -> io::Result<()> {
+        let path = PathBuf::from(path);
+        if let Some(idx) = self
+            .buffers
+            .iter()
+            .position(|b| b.filename() == Some(path.as_path()))
+        {
+            self.switch_to(idx);
+            return Ok(());
+        }
+        match open_buffer(&path) {
+            Ok(mut buffer) => {
+                let mut resolved = self.config.for_path(buffer.filename());
+                if let Some(path) = buffer.filename().map(Path::to_path_buf) {
+                    editorconfig::lookup(&path).apply(&mut resolved, &mut buffer);
+                }
+                buffer.auto_indent = resolved.auto_indent;
+                buffer.auto_pairs = resolved.auto_pairs;
+                buffer.set_indent_style(resolved.indent_style);
+                buffer.set_max_undo(resolved.max_undo);
+                buffer.tab_width = resolved.tab_width;
+                if let Some((line, col, top)) = buffer.filename().and_then(|p| self.session.get(p))
+                {
+                    buffer.set_cursor(line, col);
+                    buffer.scroll_top = top.min(buffer.lines.len().saturating_sub(1));
+                }
+                self.buffers.push(buffer);
+                self.switch_to(self.buffers.len() - 1);
+                self.offer_recovery()?;
+                if self.buffers[self.active].is_paged() {
+                    self.set_status(format!("{} opened read-only (paged)", path.display()));
+                }
+            }
+            Err(e) => self.set_status(format!("Cannot open {}: {e}", path.display())),
+        }
+        Ok(())
+    }
+
+    /// Put `text` on the clipboard, using the active buffer's line ending
+    /// when mirroring to the OS clipboard.
+    fn set_clipboard(&mut self, text: String) {
+        self.clipboard
+            .set(text, self.buffers[self.active].line_ending().as_str());
+    }
+
+    /// The clipboard contents with `\n` separators.
+    fn get_clipboard(&mut self) -> String {
+        self.clipboard.get()
+    }
+
+    /// Record the cursor's current position on the jump list. Called just
+    /// before a jump moves the cursor somewhere far away.
+    fn record_jump(&mut self) {
+        let buffer = &self.buffers[self.active];
+        self.jumps.record((buffer.cursor_line, buffer.cursor_col));
+    }
+
+    /// Re-read the active buffer's file from disk, confirming first when
+    /// that would throw away unsaved edits.
+    fn reload_active(&mut self) -> io::Result<()> {
+        let Some(path) = self.buffers[self.active].filename().map(Path::to_path_buf) else {
+            self.set_status("No file to reload");
+            return Ok(());
+        };
+        // Reloading would pull the whole file into memory, which is what
+        // paged mode exists to avoid.
+        if self.buffers[self.active].is_paged() {
+            self.set_status("Cannot reload a paged buffer");
+            return Ok(());
+        }
+        if self.buffers[self.active].is_modified() {
+            match self.prompt("Discard unsaved changes and reload? (y/n): ")? {
+                Some(answer) if answer.eq_ignore_ascii_case("y") => {}
+                _ => {
+                    self.set_status("Reload cancelled");
+                    return Ok(());
+                }
+            }
+        }
+        match self.buffers[self.active].reload_from(&path) {
+            Ok(()) => {
+                self.printer.invalidate();
+                self.set_status(format!("Reloaded {}", path.display()));
+            }
+            Err(e) => self.set_status(format!("Cannot reload {}: {e}", path.display())),
+        }
+        Ok(())
+    }
+
+    /// One status line listing every buffer, the active one in brackets.
+    fn list_buffers(&mut self) {
+        let entries: Vec<String> = self
+            .buffers
+            .iter()
+            .enumerate()
+            .map(|(i, b)| {
+                let name = b
+                    .filename()
+                    .map(|p| p.display().to_string())
+                    .unwrap_or_else(|| "[No Name]".to_string());
+                let star = if b.is_modified() { "*" } else { "" };
+                if i == self.active {
+                    format!("[{}:{name}{star}]", i + 1)
+                } else {
+                    format!("{}:{name}{star}", i + 1)
+                }
+            })
+            .collect();
+        self.set_status(entries.join("  "));
+    }
+
+    /// Show a transient message on the status line; it stays up until the
+    /// next keypress in the main loop.
+    fn set_status(&mut self, msg: impl Into<String>) {
+        self.status_message = Some(msg.into());
+    }
+
+    /// Status line contents for buffer `idx`. The transient message and the
+    /// overwrite indicator only appear on the focused pane.
+    fn status_info_for(&self, idx: usize, focused: bool) -> StatusInfo {
+        StatusInfo {
+            filename: self.buffers[idx]
+                .filename()
+                .map(|p| p.display().to_string()),
+            modified: self.buffers[idx].is_modified(),
+            overwrite: focused && self.keyboard.mode() == Mode::Overwrite,
+            read_only: self.read_only || self.buffers[idx].read_only,
+            message: if !focused {
+                String::new()
+            } else if let Some(message) = &self.status_message {
+                message.clone()
+            } else if self.macro_recording {
+                "Recording macro (Ctrl+R stops)".to_string()
+            } else {
+                String::new()
+            },
+        }
+    }
+
+    /// Write the buffer to its file, asking for a filename on the status
+    /// line first if the buffer doesn't have one yet.
+    fn save(&mut self) -> io::Result<()> {
+        // A paged buffer only has its resident window in memory; writing
+        // it out would truncate the file to placeholders.
+        if self.buffers[self.active].is_paged() {
+            self.set_status("Cannot write a paged buffer");
+            return Ok(());
+        }
+        if self.buffers[self.active].filename().is_none() {
+            match self.prompt("Save as: ")? {
+                Some(name) if !name.is_empty() => {
+                    let path = PathBuf::from(name);
+                    self.printer.set_highlighter(syntax::for_path(&path));
+                    self.buffers[self.active].set_filename(path);
+                }
+                _ => {
+                    self.set_status("Save cancelled");
+                    return Ok(());
+                }
+            }
+        }
+        let path = self.buffers[self.active]
+            .filename()
+            .expect("filename was just set")
+            .to_path_buf();
+        // Someone else (git, another editor) wrote the file since we read
+        // it; saving now would silently clobber their changes.
+        if self.buffers[self.active].disk_changed() {
+            match self.prompt("File changed on disk - overwrite? (y/n): ")? {
+                Some(answer) if answer.eq_ignore_ascii_case("y") => {}
+                _ => {
+                    self.set_status("Save cancelled (:reload discards your changes)");
+                    return Ok(());
+                }
+            }
+        }
+        // An `.editorconfig` may ask for save-time cleanup; the trim is a
+        // normal undoable edit, so it happens before the write.
+        if self.buffers[self.active].trim_on_save {
+            self.buffers[self.active].trim_trailing_whitespace();
+        }
+        match fs::write(&path, self.buffers[self.active].content()) {
+            Ok(()) => {
+                self.buffers[self.active].mark_saved();
+                recovery::remove_swap(&path);
+                self.set_status(format!("Saved {}", path.display()));
+            }
+            Err(e) => self.set_status(format!("Save failed: {e}")),
+        }
+        Ok(())
+    }
+
+    /// Incremental search: keystrokes refine the query and jump to the first
+    /// match from where the cursor started; Enter advances to the next
+    /// match; Esc leaves search mode. Alt+R toggles regex matching, Alt+C
+    /// case-insensitivity and Alt+W whole-word matching, as in Emacs'
+    /// isearch.
+    fn search(&mut self) -> io::Result<()> {
+        let origin = (
+            self.buffers[self.active].cursor_line,
+            self.buffers[self.active].cursor_col,
+        );
+        self.record_jump();
+        self.prompt_cursor = true;
+        let mut query = String::new();
+        let mut regex_mode = false;
+        let mut ignore_case = false;
+        let mut whole_word = false;
+        let mut error: Option<String> = None;
+        let mut found = true;
+        loop {
+            let mode = format!(
+                "{}{}{}",
+                if regex_mode { "[re]" } else { "" },
+                if ignore_case { "[ic]" } else { "" },
+                if whole_word { "[w]" } else { "" }
+            );
+            self.set_status(match &error {
+                Some(msg) => format!("Search{mode}: {query} ({msg})"),
+                None if found => format!("Search{mode}: {query}"),
+                None => format!("Search{mode}: {query} (not found)"),
+            });
+            self.redraw()?;
+            let key = self.keyboard.read_key()?;
+            let alt = key.modifiers.contains(KeyModifiers::ALT);
+            let mut rerun_from = None;
+            match key.code {
+                KeyCode::Char('r') if alt => {
+                    regex_mode = !regex_mode;
+                    rerun_from = Some(origin);
+                }
+                KeyCode::Char('c') if alt => {
+                    ignore_case = !ignore_case;
+                    rerun_from = Some(origin);
+                }
+                KeyCode::Char('w') if alt => {
+                    whole_word = !whole_word;
+                    rerun_from = Some(origin);
+                }
+                KeyCode::Char(c) => {
+                    query.push(c);
+                    rerun_from = Some(origin);
+                }
+                KeyCode::Backspace => {
+                    query.pop();
+                    if query.is_empty() {
+                        self.buffers[self.active].clear_selection();
+                        self.buffers[self.active].set_cursor(origin.0, origin.1);
+                        error = None;
+                        found = true;
+                    } else {
+                        rerun_from = Some(origin);
+                    }
+                }
+                KeyCode::Enter => {
+                    // Continue just past the current match.
+                    rerun_from = Some((
+                        self.buffers[self.active].cursor_line,
+                        self.buffers[self.active].cursor_col,
+                    ));
+                }
+                KeyCode::Esc => {
+                    self.status_message = None;
+                    self.prompt_cursor = false;
+                    return Ok(());
+                }
+                _ => {}
+            }
+            if let Some(from) = rerun_from {
+                match self.jump_to_search(&query, from, regex_mode, ignore_case, whole_word) {
+                    Ok(hit) => {
+                        found = hit;
+                        error = None;
+                    }
+                    Err(msg) => {
+                        found = false;
+                        error = Some(msg);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Move to the next match of `query` from `from`, honoring the search
+    /// mode flags. `Err` carries a user-facing message for regex patterns
+    /// that don't compile.
+    fn jump_to_search(
+        &mut self,
+        query: &str,
+        from: (usize, usize),
+        regex_mode: bool,
+        ignore_case: bool,
+        whole_word: bool,
+    ) -> Result<bool, String> {
+        if query.is_empty() {
+            return Ok(false);
+        }
+        if !regex_mode {
+            return Ok(self.jump_to_match(query, from, ignore_case, whole_word));
+        }
+        // In regex mode whole-word wraps the pattern in word boundaries,
+        // which use the same word-character class as the buffer's.
+        let pattern = if whole_word {
+            format!(r"\b(?:{query})\b")
+        } else {
+            query.to_string()
+        };
+        let re = RegexBuilder::new(&pattern)
+            .case_insensitive(ignore_case)
+            .build()
+            .map_err(|_| "bad pattern".to_string())?;
+        match self.buffers[self.active].find_regex(&re, from) {
+            Some((line, start, end)) => {
+                self.buffers[self.active].select_match((line, start), end - start);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Move to the next match of `query` from `from`; returns whether a
+    /// match was found. On no match the cursor stays where it is.
+    fn jump_to_match(
+        &mut self,
+        query: &str,
+        from: (usize, usize),
+        ignore_case: bool,
+        whole_word: bool,
+    ) -> bool {
+        match self.buffers[self.active].find_with(query, from, ignore_case, whole_word) {
+            Some(pos) => {
+                self.buffers[self.active].select_match(pos, query.chars().count());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Find-and-replace: prompt for both strings, then step through matches
+    /// with Enter or replace the rest in one go with `a`.
+    fn replace(&mut self) -> io::Result<()> {
+        let Some(needle) = self.prompt("Replace: ")? else {
+            return Ok(());
+        };
+        if needle.is_empty() {
+            return Ok(());
+        }
+        let Some(replacement) = self.prompt("With: ")? else {
+            return Ok(());
+        };
+        let mut replaced = 0usize;
+        loop {
+            self.set_status(format!(
+                "Replace: Enter=next  a=all  Esc=done  ({replaced} replaced)"
+            ));
+            self.redraw()?;
+            match self.keyboard.read_key()?.code {
+                KeyCode::Enter => {
+                    if self.buffers[self.active].replace_next(&needle, &replacement) {
+                        replaced += 1;
+                    } else {
+                        self.set_status(format!("No more matches ({replaced} replaced)"));
+                        return Ok(());
+                    }
+                }
+                KeyCode::Char('a') => {
+                    replaced += self.buffers[self.active].replace_all(&needle, &replacement);
+                    self.set_status(format!("Replaced {replaced} occurrence(s)"));
+                    return Ok(());
+                }
+                KeyCode::Esc => {
+                    self.set_status(format!("Replaced {replaced} occurrence(s)"));
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Jump to a 1-based `line` or `line:col` read from the status line,
+    /// clamping out-of-range values, then center the viewport on the cursor.
+    fn goto_line(&mut self) -> io::Result<()> {
+        let Some(input) = self.prompt("Go to line: ")? else {
+            return Ok(());
+        };
+        let Some((line, col)) = parse_goto(&input) else {
+            if !input.is_empty() {
+                self.set_status(format!("Invalid line number: {input}"));
+            }
+            return Ok(());
+        };
+        self.record_jump();
+        self.buffers[self.active]
+            .set_cursor(line.saturating_sub(1), col.unwrap_or(1).saturating_sub(1));
+        let half = self.focused_text_rows() / 2;
+        self.buffers[self.active].scroll_top =
+            self.buffers[self.active].cursor_line.saturating_sub(half);
+        Ok(())
+    }
+
+    /// Read a line of input on the status line. Returns `None` when the user
+    /// cancels with Esc.
+    fn prompt(&mut self, label: &str) -> io::Result<Option<String>> {
+        self.prompt_cursor = true;
+        let mut input = String::new();
+        let answer = loop {
+            self.set_status(format!("{label}{input}"));
+            self.redraw()?;
+            match self.keyboard.read_key()?.code {
+                KeyCode::Char(c) => input.push(c),
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Enter => break Some(input),
+                KeyCode::Esc => break None,
+                _ => {}
+            }
+        };
+        self.prompt_cursor = false;
+        Ok(answer)
+    }
+
+    /// Show the key-binding help overlay until Esc (or F1/q) dismisses it.
+    /// The listing comes from the live keymap, so user remaps show up.
+    fn show_help(&mut self) -> io::Result<()> {
+        let lines = self.keyboard.help_lines();
+        let mut selected = 0usize;
+        loop {
+            self.redraw()?;
+            let longest = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+            let width = ((longest + 4) as u16).min(self.printer.width);
+            let height = ((lines.len() + 2) as u16).min(self.printer.height);
+            let rect = Rect {
+                x: (self.printer.width - width) / 2,
+                y: (self.printer.height - height) / 2,
+                width,
+                height,
+            };
+            self.printer
+                .draw_popup(rect, "Help", &lines, Some(selected))?;
+            match self.keyboard.read_key()?.code {
+                KeyCode::Esc | KeyCode::F(1) | KeyCode::Char('q') => break,
+                KeyCode::Up => selected = selected.saturating_sub(1),
+                KeyCode::Down => selected = (selected + 1).min(lines.len().saturating_sub(1)),
+                _ => {}
+            }
+        }
+        self.printer.invalidate();
+        Ok(())
+    }
+
+    fn apply(&mut self, action: Action) -> io::Result<()> {
+        // Read-only mode and read-only buffers reject edits up front.
+        // Replace, Undo and Redo are guarded too: they are app-level rather
+        // than edit actions (repeat-last-edit must not replay them), but
+        // rewrite text all the same.
+        if (self.read_only || self.buffers[self.active].read_only)
+            && (action.is_edit() || matches!(action, Action::Replace | Action::Undo | Action::Redo))
+        {
+            self.set_status("Buffer is read-only");
+            return Ok(());
+        }
+        // Remember edits so repeat-last-edit can replay them wherever the
+        // cursor is next.
+        if action.is_edit() {
+            self.last_edit = Some(action.clone());
+        }
+        match action {
+            // Tab indents the whole selection when it spans lines; within a
+            // line it inserts indentation at the cursor.
+            Action::InsertChar('\t') => {
+                let tab_width = self.printer.tab_width();
+                match self.buffers[self.active].get_selection() {
+                    Some(((start, _), (end, _))) if start != end => {
+                        self.buffers[self.active].indent_lines(start, end, tab_width);
+                    }
+                    _ => self.buffers[self.active].insert_indent(tab_width),
+                }
+            }
+            Action::Unindent => {
+                let tab_width = self.printer.tab_width();
+                match self.buffers[self.active].get_selection() {
+                    Some(((start, _), (end, _))) => {
+                        self.buffers[self.active].unindent_lines(start, end, tab_width);
+                    }
+                    None => self.buffers[self.active].unindent_current_line(tab_width),
+                }
+            }
+            Action::InsertText(text) => {
+                // Terminals bracket-paste with whatever line endings the
+                // source had; the buffer stores bare `\n`.
+                let text = text.replace("\r\n", "\n").replace('\r', "\n");
+                self.buffers[self.active].paste(&text);
+            }
+            // With a block selection, typing inserts on every selected row
+            // and deleting removes the rectangle.
+            Action::InsertChar(c) if self.block_selection_active() => {
+                self.buffers[self.active].block_insert(&c.to_string());
+            }
+            Action::InsertChar(c) => match self.keyboard.mode() {
+                Mode::Insert => self.buffers[self.active].insert_char_smart(c),
+                Mode::Overwrite => self.buffers[self.active].overwrite_char(c),
+            },
+            Action::NewLine => self.buffers[self.active].insert_newline(),
+            Action::Backspace | Action::Delete if self.block_selection_active() => {
+                self.buffers[self.active].block_delete();
+            }
+            Action::Backspace => self.buffers[self.active].delete_char_before_cursor(),
+            Action::Delete => self.buffers[self.active].delete_char_at_cursor(),
+            Action::DeleteWordLeft => self.buffers[self.active].delete_word_before_cursor(),
+            Action::DeleteWordRight => self.buffers[self.active].delete_word_after_cursor(),
+            Action::DeleteToLineEnd => self.buffers[self.active].delete_to_line_end(),
+            Action::DeleteToLineStart => self.buffers[self.active].delete_to_line_start(),
+            Action::TransposeChars => self.buffers[self.active].transpose_chars(),
+            Action::ToggleFold => {
+                if !self.buffers[self.active].toggle_fold() {
+                    self.set_status("No foldable block here");
+                }
+            }
+            Action::MoveUp => self.buffers[self.active].move_up(),
+            Action::MoveDown => self.buffers[self.active].move_down(),
+            Action::MoveLeft => self.buffers[self.active].move_left(),
+            Action::MoveRight => self.buffers[self.active].move_right(),
+            Action::MoveWordLeft => self.buffers[self.active].move_word_left(),
+            Action::MoveWordRight => self.buffers[self.active].move_word_right(),
+            Action::LineStart => self.buffers[self.active].move_line_start_smart(),
+            Action::LineEnd => self.buffers[self.active].move_line_end(),
+            Action::SelectUp => self.buffers[self.active].select_up(),
+            Action::SelectDown => self.buffers[self.active].select_down(),
+            Action::SelectLeft => self.buffers[self.active].select_left(),
+            Action::SelectRight => self.buffers[self.active].select_right(),
+            Action::SelectLineStart => self.buffers[self.active].select_line_start(),
+            Action::SelectLineEnd => self.buffers[self.active].select_line_end(),
+            Action::SelectWordLeft => self.buffers[self.active].select_word_left(),
+            Action::SelectWordRight => self.buffers[self.active].select_word_right(),
+            Action::SelectBlockUp => self.buffers[self.active].select_block_up(),
+            Action::SelectBlockDown => self.buffers[self.active].select_block_down(),
+            Action::SelectBlockLeft => self.buffers[self.active].select_block_left(),
+            Action::SelectBlockRight => self.buffers[self.active].select_block_right(),
+            Action::DuplicateLine => self.buffers[self.active].duplicate_line(),
+            Action::ToggleComment => {
+                let prefix = self
+                    .config
+                    .for_path(self.buffers[self.active].filename())
+                    .comment_prefix
+                    .unwrap_or_else(|| {
+                        self.buffers[self.active]
+                            .filename()
+                            .and_then(syntax::comment_prefix)
+                            .unwrap_or("//")
+                            .to_string()
+                    });
+                let (start, end) = match self.buffers[self.active].get_selection() {
+                    Some((start, end)) => (start.0, end.0),
+                    None => (
+                        self.buffers[self.active].cursor_line,
+                        self.buffers[self.active].cursor_line,
+                    ),
+                };
+                self.buffers[self.active].toggle_comment(&prefix, start, end);
+            }
+            Action::JoinLines => self.buffers[self.active].join_line_below(),
+            Action::Cancel => {
+                self.buffers[self.active].clear_selection();
+                self.buffers[self.active].collapse_cursors();
+            }
+            Action::AddCursorBelow => self.buffers[self.active].add_cursor_below(),
+            Action::AddCursorNextMatch => {
+                if !self.buffers[self.active].add_cursor_at_next_match() {
+                    self.set_status("No further occurrence to add a cursor at");
+                }
+            }
+            Action::MatchBracket => {
+                let pos = (
+                    self.buffers[self.active].cursor_line,
+                    self.buffers[self.active].cursor_col,
+                );
+                if let Some((line, col)) = self.buffers[self.active].matching_bracket(pos) {
+                    self.record_jump();
+                    self.buffers[self.active].clear_selection();
+                    self.buffers[self.active].set_cursor(line, col);
+                }
+            }
+            Action::SelectMatchBracket => {
+                self.buffers[self.active].select_to_matching_bracket();
+            }
+            Action::SelectInsideBrackets => {
+                // Try every bracket type; the pair whose opener sits closest
+                // before the cursor is the innermost one.
+                let innermost = [('(', ')'), ('[', ']'), ('{', '}')]
+                    .into_iter()
+                    .filter_map(|(open, close)| {
+                        self.buffers[self.active]
+                            .enclosing_pair(open, close)
+                            .map(|(opener, _)| (opener, open, close))
+                    })
+                    .max_by_key(|&(opener, ..)| opener);
+                if let Some((_, open, close)) = innermost {
+                    self.buffers[self.active].select_inside_pair(open, close);
+                }
+            }
+            Action::JumpBack => {
+                let buffer = &self.buffers[self.active];
+                let current = (buffer.cursor_line, buffer.cursor_col);
+                match self.jumps.back(current) {
+                    // `set_cursor` clamps, so positions recorded before the
+                    // buffer shrank still land somewhere valid.
+                    Some((line, col)) => self.buffers[self.active].set_cursor(line, col),
+                    None => self.set_status("Already at the oldest jump"),
+                }
+            }
+            Action::JumpForward => match self.jumps.forward() {
+                Some((line, col)) => self.buffers[self.active].set_cursor(line, col),
+                None => self.set_status("Already at the newest jump"),
+            },
+            Action::RepeatEdit => match self.last_edit.clone() {
+                Some(edit) => self.apply(edit)?,
+                None => self.set_status("Nothing to repeat"),
+            },
+            Action::MoveLineUp => self.buffers[self.active].move_line_up(),
+            Action::MoveLineDown => self.buffers[self.active].move_line_down(),
+            Action::Click(x, y) => {
+                // Clicking a pane also focuses it.
+                let (pane, top) = self.pane_at(y);
+                if pane != self.focused_pane {
+                    self.focused_pane = pane;
+                    self.switch_to(self.panes[pane]);
+                }
+                let (line, col) =
+                    self.printer
+                        .buffer_position(&self.buffers[self.active], x, y - top);
+                self.buffers[self.active].clear_selection();
+                self.buffers[self.active].set_cursor(line, col);
+            }
+            Action::Drag(x, y) => {
+                // Drags stay in the pane where the click landed.
+                let (_, top) = self.pane_regions()[self.focused_pane];
+                let (line, col) = self.printer.buffer_position(
+                    &self.buffers[self.active],
+                    x,
+                    y.saturating_sub(top),
+                );
+                self.buffers[self.active].select_to(line, col);
+            }
+            Action::ScrollUp => self.scroll_view(-3),
+            Action::ScrollDown => self.scroll_view(3),
+            Action::PageUp => {
+                for _ in 0..self.focused_text_rows() {
+                    self.buffers[self.active].move_up();
+                }
+            }
+            Action::PageDown => {
+                for _ in 0..self.focused_text_rows() {
+                    self.buffers[self.active].move_down();
+                }
+            }
+            Action::Copy => {
+                let text = self.buffers[self.active].copy_selected_text();
+                self.set_clipboard(text);
+            }
+            Action::Cut => {
+                let text = self.buffers[self.active].cut_selected_text();
+                self.set_clipboard(text);
+            }
+            Action::Paste => {
+                let text = self.get_clipboard();
+                self.buffers[self.active].paste(&text);
+            }
+            Action::PasteReindent => {
+                let text = self.get_clipboard();
+                self.buffers[self.active].paste_reindent(&text);
+            }
+            Action::Undo => self.buffers[self.active].undo(),
+            Action::Redo => self.buffers[self.active].redo(),
+            Action::SelectAll => self.buffers[self.active].select_all(),
+            Action::Save => self.save()?,
+            Action::Find => self.search()?,
+            Action::Replace => self.replace()?,
+            Action::GotoLine => self.goto_line()?,
+            Action::CommandPalette => self.command_palette()?,
+            Action::BufferNext => self.switch_to((self.active + 1) % self.buffers.len()),
+            Action::BufferPrev => {
+                self.switch_to((self.active + self.buffers.len() - 1) % self.buffers.len())
+            }
+            Action::FocusNextPane => {
+                if self.panes.len() > 1 {
+                    self.focused_pane = (self.focused_pane + 1) % self.panes.len();
+                    self.switch_to(self.panes[self.focused_pane]);
+                }
+            }
+            Action::RecordMacro => {
+                if self.macro_recording {
+                    self.macro_recording = false;
+                    self.set_status(format!("Recorded {} key(s)", self.macro_keys.len()));
+                } else {
+                    self.macro_keys.clear();
+                    self.macro_recording = true;
+                }
+            }
+            Action::PlayMacro => self.play_macro()?,
+            Action::Help => self.show_help()?,
+            Action::ToggleOverwrite => {
+                // Mode is tracked by the keyboard; nothing to do here yet.
+            }
+            Action::Resize(w, h) => self.printer.resize(w, h),
+            Action::Quit => {
+                let unsaved = self.buffers.iter().filter(|b| b.is_modified()).count();
+                if unsaved > 0 && !self.quit_pending {
+                    self.quit_pending = true;
+                    self.set_status(format!(
+                        "{unsaved} buffer(s) with unsaved changes - Ctrl+Q again to quit, Ctrl+S to save"
+                    ));
+                } else {
+                    self.running = false;
+                }
+            }
+            Action::None => {}
+        }
+        Ok(())
+    }
+
+    /// Replay the recorded macro, prompting for a repeat count (empty input
+    /// means once). The whole run is one undo group, so a single undo
+    /// reverts everything the macro did.
+    fn play_macro(&mut self) -> io::Result<()> {
+        if self.macro_recording || self.macro_keys.is_empty() {
+            self.set_status("No macro recorded");
+            return Ok(());
+        }
+        let times = match self.prompt("Play macro times: ")? {
+            None => return Ok(()),
+            Some(input) if input.is_empty() => 1,
+            Some(input) => match input.trim().parse::<usize>() {
+                Ok(n) => n,
+                Err(_) => {
+                    self.set_status(format!("Invalid count: {input}"));
+                    return Ok(());
+                }
+            },
+        };
+        let keys = self.macro_keys.clone();
+        let mark = self.buffers[self.active].start_undo_group();
+        for _ in 0..times {
+            for &key in &keys {
+                self.handle_key(key)?;
+            }
+        }
+        self.buffers[self.active].end_undo_group(mark);
+        Ok(())
+    }
+
+    /// Read a command from the status line and run it. Parse errors land on
+    /// the status line instead of aborting anything.
+    fn command_palette(&mut self) -> io::Result<()> {
+        let Some(input) = self.prompt(": ")? else {
+            return Ok(());
+        };
+        self.run_command(&input)
+    }
+
+    /// Run one command-mode line: a registered command when the line is a
+    /// bare name the registry knows, the built-in parser otherwise. An
+    /// `Err` from a registered command lands on the status line, like a
+    /// parse error.
+    fn run_command(&mut self, input: &str) -> io::Result<()> {
+        if let Some(run) = self.registry.get(input.trim()) {
+            if let Err(msg) = run(self) {
+                self.set_status(msg);
+            }
+            return Ok(());
+        }
+        match command::parse(input) {
+            Ok(cmd) => self.dispatch(cmd)?,
+            Err(msg) => self.set_status(msg),
+        }
+        Ok(())
+    }
+
+    /// The argument-less built-ins, registered through the public registry
+    /// so the editor itself exercises the extension seam. Commands that
+    /// take arguments (`:goto 42`, `:e path`) stay in the parser; their
+    /// short aliases (`:w`, `:q`) do too.
+    fn builtin_registry() -> CommandRegistry {
+        let mut registry = CommandRegistry::new();
+        registry.register("write", |app: &mut App| {
+            app.save().map_err(|e| e.to_string())
+        });
+        registry.register("quit", |app: &mut App| {
+            app.apply(Action::Quit).map_err(|e| e.to_string())
+        });
+        registry.register("goto", |app: &mut App| {
+            app.goto_line().map_err(|e| e.to_string())
+        });
+        registry
+    }
+
+    fn dispatch(&mut self, cmd: Command) -> io::Result<()> {
+        if cmd.edits() && (self.read_only || self.buffers[self.active].read_only) {
+            self.set_status("Buffer is read-only");
+            return Ok(());
+        }
+        match cmd {
+            Command::Write => self.save()?,
+            // Plain quit goes through `apply` so the unsaved-changes guard
+            // applies to `:q` exactly as it does to the quit key.
+            Command::Quit => self.apply(Action::Quit)?,
+            Command::ForceQuit => self.running = false,
+            Command::WriteQuit => {
+                self.save()?;
+                if !self.buffers[self.active].is_modified() {
+                    self.running = false;
+                }
+            }
+            Command::Goto(line) => {
+                self.record_jump();
+                self.buffers[self.active].set_cursor(line - 1, 0);
+            }
+            Command::Edit(path) => self.open_file(&path)?,
+            Command::Reload => self.reload_active()?,
+            Command::BufferNext => self.apply(Action::BufferNext)?,
+            Command::BufferPrev => self.apply(Action::BufferPrev)?,
+            Command::Buffers => self.list_buffers(),
+            Command::Split => {
+                if self.panes.len() > 1 {
+                    self.set_status("Already split");
+                } else if self.printer.height < 4 {
+                    self.set_status("Not enough rows to split");
+                } else {
+                    // Both panes start on the active buffer; `:e` or the
+                    // buffer keys change what the focused one shows.
+                    self.panes.push(self.active);
+                    self.printer.invalidate();
+                }
+            }
+            Command::CloseSplit => {
+                self.panes = vec![self.active];
+                self.focused_pane = 0;
+                self.printer.invalidate();
+            }
+            Command::SetTabWidth(width) => {
+                self.printer.set_tab_width(width);
+                self.buffers[self.active].tab_width = width;
+            }
+            Command::SetLineNumbers(on) => {
+                self.printer.show_line_numbers = on;
+                self.printer.invalidate();
+            }
+            Command::SetNumberMode(mode) => {
+                self.printer.show_line_numbers = true;
+                self.printer.set_number_mode(mode);
+            }
+            Command::SetAutoIndent(on) => self.buffers[self.active].auto_indent = on,
+            Command::SetShowWhitespace(on) => {
+                self.printer.show_whitespace = on;
+                self.printer.invalidate();
+            }
+            Command::SetTrailingWhitespace(on) => {
+                self.printer.show_trailing_whitespace = on;
+                self.printer.invalidate();
+            }
+            Command::SetRulers(cols) => self.printer.set_rulers(cols),
+            Command::SetScrollOff(rows) => self.printer.set_scroll_off(rows),
+            Command::SetWrap(on) => self.printer.set_wrap(on),
+            Command::SetReadOnly(value) => {
+                self.read_only = value.unwrap_or(!self.read_only);
+                self.set_status(if self.read_only {
+                    "Read-only on"
+                } else {
+                    "Read-only off"
+                });
+            }
+            Command::Center => {
+                let rows = self.focused_text_rows();
+                let buffer = &mut self.buffers[self.active];
+                buffer.scroll_top = buffer.cursor_line.saturating_sub(rows / 2);
+            }
+            Command::SetIndentStyle(style) => self.buffers[self.active].indent_style = style,
+            Command::Stats => {
+                let (stats, scope) = match self.buffers[self.active].get_selection() {
+                    Some((start, end)) => {
+                        let text = self.buffers[self.active].text_in_range(start, end);
+                        (BufferStats::of_text(&text), "selection: ")
+                    }
+                    None => (self.buffers[self.active].stats(), ""),
+                };
+                self.set_status(format!(
+                    "{scope}{} lines, {} words, {} chars, {} bytes",
+                    stats.lines, stats.words, stats.chars, stats.bytes
+                ));
+            }
+            Command::Help => self.show_help()?,
+            Command::SetMark(name) => {
+                self.buffers[self.active].set_mark(name);
+                self.set_status(format!("Mark {name} set"));
+            }
+            Command::GotoMark(name) => {
+                let buffer = &mut self.buffers[self.active];
+                let from = (buffer.cursor_line, buffer.cursor_col);
+                if buffer.goto_mark(name) {
+                    self.jumps.record(from);
+                } else {
+                    self.set_status(format!("No mark {name}"));
+                }
+            }
+            Command::Sort {
+                descending,
+                case_insensitive,
+            } => {
+                let buffer = &mut self.buffers[self.active];
+                let (start, end) = match buffer.get_selection() {
+                    Some((start, end)) => (start.0, end.0),
+                    None => (0, buffer.lines.len() - 1),
+                };
+                buffer.sort_lines(start, end, descending, case_insensitive);
+            }
+            Command::Dedup { all, trim } => {
+                let buffer = &mut self.buffers[self.active];
+                let (start, end) = match buffer.get_selection() {
+                    Some((start, end)) => (start.0, end.0),
+                    None => (0, buffer.lines.len() - 1),
+                };
+                let removed = buffer.dedup_lines(start, end, !all, trim);
+                self.set_status(format!("Removed {removed} duplicate line(s)"));
+            }
+            Command::Upcase => self.buffers[self.active].upcase(),
+            Command::Downcase => self.buffers[self.active].downcase(),
+            Command::ToggleCase => self.buffers[self.active].toggle_case(),
+            Command::ExportHtml(path) => {
+                let buffer = &self.buffers[self.active];
+                let highlighter = buffer.filename().and_then(syntax::for_path);
+                let html = export::to_html(buffer, highlighter.as_deref());
+                match fs::write(&path, html) {
+                    Ok(()) => self.set_status(format!("Exported to {path}")),
+                    Err(err) => self.set_status(format!("Cannot export {path}: {err}")),
+                }
+            }
+            Command::ExpandTabs => self.buffers[self.active].expand_tabs(self.printer.tab_width()),
+            Command::UnexpandTabs => {
+                self.buffers[self.active].unexpand_leading_tabs(self.printer.tab_width())
+            }
+        }
+        Ok(())
+    }
+
+    /// Scroll the viewport by `delta` lines, dragging the cursor along just
+    /// enough to keep it on screen (otherwise the next draw would snap the
+    /// view back to the cursor).
+    fn scroll_view(&mut self, delta: isize) {
+        let max_top = self.buffers[self.active].lines.len().saturating_sub(1);
+        self.buffers[self.active].scroll_top = self.buffers[self.active]
+            .scroll_top
+            .saturating_add_signed(delta)
+            .min(max_top);
+        let last_visible =
+            self.buffers[self.active].scroll_top + self.focused_text_rows().saturating_sub(1);
+        let line = self.buffers[self.active]
+            .cursor_line
+            .clamp(self.buffers[self.active].scroll_top, last_visible);
+        if line != self.buffers[self.active].cursor_line {
+            let col = self.buffers[self.active].cursor_col;
+            self.buffers[self.active].set_cursor(line, col);
+        }
+    }
+
+    /// Leave the cursor somewhere sensible on the alternate screen before
+    /// [`Keyboard`]'s `Drop` impl switches back to the user's shell and
+    /// restores the terminal modes.
+    fn cleanup(&mut self) -> io::Result<()> {
+        if self.config.save_session {
+            for buffer in &self.buffers {
+                if let Some(path) = buffer.filename() {
+                    self.session.remember(
+                        path,
+                        (buffer.cursor_line, buffer.cursor_col, buffer.scroll_top),
+                    );
+                }
+            }
+            // A cache write failure shouldn't keep the terminal raw.
+            let _ = self.session.save();
+        }
+        // Quitting is an explicit decision about unsaved changes, so the
+        // autosaved swaps have served their purpose.
+        for buffer in &self.buffers {
+            if let Some(path) = buffer.filename() {
+                recovery::remove_swap(path);
+            }
+        }
+        let mut out = io::stdout();
+        out.queue(Clear(ClearType::All))?;
+        out.queue(MoveTo(0, 0))?;
+        out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_pane_owns_the_whole_screen() {
+        assert_eq!(split_regions(24, 1), vec![(0, 24)]);
+    }
+
+    #[test]
+    fn split_regions_cover_every_row_without_overlap() {
+        assert_eq!(split_regions(24, 2), vec![(0, 12), (12, 12)]);
+        // The bottom pane absorbs the odd row.
+        assert_eq!(split_regions(25, 2), vec![(0, 12), (12, 13)]);
+    }
+
+    #[test]
+    fn goto_accepts_line_and_line_col() {
+        assert_eq!(parse_goto("42"), Some((42, None)));
+        assert_eq!(parse_goto(" 42:8 "), Some((42, Some(8))));
+        assert_eq!(parse_goto("abc"), None);
+        assert_eq!(parse_goto("4:x"), None);
+    }
+
+    #[test]
+    fn goto_clamps_to_last_line() {
+        let mut buf = TextBuffer::new();
+        buf.paste("a\nb\nc");
+        buf.set_cursor(999, 0);
+        assert_eq!(buf.cursor_line, 2);
+    }
+
+    #[test]
+    fn jump_list_walks_back_through_recorded_positions() {
+        let mut jumps = JumpList::new();
+        jumps.record((0, 0));
+        jumps.record((10, 2));
+        jumps.record((20, 4));
+        // The cursor is now at (30, 0); back returns through the records,
+        // newest first.
+        assert_eq!(jumps.back((30, 0)), Some((20, 4)));
+        assert_eq!(jumps.back((20, 4)), Some((10, 2)));
+        // Forward retraces the same path, ending where browsing started.
+        assert_eq!(jumps.forward(), Some((20, 4)));
+        assert_eq!(jumps.forward(), Some((30, 0)));
+        assert_eq!(jumps.forward(), None);
+    }
+
+    #[test]
+    fn a_new_jump_discards_the_forward_entries() {
+        let mut jumps = JumpList::new();
+        jumps.record((0, 0));
+        jumps.record((10, 0));
+        assert_eq!(jumps.back((20, 0)), Some((10, 0)));
+        jumps.record((10, 0));
+        // (20, 0) is gone; back now returns to the positions before it.
+        assert_eq!(jumps.back((40, 0)), Some((10, 0)));
+        assert_eq!(jumps.back((10, 0)), Some((0, 0)));
+        assert_eq!(jumps.back((0, 0)), None);
+    }
+
+    #[test]
+    fn plain_path_has_no_position() {
+        let t = parse_file_target("src/main.rs");
+        assert_eq!(t.path, PathBuf::from("src/main.rs"));
+        assert_eq!(t.line, None);
+        assert_eq!(t.col, None);
+    }
+
+    #[test]
+    fn path_with_line() {
+        let t = parse_file_target("src/main.rs:42");
+        assert_eq!(t.path, PathBuf::from("src/main.rs"));
+        assert_eq!(t.line, Some(42));
+        assert_eq!(t.col, None);
+    }
+
+    #[test]
+    fn path_with_line_and_col() {
+        let t = parse_file_target("src/main.rs:42:8");
+        assert_eq!(t.path, PathBuf::from("src/main.rs"));
+        assert_eq!(t.line, Some(42));
+        assert_eq!(t.col, Some(8));
+    }
+
+    #[test]
+    fn windows_drive_letter_is_not_a_position() {
+        let t = parse_file_target("C:\\code\\main.rs:10");
+        assert_eq!(t.path, PathBuf::from("C:\\code\\main.rs"));
+        assert_eq!(t.line, Some(10));
+
+        let t = parse_file_target("C:\\code\\main.rs");
+        assert_eq!(t.path, PathBuf::from("C:\\code\\main.rs"));
+        assert_eq!(t.line, None);
+    }
+
+    #[test]
+    fn non_numeric_suffix_stays_in_path() {
+        let t = parse_file_target("notes:draft.txt");
+        assert_eq!(t.path, PathBuf::from("notes:draft.txt"));
+        assert_eq!(t.line, None);
+    }
+
+    #[test]
+    fn only_two_trailing_groups_are_stripped() {
+        let t = parse_file_target("a:1:2:3");
+        assert_eq!(t.path, PathBuf::from("a:1"));
+        assert_eq!(t.line, Some(2));
+        assert_eq!(t.col, Some(3));
+    }
+}
@@ -0,0 +1,4673 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use regex::Regex;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::paged::{LineProvider, PagedFile};
+use crate::printer::{char_col_at, visual_col};
+
+/// A single reversible edit. Positions are (line, char column); `text` may
+/// contain `\n`, which is how line splits, merges and multi-line pastes are
+/// captured.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum EditOp {
+    /// `text` was inserted starting at `line`/`col`.
+    Insert {
+        line: usize,
+        col: usize,
+        text: String,
+    },
+    /// `text` was removed starting at `line`/`col`.
+    Delete {
+        line: usize,
+        col: usize,
+        text: String,
+    },
+    /// Several edits applied in sequence but undone/redone as one unit,
+    /// e.g. a replace-all pass.
+    Group(Vec<EditOp>),
+}
+
+/// An [`EditOp`] plus where the cursor was before it, so undo can put the
+/// cursor back where the user was.
+#[derive(Debug, Clone)]
+struct EditRecord {
+    op: EditOp,
+    cursor_before: (usize, usize),
+    /// Set for single keystrokes, which may coalesce with the next one.
+    typed: bool,
+    /// When the record was made (or last extended); a pause longer than
+    /// [`COALESCE_TIMEOUT`] starts a new undo unit.
+    at: Instant,
+}
+
+/// How long a pause between keystrokes splits a typed run into separate
+/// undo units, so undo steps back through bursts of typing rather than a
+/// whole session or single characters.
+const COALESCE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How many undo records a buffer keeps before the oldest are discarded.
+const DEFAULT_MAX_UNDO: usize = 1000;
+
+/// The newline convention of the file behind a buffer. Detected on load and
+/// written back unchanged on save.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+}
+
+impl LineEnding {
+    /// The convention used for brand-new files.
+    fn platform_default() -> Self {
+        if cfg!(windows) {
+            LineEnding::Crlf
+        } else {
+            LineEnding::Lf
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+        }
+    }
+}
+
+/// Line, word, character and byte counts for a buffer or a selection.
+/// Characters are grapheme clusters, so an emoji counts once; newlines are
+/// not counted as characters or bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BufferStats {
+    pub lines: usize,
+    pub words: usize,
+    pub chars: usize,
+    pub bytes: usize,
+}
+
+impl BufferStats {
+    /// Stats for a standalone piece of text, e.g. the current selection.
+    pub fn of_text(text: &str) -> Self {
+        let mut stats = BufferStats::default();
+        for line in text.lines() {
+            stats.lines += 1;
+            stats.words += line.split_whitespace().count();
+            stats.chars += line.graphemes(true).count();
+            stats.bytes += line.len();
+        }
+        stats.lines = stats.lines.max(1);
+        stats
+    }
+}
+
+/// What the Tab key inserts: a literal tab, or spaces up to the next tab
+/// stop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndentStyle {
+    Tabs,
+    Spaces,
+}
+
+/// Whether the selection covers a contiguous run of the document or a
+/// rectangle of columns across several lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionMode {
+    Normal,
+    Block,
+}
+
+/// How a line differs from the file as it was loaded, for the gutter's
+/// change bar; see [`change_mark`](TextBuffer::change_mark).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeMark {
+    /// The line also appears, unchanged, in the on-disk version.
+    Unchanged,
+    /// The line has no counterpart in the on-disk version.
+    Added,
+    /// The line replaced one or more on-disk lines.
+    Modified,
+    /// On-disk lines were deleted just above this (otherwise unchanged)
+    /// line.
+    DeletedAbove,
+}
+
+/// Buffers larger than this (on either side of the diff) skip change-mark
+/// computation: the LCS table below is quadratic in the line count.
+const DIFF_MAX_LINES: usize = 10_000;
+
+/// Classify each line of `current` against `original`; see [`ChangeMark`].
+///
+/// Matching lines are found with a line-level LCS. Within each gap between
+/// matches, current lines paired with removed original lines count as
+/// modified, surplus current lines as added, and a gap that only removed
+/// lines puts a deletion mark on the line after it.
+fn diff_marks(original: &[String], current: &[String]) -> Vec<ChangeMark> {
+    let mut marks = vec![ChangeMark::Unchanged; current.len()];
+    if original.len() > DIFF_MAX_LINES || current.len() > DIFF_MAX_LINES {
+        return marks;
+    }
+    // Strip the common prefix and suffix first; the quadratic table then
+    // only covers the edited region, which is tiny for typical edits.
+    let prefix = original
+        .iter()
+        .zip(current)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = original[prefix..]
+        .iter()
+        .rev()
+        .zip(current[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old = &original[prefix..original.len() - suffix];
+    let new = &current[prefix..current.len() - suffix];
+
+    // lcs[i][j]: length of the longest common subsequence of old[i..] and
+    // new[j..].
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    loop {
+        // Walk one gap: lines skipped on either side before the next match.
+        let (gap_i, gap_j) = (i, j);
+        while i < old.len() || j < new.len() {
+            if i < old.len() && j < new.len() && old[i] == new[j] {
+                break;
+            }
+            if j < new.len() && (i >= old.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+                j += 1;
+            } else {
+                i += 1;
+            }
+        }
+        let removed = i - gap_i;
+        for (n, mark) in marks[prefix + gap_j..prefix + j].iter_mut().enumerate() {
+            *mark = if n < removed {
+                ChangeMark::Modified
+            } else {
+                ChangeMark::Added
+            };
+        }
+        if removed > 0 && j == gap_j && !marks.is_empty() {
+            // Pure deletion: mark the line the gap sits above, or the last
+            // line when the deletion was at the end of the file.
+            let below = (prefix + j).min(current.len().saturating_sub(1));
+            if marks[below] == ChangeMark::Unchanged {
+                marks[below] = ChangeMark::DeletedAbove;
+            }
+        }
+        if i >= old.len() && j >= new.len() {
+            break;
+        }
+        i += 1;
+        j += 1;
+    }
+    marks
+}
+
+/// The in-memory text being edited, plus the cursor and scroll state that
+/// belongs to it.
+///
+/// Lines are stored without their trailing newline. The cursor column is a
+/// character index into the current line, not a byte index.
+pub struct TextBuffer {
+    pub lines: Vec<String>,
+    pub cursor_line: usize,
+    pub cursor_col: usize,
+    /// Column the user is aiming for when moving vertically; lets the cursor
+    /// snap back out to its old column after crossing short lines. Stored
+    /// in visual columns, so tab-indented lines don't make the cursor
+    /// drift sideways on screen.
+    desired_col: usize,
+    pub scroll_top: usize,
+    /// First visible column (in screen cells) when long lines force the
+    /// viewport to scroll sideways.
+    pub scroll_left: usize,
+    /// Where the selection started, or `None` when nothing is selected. The
+    /// other end of the selection is the cursor itself.
+    selection_anchor: Option<(usize, usize)>,
+    /// How the anchor and cursor are interpreted; see [`SelectionMode`].
+    selection_mode: SelectionMode,
+    /// Secondary cursor positions for multi-cursor editing, kept sorted in
+    /// document order and distinct from the primary.
+    extra_cursors: Vec<(usize, usize)>,
+    /// Named marks (`m{char}` / `` `{char} ``), adjusted as edits move the
+    /// text they point at.
+    marks: HashMap<char, (usize, usize)>,
+    /// Collapsed indentation blocks as `(header line, last hidden line)`
+    /// pairs, sorted; lines strictly inside a pair are not rendered.
+    folds: Vec<(usize, usize)>,
+    /// The file this buffer was loaded from, or will be saved to.
+    filename: Option<PathBuf>,
+    /// True when the buffer has edits that have not been written to disk.
+    modified: bool,
+    /// The file's mtime when we last loaded or saved it, for spotting
+    /// external changes before they get overwritten.
+    disk_mtime: Option<SystemTime>,
+    /// Newline style the file uses; preserved across load and save.
+    line_ending: LineEnding,
+    /// The lines as loaded from (or last saved to) disk, diffed against
+    /// `lines` for the gutter's change bar. `None` when there is no on-disk
+    /// version to compare with.
+    original_lines: Option<Vec<String>>,
+    /// Cached result of diffing against `original_lines`; cleared by every
+    /// edit and rebuilt by [`refresh_change_marks`](Self::refresh_change_marks).
+    change_marks: Option<Vec<ChangeMark>>,
+    /// Edits are rejected up front; set for paged buffers, where partial
+    /// edits of a file that is mostly not in memory would lose data.
+    pub read_only: bool,
+    /// For paged (large-file) buffers: the provider lines are faulted in
+    /// from as the view moves. `None` for ordinary buffers.
+    pager: Option<Box<dyn LineProvider>>,
+    /// The `start..end` line range currently faulted in from the pager;
+    /// lines outside it are placeholders.
+    resident: (usize, usize),
+    /// Copy the current line's leading whitespace onto new lines.
+    pub auto_indent: bool,
+    /// Auto-close brackets and quotes as they are typed.
+    pub auto_pairs: bool,
+    pub indent_style: IndentStyle,
+    /// Tab stops for the visual-column math in cursor movement; kept in
+    /// sync with the printer's tab width.
+    pub tab_width: usize,
+    /// Strip trailing whitespace from every line just before saving; set
+    /// from an `.editorconfig`, off by default.
+    pub trim_on_save: bool,
+    /// Write a newline after the last line, the editor's usual behavior;
+    /// an `.editorconfig` with `insert_final_newline = false` turns it off.
+    pub final_newline: bool,
+    undo_stack: Vec<EditRecord>,
+    redo_stack: Vec<EditRecord>,
+    /// Undo records kept before the oldest are discarded.
+    max_undo: usize,
+}
+
+impl TextBuffer {
+    pub fn new() -> Self {
+        TextBuffer {
+            lines: vec![String::new()],
+            cursor_line: 0,
+            cursor_col: 0,
+            desired_col: 0,
+            scroll_top: 0,
+            scroll_left: 0,
+            selection_anchor: None,
+            selection_mode: SelectionMode::Normal,
+            extra_cursors: Vec::new(),
+            marks: HashMap::new(),
+            folds: Vec::new(),
+            filename: None,
+            modified: false,
+            disk_mtime: None,
+            line_ending: LineEnding::platform_default(),
+            original_lines: None,
+            change_marks: None,
+            read_only: false,
+            pager: None,
+            resident: (0, 0),
+            auto_indent: true,
+            auto_pairs: true,
+            indent_style: IndentStyle::Tabs,
+            tab_width: 4,
+            trim_on_save: false,
+            final_newline: true,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            max_undo: DEFAULT_MAX_UNDO,
+        }
+    }
+
+    /// Build a buffer from in-memory text, for tests and embedding. CRLF
+    /// endings are detected and stripped like [`from_file`](Self::from_file)
+    /// does; unlike a file load, a trailing newline is kept as a final
+    /// empty line, so [`into_string`](Self::into_string) round-trips the
+    /// input exactly.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Self {
+        let mut buf = TextBuffer::new();
+        if s.contains("\r\n") {
+            buf.line_ending = LineEnding::Crlf;
+        }
+        buf.lines = s
+            .split('\n')
+            .map(|line| line.strip_suffix('\r').unwrap_or(line).to_string())
+            .collect();
+        buf
+    }
+
+    /// The buffer's text with its own line endings, the exact inverse of
+    /// [`from_str`](Self::from_str).
+    #[allow(dead_code)] // embedding API; the editor itself saves via `content`
+    pub fn into_string(self) -> String {
+        self.lines.join(self.line_ending.as_str())
+    }
+
+    /// Load `path` into a buffer. A file that does not exist yet yields an
+    /// empty buffer that remembers the path, so it can be created on save.
+    /// Splitting with [`str::lines`] keeps the last line even when the file
+    /// has no trailing newline, and strips the `\r` of CRLF endings so it
+    /// never shows up as a control character; the detected ending style is
+    /// remembered and restored by [`content`](Self::content).
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        let mut buf = if path.exists() {
+            let content = fs::read_to_string(path)?;
+            let mut buf = TextBuffer::from_str(&content);
+            // A file's trailing newline is a terminator, not an extra empty
+            // line; `content` puts it back on save.
+            if buf.lines.len() > 1 && buf.lines.last().is_some_and(|l| l.is_empty()) {
+                buf.lines.pop();
+            }
+            if !content.is_empty() && !content.contains("\r\n") {
+                buf.line_ending = LineEnding::Lf;
+            }
+            buf.disk_mtime = fs::metadata(path).and_then(|m| m.modified()).ok();
+            buf.original_lines = Some(buf.lines.clone());
+            buf
+        } else {
+            TextBuffer::new()
+        };
+        buf.filename = Some(path.to_path_buf());
+        Ok(buf)
+    }
+
+    /// Open `path` as a read-only paged buffer: every line starts as an
+    /// empty placeholder (a constant few bytes each) and the text itself is
+    /// faulted in by [`page_in`](Self::page_in) as the view moves, so a
+    /// multi-gigabyte file never sits in memory whole.
+    pub fn open_paged(path: &Path) -> io::Result<Self> {
+        let pager = PagedFile::open(path)?;
+        let mut buf = TextBuffer::new();
+        buf.lines = vec![String::new(); pager.line_count()];
+        buf.disk_mtime = fs::metadata(path).and_then(|m| m.modified()).ok();
+        buf.filename = Some(path.to_path_buf());
+        buf.pager = Some(Box::new(pager));
+        buf.read_only = true;
+        Ok(buf)
+    }
+
+    /// Whether this buffer streams its lines from disk instead of holding
+    /// them all; see [`open_paged`](Self::open_paged).
+    pub fn is_paged(&self) -> bool {
+        self.pager.is_some()
+    }
+
+    /// Fault in the lines the next draw of a `rows`-row viewport will need,
+    /// plus a margin of `rows` on each side, and release what scrolled out
+    /// of that window. Predicts where scrolling will put the view, so the
+    /// lines are there before the printer reads them. No-op for ordinary
+    /// buffers.
+    pub fn page_in(&mut self, rows: usize) {
+        if self.pager.is_none() {
+            return;
+        }
+        let top = if self.cursor_line < self.scroll_top {
+            self.cursor_line
+        } else if rows > 0 && self.cursor_line >= self.scroll_top + rows {
+            self.cursor_line + 1 - rows
+        } else {
+            self.scroll_top
+        };
+        let start = top.saturating_sub(rows);
+        let end = (top + rows * 2).min(self.lines.len());
+        if start >= self.resident.0 && end <= self.resident.1 {
+            return;
+        }
+        let Ok(fetched) = self
+            .pager
+            .as_mut()
+            .expect("checked above")
+            .fetch(start, end)
+        else {
+            return;
+        };
+        for line in self.resident.0..self.resident.1 {
+            if line < start || line >= end {
+                self.lines[line] = String::new();
+            }
+        }
+        for (i, line) in fetched.into_iter().enumerate() {
+            self.lines[start + i] = line;
+        }
+        self.resident = (start, end);
+    }
+
+    /// Recompute the change marks if an edit invalidated them, so the
+    /// printer can read [`change_mark`](Self::change_mark) without a
+    /// mutable borrow. Called once per redraw, like
+    /// [`page_in`](Self::page_in).
+    pub fn refresh_change_marks(&mut self) {
+        if self.change_marks.is_none() {
+            if let Some(original) = &self.original_lines {
+                self.change_marks = Some(diff_marks(original, &self.lines));
+            }
+        }
+    }
+
+    /// How `line` differs from the on-disk version, as of the last
+    /// [`refresh_change_marks`](Self::refresh_change_marks) pass.
+    pub fn change_mark(&self, line: usize) -> ChangeMark {
+        self.change_marks
+            .as_ref()
+            .and_then(|marks| marks.get(line))
+            .copied()
+            .unwrap_or(ChangeMark::Unchanged)
+    }
+
+    pub fn filename(&self) -> Option<&Path> {
+        self.filename.as_deref()
+    }
+
+    pub fn set_filename(&mut self, path: PathBuf) {
+        self.filename = Some(path);
+    }
+
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    /// Override the newline convention used on save, e.g. from an
+    /// `.editorconfig`'s `end_of_line`.
+    pub fn set_line_ending(&mut self, ending: LineEnding) {
+        self.line_ending = ending;
+    }
+
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    /// Mark the buffer as in sync with the file on disk, remembering the
+    /// file's new mtime so [`disk_changed`](Self::disk_changed) measures
+    /// from this save.
+    pub fn mark_saved(&mut self) {
+        self.modified = false;
+        // The buffer is the on-disk version now; the change bar starts over.
+        self.original_lines = Some(self.lines.clone());
+        self.change_marks = None;
+        self.disk_mtime = self
+            .filename
+            .as_deref()
+            .and_then(|p| fs::metadata(p).and_then(|m| m.modified()).ok());
+    }
+
+    /// True when something external wrote to the file since this buffer
+    /// last loaded or saved it. A file we cannot stat (deleted, never
+    /// existed) counts as unchanged — saving is the only way forward there.
+    pub fn disk_changed(&self) -> bool {
+        let (Some(path), Some(known)) = (self.filename.as_deref(), self.disk_mtime) else {
+            return false;
+        };
+        match fs::metadata(path).and_then(|m| m.modified()) {
+            Ok(on_disk) => on_disk > known,
+            Err(_) => false,
+        }
+    }
+
+    /// Replace the contents with what `path` holds now, discarding unsaved
+    /// edits and the undo history. The cursor and scroll are clamped so
+    /// they stay valid when the file shrank.
+    pub fn reload_from(&mut self, path: &Path) -> io::Result<()> {
+        let fresh = TextBuffer::from_file(path)?;
+        self.lines = fresh.lines;
+        self.line_ending = fresh.line_ending;
+        self.disk_mtime = fresh.disk_mtime;
+        self.original_lines = fresh.original_lines;
+        self.change_marks = None;
+        self.filename = Some(path.to_path_buf());
+        self.modified = false;
+        self.clear_selection();
+        self.collapse_cursors();
+        self.marks.clear();
+        self.folds.clear();
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.set_cursor(self.cursor_line, self.cursor_col);
+        self.scroll_top = self.scroll_top.min(self.lines.len() - 1);
+        Ok(())
+    }
+
+    /// The whole buffer as written to disk: every line followed by the
+    /// buffer's newline style, so the file ends in exactly one newline
+    /// (unless `final_newline` is off) and CRLF files stay CRLF.
+    pub fn content(&self) -> String {
+        let ending = self.line_ending.as_str();
+        let mut out =
+            String::with_capacity(self.lines.iter().map(|l| l.len() + ending.len()).sum());
+        let last = self.lines.len().saturating_sub(1);
+        for (idx, line) in self.lines_iter().enumerate() {
+            out.push_str(line);
+            if idx < last || self.final_newline {
+                out.push_str(ending);
+            }
+        }
+        out
+    }
+
+    /// Borrowing iterator over the lines, for read paths that would
+    /// otherwise clone the whole document via [`content`](Self::content).
+    pub fn lines_iter(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().map(String::as_str)
+    }
+
+    /// The lines with indices in `start..end`, clamped to the buffer, so a
+    /// caller walking a window of the text never touches lines outside it.
+    pub fn lines_in_range(&self, start: usize, end: usize) -> &[String] {
+        let end = end.min(self.lines.len());
+        let start = start.min(end);
+        &self.lines[start..end]
+    }
+
+    fn current_line(&self) -> &String {
+        &self.lines[self.cursor_line]
+    }
+
+    fn line_char_count(&self, line: usize) -> usize {
+        self.lines[line].chars().count()
+    }
+
+    /// Byte offset of character `col` in `line`, so `String` mutations land
+    /// between characters and never split a multi-byte sequence.
+    fn byte_index(line: &str, col: usize) -> usize {
+        line.char_indices()
+            .nth(col)
+            .map(|(i, _)| i)
+            .unwrap_or(line.len())
+    }
+
+    /// Char column where the grapheme cluster ending at `col` starts, so the
+    /// cursor steps over emoji and combining sequences as one unit.
+    fn prev_grapheme_start(line: &str, col: usize) -> usize {
+        let byte_col = Self::byte_index(line, col);
+        let start = line[..byte_col]
+            .grapheme_indices(true)
+            .next_back()
+            .map(|(i, _)| i)
+            .unwrap_or(0);
+        line[..start].chars().count()
+    }
+
+    /// True for the characters that make up a word: letters, digits and
+    /// underscores. Everything else is punctuation or whitespace.
+    fn is_word_char(c: char) -> bool {
+        c.is_alphanumeric() || c == '_'
+    }
+
+    /// Char column where the word-delete reaching back from `col` should
+    /// stop: the start of the whitespace run, word or punctuation run
+    /// immediately before the cursor.
+    fn word_start_before(line: &str, col: usize) -> usize {
+        let chars: Vec<char> = line.chars().collect();
+        let mut i = col.min(chars.len());
+        if i > 0 && chars[i - 1].is_whitespace() {
+            while i > 0 && chars[i - 1].is_whitespace() {
+                i -= 1;
+            }
+        } else if i > 0 && Self::is_word_char(chars[i - 1]) {
+            while i > 0 && Self::is_word_char(chars[i - 1]) {
+                i -= 1;
+            }
+        } else {
+            while i > 0 && !chars[i - 1].is_whitespace() && !Self::is_word_char(chars[i - 1]) {
+                i -= 1;
+            }
+        }
+        i
+    }
+
+    /// Char column where the word-delete reaching forward from `col` should
+    /// stop. Mirror image of [`word_start_before`](Self::word_start_before).
+    fn word_end_after(line: &str, col: usize) -> usize {
+        let chars: Vec<char> = line.chars().collect();
+        let mut i = col.min(chars.len());
+        let n = chars.len();
+        if i < n && chars[i].is_whitespace() {
+            while i < n && chars[i].is_whitespace() {
+                i += 1;
+            }
+        } else if i < n && Self::is_word_char(chars[i]) {
+            while i < n && Self::is_word_char(chars[i]) {
+                i += 1;
+            }
+        } else {
+            while i < n && !chars[i].is_whitespace() && !Self::is_word_char(chars[i]) {
+                i += 1;
+            }
+        }
+        i
+    }
+
+    /// The char column of the word boundary left of `pos`: any whitespace
+    /// is skipped, then the word or punctuation run before it. Word
+    /// movement, word selection and word deletion all share these rules, so
+    /// `foo.bar_baz` has stops at `foo`, `.` and `bar_baz`.
+    pub fn word_boundary_left(&self, pos: (usize, usize)) -> usize {
+        let line = &self.lines[pos.0];
+        let chars: Vec<char> = line.chars().collect();
+        let mut i = pos.1.min(chars.len());
+        while i > 0 && chars[i - 1].is_whitespace() {
+            i -= 1;
+        }
+        Self::word_start_before(line, i)
+    }
+
+    /// The char column of the word boundary right of `pos`. Mirror image of
+    /// [`word_boundary_left`](Self::word_boundary_left).
+    pub fn word_boundary_right(&self, pos: (usize, usize)) -> usize {
+        let line = &self.lines[pos.0];
+        let chars: Vec<char> = line.chars().collect();
+        let mut i = pos.1.min(chars.len());
+        while i < chars.len() && chars[i].is_whitespace() {
+            i += 1;
+        }
+        Self::word_end_after(line, i)
+    }
+
+    /// Where a leftward word step from the cursor lands: the previous word
+    /// boundary, or the end of the previous line when already at column 0.
+    fn word_left_position(&self) -> (usize, usize) {
+        if self.cursor_col == 0 {
+            if self.cursor_line == 0 {
+                return (0, 0);
+            }
+            return (
+                self.cursor_line - 1,
+                self.line_char_count(self.cursor_line - 1),
+            );
+        }
+        (
+            self.cursor_line,
+            self.word_boundary_left((self.cursor_line, self.cursor_col)),
+        )
+    }
+
+    /// Where a rightward word step from the cursor lands: the next word
+    /// boundary, or the start of the next line when already at the end.
+    fn word_right_position(&self) -> (usize, usize) {
+        if self.cursor_col == self.line_char_count(self.cursor_line) {
+            if self.cursor_line + 1 == self.lines.len() {
+                return (self.cursor_line, self.cursor_col);
+            }
+            return (self.cursor_line + 1, 0);
+        }
+        (
+            self.cursor_line,
+            self.word_boundary_right((self.cursor_line, self.cursor_col)),
+        )
+    }
+
+    /// Char column just past the grapheme cluster starting at `col`.
+    fn next_grapheme_end(line: &str, col: usize) -> usize {
+        let byte_col = Self::byte_index(line, col);
+        let end = line[byte_col..]
+            .graphemes(true)
+            .next()
+            .map(|g| byte_col + g.len())
+            .unwrap_or(line.len());
+        line[..end].chars().count()
+    }
+
+    /// The position of the bracket matching the one at `pos`, scanning
+    /// forward from an opener or backward from a closer and respecting
+    /// nesting. `None` when `pos` is not on a bracket or the bracket is
+    /// unbalanced. Purely textual: brackets in strings and comments count.
+    pub fn matching_bracket(&self, pos: (usize, usize)) -> Option<(usize, usize)> {
+        let (line, col) = pos;
+        let c = self.lines.get(line)?.chars().nth(col)?;
+        let (open, close, forward) = match c {
+            '(' => ('(', ')', true),
+            '[' => ('[', ']', true),
+            '{' => ('{', '}', true),
+            ')' => ('(', ')', false),
+            ']' => ('[', ']', false),
+            '}' => ('{', '}', false),
+            _ => return None,
+        };
+        let mut depth = 1usize;
+        if forward {
+            let mut start = col + 1;
+            for l in line..self.lines.len() {
+                for (i, ch) in self.lines[l].chars().enumerate().skip(start) {
+                    if ch == open {
+                        depth += 1;
+                    } else if ch == close {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Some((l, i));
+                        }
+                    }
+                }
+                start = 0;
+            }
+        } else {
+            return self.scan_back_for_opener(open, close, pos);
+        }
+        None
+    }
+
+    /// The first `open` scanning backward from `pos` (exclusive) that is not
+    /// matched by an intervening `close`, respecting nesting.
+    fn scan_back_for_opener(
+        &self,
+        open: char,
+        close: char,
+        pos: (usize, usize),
+    ) -> Option<(usize, usize)> {
+        let mut depth = 1usize;
+        let mut end = pos.1;
+        for l in (0..=pos.0).rev() {
+            let chars: Vec<char> = self.lines[l].chars().collect();
+            for i in (0..end.min(chars.len())).rev() {
+                if chars[i] == close {
+                    depth += 1;
+                } else if chars[i] == open {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some((l, i));
+                    }
+                }
+            }
+            end = usize::MAX;
+        }
+        None
+    }
+
+    /// The nearest `open`/`close` pair enclosing the cursor, as the two
+    /// bracket positions. With the cursor directly on an opener that pair
+    /// is used; on a closer, the backward scan finds its own opener.
+    pub fn enclosing_pair(
+        &self,
+        open: char,
+        close: char,
+    ) -> Option<((usize, usize), (usize, usize))> {
+        let pos = (self.cursor_line, self.cursor_col);
+        let opener = if self.lines[pos.0].chars().nth(pos.1) == Some(open) {
+            pos
+        } else {
+            self.scan_back_for_opener(open, close, pos)?
+        };
+        let closer = self.matching_bracket(opener)?;
+        Some((opener, closer))
+    }
+
+    /// Select from the cursor's bracket through its matching partner,
+    /// both brackets included. A no-op (returning false) when the cursor
+    /// is not on a bracket or it is unbalanced.
+    pub fn select_to_matching_bracket(&mut self) -> bool {
+        let pos = (self.cursor_line, self.cursor_col);
+        let Some(partner) = self.matching_bracket(pos) else {
+            return false;
+        };
+        self.selection_mode = SelectionMode::Normal;
+        if partner > pos {
+            self.selection_anchor = Some(pos);
+            self.set_cursor(partner.0, partner.1 + 1);
+        } else {
+            self.selection_anchor = Some((pos.0, pos.1 + 1));
+            self.set_cursor(partner.0, partner.1);
+        }
+        true
+    }
+
+    /// Select the contents of the nearest enclosing `open`/`close` pair,
+    /// brackets excluded, like an editor's "inner brackets" text object.
+    /// Returns false (selecting nothing) when no pair encloses the cursor.
+    pub fn select_inside_pair(&mut self, open: char, close: char) -> bool {
+        let Some((opener, closer)) = self.enclosing_pair(open, close) else {
+            return false;
+        };
+        self.selection_anchor = Some((opener.0, opener.1 + 1));
+        self.selection_mode = SelectionMode::Normal;
+        self.set_cursor(closer.0, closer.1);
+        true
+    }
+
+    /// Move the cursor to `line`/`col`, clamping both to the buffer contents.
+    pub fn set_cursor(&mut self, line: usize, col: usize) {
+        self.cursor_line = line.min(self.lines.len().saturating_sub(1));
+        self.cursor_col = col.min(self.line_char_count(self.cursor_line));
+        self.remember_desired_col();
+        // Jumping into a fold (search, goto, a mark) reveals it; the cursor
+        // must never sit on a line that isn't rendered.
+        let landed = self.cursor_line;
+        self.folds
+            .retain(|&(header, last)| landed <= header || landed > last);
+    }
+
+    /// Apply an insertion without touching the history. Returns the position
+    /// just past the inserted text.
+    fn apply_insert(&mut self, line: usize, col: usize, text: &str) -> (usize, usize) {
+        self.change_marks = None;
+        let idx = Self::byte_index(&self.lines[line], col);
+        let tail = self.lines[line].split_off(idx);
+        let mut segments = text.split('\n');
+        self.lines[line].push_str(segments.next().unwrap_or(""));
+        // Splice the remaining lines in as one block; inserting them one at
+        // a time would shift everything below once per line, which gets
+        // quadratic for large pastes.
+        let rest: Vec<String> = segments.map(str::to_string).collect();
+        let end_line = line + rest.len();
+        self.lines.splice(line + 1..line + 1, rest);
+        let end_col = self.line_char_count(end_line);
+        self.lines[end_line].push_str(&tail);
+        // Marks at or after the insertion point ride along with the text
+        // they sit on. The selection anchor gets the same bookkeeping, so
+        // a selection keeps covering its text when a replayed or grouped
+        // edit lands above it.
+        for pos in self
+            .marks
+            .values_mut()
+            .chain(self.selection_anchor.as_mut())
+        {
+            if pos.0 == line && pos.1 >= col {
+                *pos = (end_line, end_col + (pos.1 - col));
+            } else if pos.0 > line {
+                pos.0 += end_line - line;
+            }
+        }
+        // Folds below the insertion slide down with their text; a fold the
+        // insertion lands inside is opened rather than left hiding it.
+        let delta = end_line - line;
+        self.folds.retain_mut(|(header, last)| {
+            if line < *header {
+                *header += delta;
+                *last += delta;
+                true
+            } else {
+                line > *last || (line == *header && delta == 0)
+            }
+        });
+        (end_line, end_col)
+    }
+
+    /// Remove exactly `text` starting at `line`/`col`, without touching the
+    /// history. The caller guarantees the text is actually there.
+    fn apply_delete(&mut self, line: usize, col: usize, text: &str) {
+        self.change_marks = None;
+        let newlines = text.matches('\n').count();
+        let last_segment_len = text
+            .rsplit('\n')
+            .next()
+            .map(|s| s.chars().count())
+            .unwrap_or(0);
+        let end_line = line + newlines;
+        let end_col = if newlines == 0 {
+            col + last_segment_len
+        } else {
+            last_segment_len
+        };
+        let tail_idx = Self::byte_index(&self.lines[end_line], end_col);
+        let tail = self.lines[end_line].split_off(tail_idx);
+        let head_idx = Self::byte_index(&self.lines[line], col);
+        self.lines[line].truncate(head_idx);
+        self.lines.drain(line + 1..=end_line);
+        self.lines[line].push_str(&tail);
+        // Marks past the deleted region slide up to follow their text;
+        // marks inside it collapse to where the deletion happened. The
+        // selection anchor rides along the same way.
+        for pos in self
+            .marks
+            .values_mut()
+            .chain(self.selection_anchor.as_mut())
+        {
+            if *pos >= (end_line, end_col) {
+                if pos.0 == end_line {
+                    *pos = (line, col + (pos.1 - end_col));
+                } else {
+                    pos.0 -= end_line - line;
+                }
+            } else if *pos > (line, col) {
+                *pos = (line, col);
+            }
+        }
+        // Folds below the deleted lines slide up; a fold the deletion
+        // touches is opened rather than left hiding changed text.
+        let delta = end_line - line;
+        self.folds.retain_mut(|(header, last)| {
+            if end_line < *header {
+                *header -= delta;
+                *last -= delta;
+                true
+            } else {
+                line > *last || (line == *header && delta == 0)
+            }
+        });
+    }
+
+    /// Apply `op` going forward; returns the position just past its effect.
+    fn apply_op(&mut self, op: &EditOp) -> (usize, usize) {
+        match op {
+            EditOp::Insert { line, col, text } => self.apply_insert(*line, *col, text),
+            EditOp::Delete { line, col, text } => {
+                self.apply_delete(*line, *col, text);
+                (*line, *col)
+            }
+            EditOp::Group(ops) => {
+                let mut end = (self.cursor_line, self.cursor_col);
+                for op in ops {
+                    end = self.apply_op(op);
+                }
+                end
+            }
+        }
+    }
+
+    /// Apply the inverse of `op`, unwinding groups back to front.
+    fn revert_op(&mut self, op: &EditOp) {
+        match op {
+            EditOp::Insert { line, col, text } => self.apply_delete(*line, *col, text),
+            EditOp::Delete { line, col, text } => {
+                self.apply_insert(*line, *col, text);
+            }
+            EditOp::Group(ops) => {
+                for op in ops.iter().rev() {
+                    self.revert_op(op);
+                }
+            }
+        }
+    }
+
+    /// Record `op` on the undo stack. Any edit invalidates the redo stack.
+    fn record(&mut self, op: EditOp) {
+        self.record_with(op, false);
+    }
+
+    fn record_with(&mut self, op: EditOp, typed: bool) {
+        self.modified = true;
+        self.redo_stack.clear();
+        self.undo_stack.push(EditRecord {
+            op,
+            cursor_before: (self.cursor_line, self.cursor_col),
+            typed,
+            at: Instant::now(),
+        });
+        if self.undo_stack.len() > self.max_undo {
+            let excess = self.undo_stack.len() - self.max_undo;
+            self.undo_stack.drain(..excess);
+        }
+    }
+
+    /// Try to extend the previous insertion instead of starting a new undo
+    /// entry, so typing a run of characters undoes as one unit. A pause
+    /// longer than [`COALESCE_TIMEOUT`] starts a new unit, and so does
+    /// moving the cursor away, because the new insertion no longer lines up
+    /// with the end of the recorded text.
+    fn coalesce_insert(&mut self, c: char) -> bool {
+        if c == '\n' || !self.redo_stack.is_empty() {
+            return false;
+        }
+        if let Some(EditRecord {
+            op: EditOp::Insert { line, col, text },
+            typed: true,
+            at,
+            ..
+        }) = self.undo_stack.last_mut()
+        {
+            if !text.contains('\n')
+                && *line == self.cursor_line
+                && *col + text.chars().count() == self.cursor_col
+                && at.elapsed() <= COALESCE_TIMEOUT
+            {
+                text.push(c);
+                *at = Instant::now();
+                self.modified = true;
+                return true;
+            }
+        }
+        false
+    }
+
+    /// The closing counterpart of an auto-pairing character.
+    fn closing_pair(c: char) -> Option<char> {
+        Some(match c {
+            '(' => ')',
+            '[' => ']',
+            '{' => '}',
+            '"' => '"',
+            '\'' => '\'',
+            _ => return None,
+        })
+    }
+
+    /// Insert `c` with auto-pairing: openers bring their closer along with
+    /// the cursor between them, typing a closer that is already under the
+    /// cursor just steps over it, and typing an opener with a selection
+    /// wraps the selection. Plain [`insert_char`](Self::insert_char) when
+    /// [`auto_pairs`](Self::auto_pairs) is off.
+    pub fn insert_char_smart(&mut self, c: char) {
+        // With multiple cursors, typing is plain insertion at each of them;
+        // auto-pairing per cursor is more surprise than help.
+        if !self.extra_cursors.is_empty() {
+            self.multi_insert_char(c);
+            return;
+        }
+        if !self.auto_pairs {
+            self.insert_char(c);
+            return;
+        }
+        if let (Some(close), Some((start, end))) = (Self::closing_pair(c), self.get_selection()) {
+            // Wrap the selection and keep it selected inside the pair.
+            let close_at = if end.0 == start.0 {
+                (end.0, end.1 + 1)
+            } else {
+                end
+            };
+            self.record(EditOp::Group(vec![
+                EditOp::Insert {
+                    line: start.0,
+                    col: start.1,
+                    text: c.to_string(),
+                },
+                EditOp::Insert {
+                    line: close_at.0,
+                    col: close_at.1,
+                    text: close.to_string(),
+                },
+            ]));
+            self.apply_insert(start.0, start.1, &c.to_string());
+            self.apply_insert(close_at.0, close_at.1, &close.to_string());
+            self.selection_anchor = Some((start.0, start.1 + 1));
+            self.cursor_line = close_at.0;
+            self.cursor_col = close_at.1;
+            self.remember_desired_col();
+            return;
+        }
+        let next = self.current_line().chars().nth(self.cursor_col);
+        if matches!(c, ')' | ']' | '}' | '"' | '\'') && next == Some(c) {
+            // Type-over: the closer is already there.
+            self.clear_selection();
+            self.cursor_col += 1;
+            self.remember_desired_col();
+            return;
+        }
+        if let Some(close) = Self::closing_pair(c) {
+            self.clear_selection();
+            let text = format!("{c}{close}");
+            self.record(EditOp::Insert {
+                line: self.cursor_line,
+                col: self.cursor_col,
+                text: text.clone(),
+            });
+            self.apply_insert(self.cursor_line, self.cursor_col, &text);
+            self.cursor_col += 1;
+            self.remember_desired_col();
+            return;
+        }
+        self.insert_char(c);
+    }
+
+    pub fn insert_char(&mut self, c: char) {
+        if !self.extra_cursors.is_empty() {
+            self.multi_insert_char(c);
+            return;
+        }
+        self.clear_selection();
+        // Every movement and edit keeps the cursor within its line, so the
+        // column needs no re-clamping here — but it is a char index, never a
+        // byte index, which the conversion below relies on.
+        debug_assert!(
+            self.cursor_col <= self.line_char_count(self.cursor_line),
+            "cursor column past the end of its line"
+        );
+        if !self.coalesce_insert(c) {
+            self.record_with(
+                EditOp::Insert {
+                    line: self.cursor_line,
+                    col: self.cursor_col,
+                    text: c.to_string(),
+                },
+                true,
+            );
+        }
+        self.apply_insert(self.cursor_line, self.cursor_col, &c.to_string());
+        self.cursor_col += 1;
+        self.remember_desired_col();
+    }
+
+    /// Overwrite-mode typing: replace the character under the cursor, or
+    /// append when the cursor sits at the end of the line.
+    pub fn overwrite_char(&mut self, c: char) {
+        if self.cursor_col >= self.line_char_count(self.cursor_line) {
+            self.insert_char(c);
+            return;
+        }
+        self.clear_selection();
+        let idx = Self::byte_index(self.current_line(), self.cursor_col);
+        let removed = self.current_line()[idx..]
+            .chars()
+            .next()
+            .expect("cursor is before the end of its line");
+        self.record(EditOp::Delete {
+            line: self.cursor_line,
+            col: self.cursor_col,
+            text: removed.to_string(),
+        });
+        self.record(EditOp::Insert {
+            line: self.cursor_line,
+            col: self.cursor_col,
+            text: c.to_string(),
+        });
+        self.apply_delete(self.cursor_line, self.cursor_col, &removed.to_string());
+        self.apply_insert(self.cursor_line, self.cursor_col, &c.to_string());
+        self.cursor_col += 1;
+        self.remember_desired_col();
+    }
+
+    /// Split the line at the cursor. With [`auto_indent`](Self::auto_indent)
+    /// on, the new line starts with the current line's leading whitespace —
+    /// but never more of it than was left of the cursor, so pressing Enter
+    /// inside the indentation doesn't over-indent.
+    pub fn insert_newline(&mut self) {
+        self.clear_selection();
+        let indent: String = if self.auto_indent {
+            self.current_line()
+                .chars()
+                .take(self.cursor_col)
+                .take_while(|c| *c == ' ' || *c == '\t')
+                .collect()
+        } else {
+            String::new()
+        };
+        let text = format!("\n{indent}");
+        self.record(EditOp::Insert {
+            line: self.cursor_line,
+            col: self.cursor_col,
+            text: text.clone(),
+        });
+        let (line, col) = self.apply_insert(self.cursor_line, self.cursor_col, &text);
+        self.cursor_line = line;
+        self.cursor_col = col;
+        self.remember_desired_col();
+    }
+
+    pub fn delete_char_before_cursor(&mut self) {
+        if !self.extra_cursors.is_empty() {
+            self.multi_delete_char(true);
+            return;
+        }
+        self.clear_selection();
+        if self.cursor_col > 0 {
+            // Remove the whole preceding grapheme cluster, not just one char.
+            let start = Self::prev_grapheme_start(self.current_line(), self.cursor_col);
+            let from = Self::byte_index(self.current_line(), start);
+            let to = Self::byte_index(self.current_line(), self.cursor_col);
+            let removed = self.current_line()[from..to].to_string();
+            self.record(EditOp::Delete {
+                line: self.cursor_line,
+                col: start,
+                text: removed.clone(),
+            });
+            self.apply_delete(self.cursor_line, start, &removed);
+            self.cursor_col = start;
+            self.remember_desired_col();
+        } else if self.cursor_line > 0 {
+            let prev_len = self.line_char_count(self.cursor_line - 1);
+            self.record(EditOp::Delete {
+                line: self.cursor_line - 1,
+                col: prev_len,
+                text: "\n".to_string(),
+            });
+            self.apply_delete(self.cursor_line - 1, prev_len, "\n");
+            self.cursor_line -= 1;
+            self.cursor_col = prev_len;
+            self.remember_desired_col();
+        }
+    }
+
+    /// Delete from the cursor back to the previous word boundary: a run of
+    /// whitespace if the cursor sits after one, otherwise the word or
+    /// punctuation run before it. At the start of a line this merges with
+    /// the previous line, like Backspace.
+    pub fn delete_word_before_cursor(&mut self) {
+        if self.cursor_col == 0 {
+            self.delete_char_before_cursor();
+            return;
+        }
+        self.clear_selection();
+        let start = Self::word_start_before(self.current_line(), self.cursor_col);
+        let from = Self::byte_index(self.current_line(), start);
+        let to = Self::byte_index(self.current_line(), self.cursor_col);
+        let removed = self.current_line()[from..to].to_string();
+        self.record(EditOp::Delete {
+            line: self.cursor_line,
+            col: start,
+            text: removed.clone(),
+        });
+        self.apply_delete(self.cursor_line, start, &removed);
+        self.cursor_col = start;
+        self.remember_desired_col();
+    }
+
+    /// Delete from the cursor forward to the next word boundary. At the end
+    /// of a line this merges with the next line, like Delete.
+    pub fn delete_word_after_cursor(&mut self) {
+        if self.cursor_col == self.line_char_count(self.cursor_line) {
+            self.delete_char_at_cursor();
+            return;
+        }
+        self.clear_selection();
+        let end = Self::word_end_after(self.current_line(), self.cursor_col);
+        let from = Self::byte_index(self.current_line(), self.cursor_col);
+        let to = Self::byte_index(self.current_line(), end);
+        let removed = self.current_line()[from..to].to_string();
+        self.record(EditOp::Delete {
+            line: self.cursor_line,
+            col: self.cursor_col,
+            text: removed.clone(),
+        });
+        self.apply_delete(self.cursor_line, self.cursor_col, &removed);
+    }
+
+    pub fn delete_char_at_cursor(&mut self) {
+        if !self.extra_cursors.is_empty() {
+            self.multi_delete_char(false);
+            return;
+        }
+        self.clear_selection();
+        if self.cursor_col < self.line_char_count(self.cursor_line) {
+            let end = Self::next_grapheme_end(self.current_line(), self.cursor_col);
+            let from = Self::byte_index(self.current_line(), self.cursor_col);
+            let to = Self::byte_index(self.current_line(), end);
+            let removed = self.current_line()[from..to].to_string();
+            self.record(EditOp::Delete {
+                line: self.cursor_line,
+                col: self.cursor_col,
+                text: removed.clone(),
+            });
+            self.apply_delete(self.cursor_line, self.cursor_col, &removed);
+        } else if self.cursor_line + 1 < self.lines.len() {
+            self.record(EditOp::Delete {
+                line: self.cursor_line,
+                col: self.cursor_col,
+                text: "\n".to_string(),
+            });
+            self.apply_delete(self.cursor_line, self.cursor_col, "\n");
+        }
+    }
+
+    /// Delete from the cursor to the end of the line, like readline's
+    /// Ctrl+K. At the very end of a line it deletes the newline instead,
+    /// joining the next line up.
+    pub fn delete_to_line_end(&mut self) {
+        self.clear_selection();
+        if self.cursor_col < self.line_char_count(self.cursor_line) {
+            let from = Self::byte_index(self.current_line(), self.cursor_col);
+            let removed = self.current_line()[from..].to_string();
+            self.record(EditOp::Delete {
+                line: self.cursor_line,
+                col: self.cursor_col,
+                text: removed.clone(),
+            });
+            self.apply_delete(self.cursor_line, self.cursor_col, &removed);
+        } else if self.cursor_line + 1 < self.lines.len() {
+            self.record(EditOp::Delete {
+                line: self.cursor_line,
+                col: self.cursor_col,
+                text: "\n".to_string(),
+            });
+            self.apply_delete(self.cursor_line, self.cursor_col, "\n");
+        }
+    }
+
+    /// Delete from the start of the line to the cursor, like readline's
+    /// Ctrl+U. Does nothing at column 0.
+    pub fn delete_to_line_start(&mut self) {
+        self.clear_selection();
+        if self.cursor_col == 0 {
+            return;
+        }
+        let to = Self::byte_index(self.current_line(), self.cursor_col);
+        let removed = self.current_line()[..to].to_string();
+        self.record(EditOp::Delete {
+            line: self.cursor_line,
+            col: 0,
+            text: removed.clone(),
+        });
+        self.apply_delete(self.cursor_line, 0, &removed);
+        self.cursor_col = 0;
+        self.desired_col = 0;
+    }
+
+    /// Swap the grapheme before the cursor with the one under it and step
+    /// past the pair, like Emacs' transpose-chars. At the end of the line
+    /// the last two graphemes swap instead; at column 0 there is no pair,
+    /// so nothing happens. One undo step.
+    pub fn transpose_chars(&mut self) {
+        self.clear_selection();
+        let len = self.line_char_count(self.cursor_line);
+        if self.cursor_col == 0 || len < 2 {
+            return;
+        }
+        // At the end of the line nothing sits under the cursor; transpose
+        // the two graphemes before it and stay put.
+        let end = if self.cursor_col == len {
+            len
+        } else {
+            Self::next_grapheme_end(self.current_line(), self.cursor_col)
+        };
+        let mid = Self::prev_grapheme_start(self.current_line(), end);
+        let start = Self::prev_grapheme_start(self.current_line(), mid);
+        let line = self.current_line();
+        let (sb, mb, eb) = (
+            Self::byte_index(line, start),
+            Self::byte_index(line, mid),
+            Self::byte_index(line, end),
+        );
+        let swapped = format!("{}{}", &line[mb..eb], &line[sb..mb]);
+        let row = self.cursor_line;
+        self.replace_range((row, start), (row, end), &swapped);
+    }
+
+    /// Splice `text` in place of everything between `start` and `end`
+    /// (exclusive), returning what was removed. `text` may span lines;
+    /// an empty `text` is a pure delete. The cursor lands just past the
+    /// inserted text, the selection is cleared, and the whole exchange is
+    /// one undo step.
+    pub fn replace_range(
+        &mut self,
+        start: (usize, usize),
+        end: (usize, usize),
+        text: &str,
+    ) -> String {
+        self.selection_anchor = None;
+        let removed = self.text_in_range(start, end);
+        let mut ops = Vec::new();
+        if !removed.is_empty() {
+            ops.push(EditOp::Delete {
+                line: start.0,
+                col: start.1,
+                text: removed.clone(),
+            });
+        }
+        if !text.is_empty() {
+            ops.push(EditOp::Insert {
+                line: start.0,
+                col: start.1,
+                text: text.to_string(),
+            });
+        }
+        if ops.is_empty() {
+            self.set_cursor(start.0, start.1);
+            return removed;
+        }
+        let op = EditOp::Group(ops);
+        self.record(op.clone());
+        let new_end = self.apply_op(&op);
+        self.set_cursor(new_end.0, new_end.1);
+        removed
+    }
+
+    /// Replace the whole buffer's contents with `text`, as one undo step.
+    pub fn replace_contents(&mut self, text: &str) {
+        let last = self.lines.len() - 1;
+        let end = (last, self.line_char_count(last));
+        self.replace_range((0, 0), end, text);
+    }
+
+    /// Rewrite the selection — or the word under the cursor when nothing is
+    /// selected — through `f`, e.g. a case mapping. Unicode case mapping
+    /// can change the text's length (`ß` upcases to `SS`), so the selection
+    /// and cursor are recomputed from the replacement rather than reused.
+    /// One undo step.
+    pub fn transform_selection(&mut self, f: impl Fn(&str) -> String) {
+        let (start, end, had_selection) = match self.get_selection() {
+            Some((start, end)) => (start, end, true),
+            None => {
+                let chars: Vec<char> = self.current_line().chars().collect();
+                let mut from = self.cursor_col.min(chars.len());
+                while from > 0 && Self::is_word_char(chars[from - 1]) {
+                    from -= 1;
+                }
+                let mut to = self.cursor_col.min(chars.len());
+                while to < chars.len() && Self::is_word_char(chars[to]) {
+                    to += 1;
+                }
+                ((self.cursor_line, from), (self.cursor_line, to), false)
+            }
+        };
+        let text = self.text_in_range(start, end);
+        let replaced = f(&text);
+        if replaced == text {
+            return;
+        }
+        self.replace_range(start, end, &replaced);
+        if had_selection {
+            self.selection_anchor = Some(start);
+        }
+    }
+
+    /// Upper-case the selection or the word under the cursor.
+    pub fn upcase(&mut self) {
+        self.transform_selection(str::to_uppercase);
+    }
+
+    /// Lower-case the selection or the word under the cursor.
+    pub fn downcase(&mut self) {
+        self.transform_selection(str::to_lowercase);
+    }
+
+    /// Flip the case of every character in the selection or the word under
+    /// the cursor.
+    pub fn toggle_case(&mut self) {
+        self.transform_selection(|text| {
+            let mut out = String::with_capacity(text.len());
+            for c in text.chars() {
+                if c.is_uppercase() {
+                    out.extend(c.to_lowercase());
+                } else {
+                    out.extend(c.to_uppercase());
+                }
+            }
+            out
+        });
+    }
+
+    /// Drop mark `name` on the cursor's position. Edits above the mark
+    /// shift it so it stays on the same text.
+    pub fn set_mark(&mut self, name: char) {
+        self.marks.insert(name, (self.cursor_line, self.cursor_col));
+    }
+
+    /// Move the cursor to mark `name`, clamped in case the marked line was
+    /// deleted. Returns false when no such mark has been set.
+    pub fn goto_mark(&mut self, name: char) -> bool {
+        let Some(&(line, col)) = self.marks.get(&name) else {
+            return false;
+        };
+        self.set_cursor(line, col);
+        true
+    }
+
+    /// Leading-whitespace depth of a line, the measure folding groups by.
+    /// Tabs count as one level each, which lines up as long as the file
+    /// indents consistently.
+    fn indent_depth(line: &str) -> usize {
+        line.chars().take_while(|c| c.is_whitespace()).count()
+    }
+
+    /// The lines an indentation fold at `line` would hide: every following
+    /// line indented deeper than it, with blank lines skipped over as long
+    /// as deeper text continues below them. `None` when the next line is
+    /// not deeper, so there is no block to collapse.
+    pub fn fold_range_at(&self, line: usize) -> Option<(usize, usize)> {
+        let header = self.lines.get(line)?;
+        if header.trim().is_empty() {
+            return None;
+        }
+        let depth = Self::indent_depth(header);
+        let mut last = line;
+        for (idx, candidate) in self.lines.iter().enumerate().skip(line + 1) {
+            if candidate.trim().is_empty() {
+                continue;
+            }
+            if Self::indent_depth(candidate) <= depth {
+                break;
+            }
+            last = idx;
+        }
+        (last > line).then_some((line + 1, last))
+    }
+
+    /// Collapse the block starting at the cursor's line, or reopen it if it
+    /// is already folded. Returns false when the line heads no block.
+    pub fn toggle_fold(&mut self) -> bool {
+        let line = self.cursor_line;
+        if let Some(idx) = self.folds.iter().position(|&(header, _)| header == line) {
+            self.folds.remove(idx);
+            return true;
+        }
+        let Some((_, last)) = self.fold_range_at(line) else {
+            return false;
+        };
+        self.folds.push((line, last));
+        self.folds.sort_unstable();
+        true
+    }
+
+    /// True when `line` sits inside a fold and must not be rendered.
+    pub fn is_line_hidden(&self, line: usize) -> bool {
+        self.folds
+            .iter()
+            .any(|&(header, last)| line > header && line <= last)
+    }
+
+    /// The number of lines the fold headed at `line` hides, if any. The
+    /// printer turns this into the `… (N lines)` summary.
+    pub fn fold_at(&self, line: usize) -> Option<usize> {
+        self.folds
+            .iter()
+            .find(|&&(header, _)| header == line)
+            .map(|&(header, last)| last - header)
+    }
+
+    /// How many lines in `start..end` are hidden by folds, for translating
+    /// buffer lines to screen rows.
+    pub fn hidden_lines_between(&self, start: usize, end: usize) -> usize {
+        self.folds
+            .iter()
+            .map(|&(header, last)| {
+                let from = (header + 1).max(start);
+                let to = (last + 1).min(end);
+                to.saturating_sub(from)
+            })
+            .sum()
+    }
+
+    /// The selection as a normalized (start, end) pair, start <= end in
+    /// document order, or `None` when there is no selection or it is empty.
+    pub fn get_selection(&self) -> Option<((usize, usize), (usize, usize))> {
+        let anchor = self.selection_anchor?;
+        let cursor = (self.cursor_line, self.cursor_col);
+        match anchor.cmp(&cursor) {
+            std::cmp::Ordering::Less => Some((anchor, cursor)),
+            std::cmp::Ordering::Greater => Some((cursor, anchor)),
+            std::cmp::Ordering::Equal => None,
+        }
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.selection_anchor = None;
+        self.selection_mode = SelectionMode::Normal;
+    }
+
+    pub fn selection_mode(&self) -> SelectionMode {
+        self.selection_mode
+    }
+
+    /// The block selection's corners as (top line, bottom line, left column,
+    /// right column), or `None` when nothing is selected.
+    pub fn block_selection(&self) -> Option<(usize, usize, usize, usize)> {
+        let (anchor_line, anchor_col) = self.selection_anchor?;
+        let top = anchor_line.min(self.cursor_line);
+        let bottom = anchor_line.max(self.cursor_line);
+        let left = anchor_col.min(self.cursor_col);
+        let right = anchor_col.max(self.cursor_col);
+        (top < bottom || left < right).then_some((top, bottom, left, right))
+    }
+
+    /// Drop the anchor at the cursor if a selection isn't already in
+    /// progress. Called by every shift-movement before the cursor moves.
+    /// Extend the selection to `line`/`col`, anchoring it at the cursor
+    /// first if nothing was selected. Used by mouse drags.
+    pub fn select_to(&mut self, line: usize, col: usize) {
+        self.anchor_selection(SelectionMode::Normal);
+        self.set_cursor(line, col);
+    }
+
+    fn anchor_selection(&mut self, mode: SelectionMode) {
+        if self.selection_anchor.is_none() {
+            self.selection_anchor = Some((self.cursor_line, self.cursor_col));
+        }
+        self.selection_mode = mode;
+    }
+
+    pub fn select_left(&mut self) {
+        self.anchor_selection(SelectionMode::Normal);
+        self.cursor_left();
+    }
+
+    pub fn select_right(&mut self) {
+        self.anchor_selection(SelectionMode::Normal);
+        self.cursor_right();
+    }
+
+    pub fn select_up(&mut self) {
+        self.anchor_selection(SelectionMode::Normal);
+        self.cursor_up();
+    }
+
+    pub fn select_down(&mut self) {
+        self.anchor_selection(SelectionMode::Normal);
+        self.cursor_down();
+    }
+
+    pub fn select_line_start(&mut self) {
+        self.anchor_selection(SelectionMode::Normal);
+        self.cursor_col = 0;
+        self.desired_col = 0;
+    }
+
+    pub fn select_line_end(&mut self) {
+        self.anchor_selection(SelectionMode::Normal);
+        self.cursor_col = self.line_char_count(self.cursor_line);
+        self.remember_desired_col();
+    }
+
+    pub fn select_word_left(&mut self) {
+        self.anchor_selection(SelectionMode::Normal);
+        let (line, col) = self.word_left_position();
+        self.cursor_line = line;
+        self.cursor_col = col;
+        self.remember_desired_col();
+    }
+
+    pub fn select_word_right(&mut self) {
+        self.anchor_selection(SelectionMode::Normal);
+        let (line, col) = self.word_right_position();
+        self.cursor_line = line;
+        self.cursor_col = col;
+        self.remember_desired_col();
+    }
+
+    pub fn select_block_left(&mut self) {
+        self.anchor_selection(SelectionMode::Block);
+        self.cursor_left();
+    }
+
+    pub fn select_block_right(&mut self) {
+        self.anchor_selection(SelectionMode::Block);
+        self.cursor_right();
+    }
+
+    pub fn select_block_up(&mut self) {
+        self.anchor_selection(SelectionMode::Block);
+        self.cursor_up();
+    }
+
+    pub fn select_block_down(&mut self) {
+        self.anchor_selection(SelectionMode::Block);
+        self.cursor_down();
+    }
+
+    /// Delete the selected rectangle: on every row, the characters between
+    /// the block's left and right columns. Rows that end before the block
+    /// starts are left alone. The whole edit undoes as one unit.
+    pub fn block_delete(&mut self) {
+        let Some((top, bottom, left, right)) = self.block_selection() else {
+            return;
+        };
+        self.clear_selection();
+        let mut ops = Vec::new();
+        for line_idx in top..=bottom {
+            let len = self.line_char_count(line_idx);
+            if left >= len {
+                continue;
+            }
+            let from = Self::byte_index(&self.lines[line_idx], left);
+            let to = Self::byte_index(&self.lines[line_idx], right.min(len));
+            let removed = self.lines[line_idx][from..to].to_string();
+            self.apply_delete(line_idx, left, &removed);
+            ops.push(EditOp::Delete {
+                line: line_idx,
+                col: left,
+                text: removed,
+            });
+        }
+        if !ops.is_empty() {
+            self.record(EditOp::Group(ops));
+        }
+        self.set_cursor(top, left);
+    }
+
+    /// Insert `text` at the block's left column on every selected row,
+    /// padding rows that end before that column with spaces so the
+    /// insertions line up. The whole edit undoes as one unit.
+    pub fn block_insert(&mut self, text: &str) {
+        let Some((top, bottom, left, _)) = self.block_selection() else {
+            return;
+        };
+        self.clear_selection();
+        let mut ops = Vec::new();
+        for line_idx in top..=bottom {
+            let len = self.line_char_count(line_idx);
+            let at = left.min(len);
+            let mut inserted = " ".repeat(left - at);
+            inserted.push_str(text);
+            self.apply_insert(line_idx, at, &inserted);
+            ops.push(EditOp::Insert {
+                line: line_idx,
+                col: at,
+                text: inserted,
+            });
+        }
+        self.record(EditOp::Group(ops));
+        self.set_cursor(top, left + text.chars().count());
+    }
+
+    /// The secondary cursor positions, in document order. Empty in ordinary
+    /// single-cursor editing.
+    pub fn extra_cursors(&self) -> &[(usize, usize)] {
+        &self.extra_cursors
+    }
+
+    /// Drop every secondary cursor, keeping only the primary. What Esc does.
+    pub fn collapse_cursors(&mut self) {
+        self.extra_cursors.clear();
+    }
+
+    /// Add a secondary cursor one line below the lowest cursor, at the
+    /// primary's column (clamped to the shorter line). No-op on the last
+    /// line.
+    pub fn add_cursor_below(&mut self) {
+        let lowest = self
+            .extra_cursors
+            .iter()
+            .map(|&(line, _)| line)
+            .chain([self.cursor_line])
+            .max()
+            .unwrap_or(self.cursor_line);
+        if lowest + 1 >= self.lines.len() {
+            return;
+        }
+        let line = lowest + 1;
+        let col = self.cursor_col.min(self.line_char_count(line));
+        self.push_extra_cursor((line, col));
+    }
+
+    /// Add a secondary cursor at the next occurrence of the selected text,
+    /// positioned past the occurrence the way the primary sits past a
+    /// search match. Returns false with no single-line selection, no
+    /// further occurrence, or a cursor already there.
+    pub fn add_cursor_at_next_match(&mut self) -> bool {
+        let Some((start, end)) = self.get_selection() else {
+            return false;
+        };
+        if start.0 != end.0 {
+            return false;
+        }
+        let needle = self.text_in_range(start, end);
+        let from = self
+            .extra_cursors
+            .iter()
+            .copied()
+            .chain([(self.cursor_line, self.cursor_col)])
+            .max()
+            .unwrap_or((self.cursor_line, self.cursor_col));
+        let Some(pos) = self.find(&needle, from) else {
+            return false;
+        };
+        let cursor = (pos.0, pos.1 + needle.chars().count());
+        if cursor == (self.cursor_line, self.cursor_col) || self.extra_cursors.contains(&cursor) {
+            return false;
+        }
+        self.push_extra_cursor(cursor);
+        true
+    }
+
+    fn push_extra_cursor(&mut self, pos: (usize, usize)) {
+        if pos != (self.cursor_line, self.cursor_col) && !self.extra_cursors.contains(&pos) {
+            self.extra_cursors.push(pos);
+            self.extra_cursors.sort_unstable();
+        }
+    }
+
+    /// Every cursor, primary included, in document order.
+    fn all_cursors(&self) -> Vec<(usize, usize)> {
+        let mut cursors = self.extra_cursors.clone();
+        cursors.push((self.cursor_line, self.cursor_col));
+        cursors.sort_unstable();
+        cursors
+    }
+
+    /// Insert `c` at every cursor as one undo record. Edits are applied
+    /// bottom-up so earlier ones never shift the offsets of later ones;
+    /// afterwards each cursor steps past its own insertion plus any made
+    /// before it on the same line.
+    fn multi_insert_char(&mut self, c: char) {
+        self.clear_selection();
+        let cursors = self.all_cursors();
+        let op = EditOp::Group(
+            cursors
+                .iter()
+                .rev()
+                .map(|&(line, col)| EditOp::Insert {
+                    line,
+                    col,
+                    text: c.to_string(),
+                })
+                .collect(),
+        );
+        self.record(op.clone());
+        self.apply_op(&op);
+        let shifted = |(line, col): (usize, usize)| {
+            let earlier = cursors
+                .iter()
+                .filter(|&&(l, c2)| l == line && c2 < col)
+                .count();
+            (line, col + 1 + earlier)
+        };
+        self.extra_cursors = self.extra_cursors.iter().map(|&p| shifted(p)).collect();
+        let (line, col) = shifted((self.cursor_line, self.cursor_col));
+        self.cursor_line = line;
+        self.cursor_col = col;
+        self.remember_desired_col();
+    }
+
+    /// Delete one grapheme before (`before` true) or under every cursor,
+    /// as one undo record. Cursors where that would join lines stay put —
+    /// multi-cursor line joins would shift every line number below them.
+    fn multi_delete_char(&mut self, before: bool) {
+        self.clear_selection();
+        let cursors = self.all_cursors();
+        // (cursor, removed char range) per cursor, empty range when the
+        // cursor sits at the edge of its line.
+        let removals: Vec<((usize, usize), (usize, usize))> = cursors
+            .iter()
+            .map(|&(line, col)| {
+                let range = if before {
+                    (Self::prev_grapheme_start(&self.lines[line], col), col)
+                } else {
+                    (col, Self::next_grapheme_end(&self.lines[line], col))
+                };
+                ((line, col), range)
+            })
+            .collect();
+        let ops: Vec<EditOp> = removals
+            .iter()
+            .rev()
+            .filter(|&&(_, (from, to))| from < to)
+            .map(|&((line, _), (from, to))| EditOp::Delete {
+                line,
+                col: from,
+                text: self.lines[line]
+                    .chars()
+                    .skip(from)
+                    .take(to - from)
+                    .collect(),
+            })
+            .collect();
+        if ops.is_empty() {
+            return;
+        }
+        let op = EditOp::Group(ops);
+        self.record(op.clone());
+        self.apply_op(&op);
+        let shifted = |(line, col): (usize, usize)| {
+            let own = removals
+                .iter()
+                .find(|&&(cursor, _)| cursor == (line, col))
+                .map(|&(_, (from, to))| if before { to - from } else { 0 })
+                .unwrap_or(0);
+            let earlier: usize = removals
+                .iter()
+                .filter(|&&((l, c2), _)| l == line && c2 < col)
+                .map(|&(_, (from, to))| to - from)
+                .sum();
+            (line, col - own - earlier)
+        };
+        self.extra_cursors = self.extra_cursors.iter().map(|&p| shifted(p)).collect();
+        let (line, col) = shifted((self.cursor_line, self.cursor_col));
+        self.cursor_line = line;
+        self.cursor_col = col;
+        self.remember_desired_col();
+    }
+
+    fn cursor_left(&mut self) {
+        if self.cursor_col > 0 {
+            self.cursor_col =
+                Self::prev_grapheme_start(&self.lines[self.cursor_line], self.cursor_col);
+        } else if self.cursor_line > 0 {
+            self.cursor_line -= 1;
+            self.cursor_col = self.line_char_count(self.cursor_line);
+        }
+        self.remember_desired_col();
+    }
+
+    fn cursor_right(&mut self) {
+        if self.cursor_col < self.line_char_count(self.cursor_line) {
+            self.cursor_col =
+                Self::next_grapheme_end(&self.lines[self.cursor_line], self.cursor_col);
+        } else if self.cursor_line + 1 < self.lines.len() {
+            self.cursor_line += 1;
+            self.cursor_col = 0;
+        }
+        self.remember_desired_col();
+    }
+
+    /// Remember the cursor's visual column as the one vertical movement
+    /// aims for. Working in visual columns keeps the cursor in place on
+    /// screen when it crosses lines whose tabs expand differently.
+    fn remember_desired_col(&mut self) {
+        self.desired_col = visual_col(
+            &self.lines[self.cursor_line],
+            self.cursor_col,
+            self.tab_width.max(1),
+        );
+    }
+
+    /// The char column on `line` closest to the remembered visual column,
+    /// clamped to the line's contents.
+    fn col_for_desired(&self, line: usize) -> usize {
+        char_col_at(&self.lines[line], self.desired_col, self.tab_width.max(1))
+    }
+
+    fn cursor_up(&mut self) {
+        if self.cursor_line > 0 {
+            let mut line = self.cursor_line - 1;
+            // Step over folded lines; a fold's header is always visible, so
+            // this terminates there at the latest.
+            while self.is_line_hidden(line) {
+                line -= 1;
+            }
+            self.cursor_line = line;
+            self.cursor_col = self.col_for_desired(line);
+        }
+    }
+
+    fn cursor_down(&mut self) {
+        let mut line = self.cursor_line + 1;
+        while line < self.lines.len() && self.is_line_hidden(line) {
+            line += 1;
+        }
+        if line < self.lines.len() {
+            self.cursor_line = line;
+            self.cursor_col = self.col_for_desired(line);
+        }
+    }
+
+    /// Move the cursor one word boundary to the left, without selecting.
+    /// At column 0 this lands on the end of the previous line.
+    pub fn move_word_left(&mut self) {
+        self.clear_selection();
+        let (line, col) = self.word_left_position();
+        self.cursor_line = line;
+        self.cursor_col = col;
+        self.remember_desired_col();
+    }
+
+    /// Move the cursor one word boundary to the right, without selecting.
+    /// At the end of a line this lands on the start of the next line.
+    pub fn move_word_right(&mut self) {
+        self.clear_selection();
+        let (line, col) = self.word_right_position();
+        self.cursor_line = line;
+        self.cursor_col = col;
+        self.remember_desired_col();
+    }
+
+    pub fn move_left(&mut self) {
+        self.clear_selection();
+        self.cursor_left();
+    }
+
+    pub fn move_right(&mut self) {
+        self.clear_selection();
+        self.cursor_right();
+    }
+
+    pub fn move_up(&mut self) {
+        self.clear_selection();
+        self.cursor_up();
+    }
+
+    pub fn move_down(&mut self) {
+        self.clear_selection();
+        self.cursor_down();
+    }
+
+    /// Smart Home: jump to the first non-blank character, or to true column
+    /// 0 when already there. Pressing Home repeatedly toggles between the
+    /// two, VS Code style — no state needed, the cursor position decides.
+    pub fn move_line_start_smart(&mut self) {
+        self.clear_selection();
+        let first_non_blank = self
+            .current_line()
+            .chars()
+            .take_while(|c| c.is_whitespace())
+            .count();
+        let col = if self.cursor_col == first_non_blank {
+            0
+        } else {
+            first_non_blank
+        };
+        self.cursor_col = col;
+        self.remember_desired_col();
+    }
+
+    pub fn move_line_end(&mut self) {
+        self.clear_selection();
+        self.cursor_col = self.line_char_count(self.cursor_line);
+        self.remember_desired_col();
+    }
+
+    /// Sort lines `start..=end` in place, as one undo group. `descending`
+    /// reverses the order and `case_insensitive` compares lowercased; the
+    /// sort is stable, so equal lines keep their relative order. The
+    /// cursor lands on the range's first line.
+    pub fn sort_lines(
+        &mut self,
+        start: usize,
+        end: usize,
+        descending: bool,
+        case_insensitive: bool,
+    ) {
+        let end = end.min(self.lines.len().saturating_sub(1));
+        if start >= end {
+            return;
+        }
+        let mut sorted = self.lines[start..=end].to_vec();
+        sorted.sort_by(|a, b| {
+            let ord = if case_insensitive {
+                a.to_lowercase().cmp(&b.to_lowercase())
+            } else {
+                a.cmp(b)
+            };
+            if descending {
+                ord.reverse()
+            } else {
+                ord
+            }
+        });
+        if sorted != self.lines[start..=end] {
+            let last = self.line_char_count(end);
+            self.replace_range((start, 0), (end, last), &sorted.join("\n"));
+        }
+        self.set_cursor(start, 0);
+    }
+
+    /// Remove duplicate lines in `start..=end`, keeping each line's first
+    /// occurrence, as one undo group. `consecutive_only` limits it to
+    /// adjacent runs, `uniq` style; otherwise duplicates are removed
+    /// wherever they appear. With `trim_whitespace`, lines compare with
+    /// leading and trailing whitespace stripped (the kept line stays
+    /// verbatim); without it, whitespace is significant. Returns how many
+    /// lines were removed; the cursor lands on the range's first line.
+    pub fn dedup_lines(
+        &mut self,
+        start: usize,
+        end: usize,
+        consecutive_only: bool,
+        trim_whitespace: bool,
+    ) -> usize {
+        let end = end.min(self.lines.len().saturating_sub(1));
+        if start >= end {
+            return 0;
+        }
+        let key = |line: &str| {
+            if trim_whitespace {
+                line.trim().to_string()
+            } else {
+                line.to_string()
+            }
+        };
+        let mut kept: Vec<String> = Vec::new();
+        let mut seen: HashSet<String> = HashSet::new();
+        for line in &self.lines[start..=end] {
+            let k = key(line);
+            let duplicate = if consecutive_only {
+                kept.last().is_some_and(|prev| key(prev) == k)
+            } else {
+                seen.contains(&k)
+            };
+            if !duplicate {
+                seen.insert(k);
+                kept.push(line.clone());
+            }
+        }
+        let removed = (end - start + 1) - kept.len();
+        if removed > 0 {
+            let last = self.line_char_count(end);
+            self.replace_range((start, 0), (end, last), &kept.join("\n"));
+        }
+        self.set_cursor(start, 0);
+        removed
+    }
+
+    /// Strip trailing spaces and tabs from every line, as one undo group.
+    /// Returns how many lines changed; nothing is recorded when none do.
+    pub fn trim_trailing_whitespace(&mut self) -> usize {
+        let mut ops = Vec::new();
+        for (idx, line) in self.lines.iter().enumerate() {
+            let trimmed = line.trim_end_matches([' ', '\t']);
+            if trimmed.len() < line.len() {
+                ops.push(EditOp::Delete {
+                    line: idx,
+                    col: trimmed.chars().count(),
+                    text: line[trimmed.len()..].to_string(),
+                });
+            }
+        }
+        if ops.is_empty() {
+            return 0;
+        }
+        let changed = ops.len();
+        self.record(EditOp::Group(ops.clone()));
+        for op in &ops {
+            self.apply_op(op);
+        }
+        // The cursor may have sat inside whitespace that just vanished.
+        self.set_cursor(self.cursor_line, self.cursor_col);
+        changed
+    }
+
+    /// The text between `start` and `end` (exclusive), with `\n` separating
+    /// lines.
+    pub fn text_in_range(&self, start: (usize, usize), end: (usize, usize)) -> String {
+        if start.0 == end.0 {
+            let line = &self.lines[start.0];
+            let from = Self::byte_index(line, start.1);
+            let to = Self::byte_index(line, end.1);
+            return line[from..to].to_string();
+        }
+        let mut out = String::new();
+        let first = &self.lines[start.0];
+        out.push_str(&first[Self::byte_index(first, start.1)..]);
+        for line in &self.lines[start.0 + 1..end.0] {
+            out.push('\n');
+            out.push_str(line);
+        }
+        let last = &self.lines[end.0];
+        out.push('\n');
+        out.push_str(&last[..Self::byte_index(last, end.1)]);
+        out
+    }
+
+    /// The selected text with `\n` separators, or `None` when nothing is
+    /// selected. The pure-data half of copy, so clipboard flows can be
+    /// unit-tested without a terminal.
+    pub fn selection_text(&self) -> Option<String> {
+        self.get_selection()
+            .map(|(start, end)| self.text_in_range(start, end))
+    }
+
+    /// Text for the clipboard: the active selection, or the whole current
+    /// line when nothing is selected.
+    pub fn copy_selected_text(&self) -> String {
+        self.selection_text()
+            .unwrap_or_else(|| self.current_line().clone())
+    }
+
+    /// Cut the active selection: copy it, remove it from the buffer, and put
+    /// the cursor at the selection start. Falls back to cutting the current
+    /// line when nothing is selected.
+    pub fn cut_selected_text(&mut self) -> String {
+        let Some((start, end)) = self.get_selection() else {
+            return self.cut_lines();
+        };
+        let text = self.text_in_range(start, end);
+        self.clear_selection();
+        self.record(EditOp::Delete {
+            line: start.0,
+            col: start.1,
+            text: text.clone(),
+        });
+        self.apply_delete(start.0, start.1, &text);
+        self.set_cursor(start.0, start.1);
+        text
+    }
+
+    /// Remove the current line and hand it to the caller for the clipboard.
+    /// Copy the current line onto a new line below it and move the cursor
+    /// to the copy. One undo step.
+    pub fn duplicate_line(&mut self) {
+        self.clear_selection();
+        let col = self.line_char_count(self.cursor_line);
+        let text = format!("\n{}", self.current_line());
+        self.record(EditOp::Insert {
+            line: self.cursor_line,
+            col,
+            text: text.clone(),
+        });
+        self.apply_insert(self.cursor_line, col, &text);
+        self.cursor_line += 1;
+    }
+
+    /// Swap lines `upper` and `upper + 1`, recorded as a single undo step.
+    fn swap_lines(&mut self, upper: usize) {
+        let lower_len = self.line_char_count(upper + 1);
+        let op = EditOp::Group(vec![
+            EditOp::Delete {
+                line: upper,
+                col: 0,
+                text: format!("{}\n", self.lines[upper]),
+            },
+            EditOp::Insert {
+                line: upper,
+                col: lower_len,
+                text: format!("\n{}", self.lines[upper]),
+            },
+        ]);
+        self.record(op.clone());
+        self.apply_op(&op);
+    }
+
+    /// Move the current line one up; a no-op on the first line.
+    pub fn move_line_up(&mut self) {
+        if self.cursor_line == 0 {
+            return;
+        }
+        self.clear_selection();
+        self.swap_lines(self.cursor_line - 1);
+        self.cursor_line -= 1;
+    }
+
+    /// Move the current line one down; a no-op on the last line.
+    pub fn move_line_down(&mut self) {
+        if self.cursor_line + 1 >= self.lines.len() {
+            return;
+        }
+        self.clear_selection();
+        self.swap_lines(self.cursor_line);
+        self.cursor_line += 1;
+    }
+
+    /// Screen column of char `col` in `line`, counting tabs as advancing to
+    /// the next `tab_width` stop. Local twin of the printer's arithmetic,
+    /// used to keep the cursor on the same visual column across tab/space
+    /// conversions.
+    fn visual_col_in(line: &str, col: usize, tab_width: usize) -> usize {
+        let mut vcol = 0;
+        for c in line.chars().take(col) {
+            if c == '\t' {
+                vcol += tab_width - vcol % tab_width;
+            } else {
+                vcol += 1;
+            }
+        }
+        vcol
+    }
+
+    /// Char column in `line` closest to screen column `vcol`.
+    fn col_at_visual(line: &str, vcol: usize, tab_width: usize) -> usize {
+        let mut acc = 0;
+        for (idx, c) in line.chars().enumerate() {
+            if acc >= vcol {
+                return idx;
+            }
+            acc += if c == '\t' {
+                tab_width - acc % tab_width
+            } else {
+                1
+            };
+        }
+        line.chars().count()
+    }
+
+    /// Insert one level of indentation at the cursor, honoring
+    /// [`indent_style`](Self::indent_style): a literal tab, or spaces out to
+    /// the next `tab_width` stop.
+    pub fn insert_indent(&mut self, tab_width: usize) {
+        match self.indent_style {
+            IndentStyle::Tabs => self.insert_char('\t'),
+            IndentStyle::Spaces => {
+                let tab_width = tab_width.max(1);
+                let vcol = Self::visual_col_in(self.current_line(), self.cursor_col, tab_width);
+                let pad = tab_width - vcol % tab_width;
+                let text = " ".repeat(pad);
+                self.clear_selection();
+                self.record(EditOp::Insert {
+                    line: self.cursor_line,
+                    col: self.cursor_col,
+                    text: text.clone(),
+                });
+                self.apply_insert(self.cursor_line, self.cursor_col, &text);
+                self.cursor_col += pad;
+                self.remember_desired_col();
+            }
+        }
+    }
+
+    /// Indent lines `start..=end` by one level, honoring
+    /// [`indent_style`](Self::indent_style). One undo group; the cursor and
+    /// selection shift right with the text so the selection survives
+    /// repeated presses. Empty lines are left alone — indenting them would
+    /// only manufacture trailing whitespace.
+    pub fn indent_lines(&mut self, start: usize, end: usize, tab_width: usize) {
+        let end = end.min(self.lines.len().saturating_sub(1));
+        let unit = match self.indent_style {
+            IndentStyle::Tabs => "\t".to_string(),
+            IndentStyle::Spaces => " ".repeat(tab_width.max(1)),
+        };
+        let touched: Vec<usize> = (start..=end)
+            .filter(|&l| !self.lines[l].is_empty())
+            .collect();
+        if touched.is_empty() {
+            return;
+        }
+        let op = EditOp::Group(
+            touched
+                .iter()
+                .map(|&line| EditOp::Insert {
+                    line,
+                    col: 0,
+                    text: unit.clone(),
+                })
+                .collect(),
+        );
+        self.record(op.clone());
+        self.apply_op(&op);
+        // The selection anchor already rode right inside `apply_insert`;
+        // only the cursor needs shifting here.
+        let width = unit.chars().count();
+        if touched.contains(&self.cursor_line) {
+            self.cursor_col += width;
+            self.remember_desired_col();
+        }
+    }
+
+    /// Remove one level of indentation from lines `start..=end`: a leading
+    /// tab, or up to `tab_width` leading spaces. One undo group; the cursor
+    /// and selection shift left with the text. Lines with no leading
+    /// whitespace are left alone.
+    pub fn unindent_lines(&mut self, start: usize, end: usize, tab_width: usize) {
+        let end = end.min(self.lines.len().saturating_sub(1));
+        let tab_width = tab_width.max(1);
+        let mut removed = Vec::new();
+        for line in start..=end {
+            let text = &self.lines[line];
+            let take = if text.starts_with('\t') {
+                1
+            } else {
+                text.chars()
+                    .take(tab_width)
+                    .take_while(|&c| c == ' ')
+                    .count()
+            };
+            if take > 0 {
+                removed.push((line, take));
+            }
+        }
+        if removed.is_empty() {
+            return;
+        }
+        let op = EditOp::Group(
+            removed
+                .iter()
+                .map(|&(line, take)| EditOp::Delete {
+                    line,
+                    col: 0,
+                    text: self.lines[line].chars().take(take).collect(),
+                })
+                .collect(),
+        );
+        self.record(op.clone());
+        self.apply_op(&op);
+        for (line, take) in removed {
+            if self.cursor_line == line {
+                self.cursor_col = self.cursor_col.saturating_sub(take);
+                self.remember_desired_col();
+            }
+        }
+    }
+
+    /// Unindent just the cursor's line; what Shift+Tab does with no
+    /// selection.
+    pub fn unindent_current_line(&mut self, tab_width: usize) {
+        self.unindent_lines(self.cursor_line, self.cursor_line, tab_width);
+    }
+
+    /// Replace every tab in the buffer with spaces using `tab_width` stops.
+    /// One undo group; the cursor keeps its visual column.
+    pub fn expand_tabs(&mut self, tab_width: usize) {
+        let tab_width = tab_width.max(1);
+        let vcol = Self::visual_col_in(&self.lines[self.cursor_line], self.cursor_col, tab_width);
+        let mut ops = Vec::new();
+        for (l, line) in self.lines.iter().enumerate() {
+            if !line.contains('\t') {
+                continue;
+            }
+            let mut expanded = String::with_capacity(line.len());
+            let mut acc = 0;
+            for c in line.chars() {
+                if c == '\t' {
+                    let pad = tab_width - acc % tab_width;
+                    expanded.extend(std::iter::repeat_n(' ', pad));
+                    acc += pad;
+                } else {
+                    expanded.push(c);
+                    acc += 1;
+                }
+            }
+            ops.push(EditOp::Delete {
+                line: l,
+                col: 0,
+                text: line.clone(),
+            });
+            ops.push(EditOp::Insert {
+                line: l,
+                col: 0,
+                text: expanded,
+            });
+        }
+        if ops.is_empty() {
+            return;
+        }
+        let op = EditOp::Group(ops);
+        self.record(op.clone());
+        self.apply_op(&op);
+        let col = Self::col_at_visual(&self.lines[self.cursor_line], vcol, tab_width);
+        self.set_cursor(self.cursor_line, col);
+    }
+
+    /// Turn runs of `tab_width` leading spaces into tabs, leaving the rest
+    /// of each line alone. One undo group; the cursor keeps its visual
+    /// column.
+    pub fn unexpand_leading_tabs(&mut self, tab_width: usize) {
+        let tab_width = tab_width.max(1);
+        let vcol = Self::visual_col_in(&self.lines[self.cursor_line], self.cursor_col, tab_width);
+        let mut ops = Vec::new();
+        for (l, line) in self.lines.iter().enumerate() {
+            let spaces = line.chars().take_while(|&c| c == ' ').count();
+            if spaces < tab_width {
+                continue;
+            }
+            let tabs = spaces / tab_width;
+            let rest = spaces % tab_width;
+            let mut leading = "\t".repeat(tabs);
+            leading.push_str(&" ".repeat(rest));
+            ops.push(EditOp::Delete {
+                line: l,
+                col: 0,
+                text: " ".repeat(spaces),
+            });
+            ops.push(EditOp::Insert {
+                line: l,
+                col: 0,
+                text: leading,
+            });
+        }
+        if ops.is_empty() {
+            return;
+        }
+        let op = EditOp::Group(ops);
+        self.record(op.clone());
+        self.apply_op(&op);
+        let col = Self::col_at_visual(&self.lines[self.cursor_line], vcol, tab_width);
+        self.set_cursor(self.cursor_line, col);
+    }
+
+    /// Counts over the whole buffer; see [`BufferStats`].
+    pub fn stats(&self) -> BufferStats {
+        let mut stats = BufferStats {
+            lines: self.lines.len(),
+            ..BufferStats::default()
+        };
+        for line in self.lines_iter() {
+            stats.words += line.split_whitespace().count();
+            stats.chars += line.graphemes(true).count();
+            stats.bytes += line.len();
+        }
+        stats
+    }
+
+    /// Comment out lines `start_line..=end_line` with `prefix` placed after
+    /// their leading whitespace — or, when every line in the range is
+    /// already commented, uncomment them all instead. One undo group.
+    pub fn toggle_comment(&mut self, prefix: &str, start_line: usize, end_line: usize) {
+        let end_line = end_line.min(self.lines.len() - 1);
+        let start_line = start_line.min(end_line);
+        let all_commented =
+            (start_line..=end_line).all(|l| self.lines[l].trim_start().starts_with(prefix));
+        let mut ops = Vec::new();
+        for l in start_line..=end_line {
+            let indent = self.lines[l]
+                .chars()
+                .take_while(|c| c.is_whitespace())
+                .count();
+            if all_commented {
+                // Strip the prefix and the single space we insert with it,
+                // when that space is there.
+                let after = &self.lines[l].trim_start()[prefix.len()..];
+                let text = if after.starts_with(' ') {
+                    format!("{prefix} ")
+                } else {
+                    prefix.to_string()
+                };
+                ops.push(EditOp::Delete {
+                    line: l,
+                    col: indent,
+                    text,
+                });
+            } else {
+                ops.push(EditOp::Insert {
+                    line: l,
+                    col: indent,
+                    text: format!("{prefix} "),
+                });
+            }
+        }
+        let op = EditOp::Group(ops);
+        self.record(op.clone());
+        self.apply_op(&op);
+        self.set_cursor(self.cursor_line, self.cursor_col);
+    }
+
+    /// Merge the next line onto this one, collapsing the whitespace around
+    /// the join to a single space — or none when this line already ends in
+    /// whitespace. The cursor lands on the join point. No-op on the last
+    /// line; one undo step.
+    pub fn join_line_below(&mut self) {
+        if self.cursor_line + 1 >= self.lines.len() {
+            return;
+        }
+        self.clear_selection();
+        let line = self.cursor_line;
+        let cur_len = self.line_char_count(line);
+        let leading: String = self.lines[line + 1]
+            .chars()
+            .take_while(|c| c.is_whitespace())
+            .collect();
+        let next_has_text = self.lines[line + 1].len() > leading.len();
+        let needs_space = next_has_text
+            && !self.lines[line].is_empty()
+            && !self.lines[line].ends_with(char::is_whitespace);
+        let mut ops = vec![EditOp::Delete {
+            line,
+            col: cur_len,
+            text: format!("\n{leading}"),
+        }];
+        if needs_space {
+            ops.push(EditOp::Insert {
+                line,
+                col: cur_len,
+                text: " ".to_string(),
+            });
+        }
+        let op = if ops.len() == 1 {
+            ops.pop().expect("one op")
+        } else {
+            EditOp::Group(ops)
+        };
+        self.record(op.clone());
+        self.apply_op(&op);
+        self.set_cursor(line, cur_len);
+    }
+
+    pub fn cut_lines(&mut self) -> String {
+        self.clear_selection();
+        let line = self.lines[self.cursor_line].clone();
+        if self.lines.len() == 1 {
+            if !line.is_empty() {
+                self.record(EditOp::Delete {
+                    line: 0,
+                    col: 0,
+                    text: line.clone(),
+                });
+                self.apply_delete(0, 0, &line);
+            }
+            self.cursor_col = 0;
+            return line;
+        }
+        if self.cursor_line + 1 < self.lines.len() {
+            // The removed span is the line plus its trailing newline.
+            let text = format!("{line}\n");
+            self.record(EditOp::Delete {
+                line: self.cursor_line,
+                col: 0,
+                text: text.clone(),
+            });
+            self.apply_delete(self.cursor_line, 0, &text);
+        } else {
+            // Last line: what really goes away is the newline before it.
+            let prev_len = self.line_char_count(self.cursor_line - 1);
+            let text = format!("\n{line}");
+            self.record(EditOp::Delete {
+                line: self.cursor_line - 1,
+                col: prev_len,
+                text: text.clone(),
+            });
+            self.apply_delete(self.cursor_line - 1, prev_len, &text);
+        }
+        if self.cursor_line >= self.lines.len() {
+            self.cursor_line = self.lines.len() - 1;
+        }
+        self.cursor_col = self.cursor_col.min(self.line_char_count(self.cursor_line));
+        line
+    }
+
+    pub fn paste(&mut self, text: &str) {
+        self.clear_selection();
+        if text.is_empty() {
+            return;
+        }
+        self.record(EditOp::Insert {
+            line: self.cursor_line,
+            col: self.cursor_col,
+            text: text.to_string(),
+        });
+        let (line, col) = self.apply_insert(self.cursor_line, self.cursor_col, text);
+        self.cursor_line = line;
+        self.cursor_col = col;
+        self.remember_desired_col();
+    }
+
+    /// [`paste`](Self::paste), then shift the pasted lines so the block
+    /// lines up with the cursor line's indentation, keeping the relative
+    /// indentation between pasted lines. A paste that starts mid-line
+    /// leaves its first line alone — that line inherits whatever already
+    /// sat before the cursor.
+    pub fn paste_reindent(&mut self, text: &str) {
+        let target = Self::indent_depth(self.current_line());
+        let first = self.cursor_line;
+        let at_line_start = self.cursor_col == 0;
+        self.paste(text);
+        let mut last = self.cursor_line;
+        if self.cursor_col == 0 && last > first {
+            // Text ending in a newline parks the cursor on the line after
+            // the pasted block; that line is not part of the paste.
+            last -= 1;
+        }
+        let start = if at_line_start { first } else { first + 1 };
+        if start > last {
+            return;
+        }
+        let start_target = if start == first {
+            target
+        } else {
+            // Continuation lines shift by the same amount the first pasted
+            // line would have: target minus its original indentation.
+            let base = Self::indent_depth(text.split('\n').next().unwrap_or(""));
+            (Self::indent_depth(&self.lines[start]) + target).saturating_sub(base)
+        };
+        self.reindent_lines(start, last, start_target);
+    }
+
+    /// Shift lines `start..=end` so line `start`'s indentation becomes
+    /// `target_indent`, moving every other line by the same amount.
+    /// Indentation never goes negative, blank lines are left alone, and
+    /// the whole shift is one undo step.
+    pub fn reindent_lines(&mut self, start: usize, end: usize, target_indent: usize) {
+        let end = end.min(self.lines.len().saturating_sub(1));
+        if start > end {
+            return;
+        }
+        let base = Self::indent_depth(&self.lines[start]);
+        let mut ops = Vec::new();
+        for line in start..=end {
+            let text = &self.lines[line];
+            if text.trim().is_empty() {
+                continue;
+            }
+            let indent = Self::indent_depth(text);
+            let new_indent = (indent + target_indent).saturating_sub(base);
+            if new_indent > indent {
+                ops.push(EditOp::Insert {
+                    line,
+                    col: 0,
+                    text: " ".repeat(new_indent - indent),
+                });
+            } else if new_indent < indent {
+                ops.push(EditOp::Delete {
+                    line,
+                    col: 0,
+                    text: text.chars().take(indent - new_indent).collect(),
+                });
+            }
+        }
+        if ops.is_empty() {
+            return;
+        }
+        let op = EditOp::Group(ops);
+        self.record(op.clone());
+        self.apply_op(&op);
+        self.set_cursor(
+            self.cursor_line,
+            self.cursor_col.min(self.line_char_count(self.cursor_line)),
+        );
+    }
+
+    /// Find the next occurrence of `needle` at or after `from`, wrapping
+    /// around to the start of the buffer. Returns the (line, char column) of
+    /// the match start.
+    pub fn find(&self, needle: &str, from: (usize, usize)) -> Option<(usize, usize)> {
+        self.find_with(needle, from, false, false)
+    }
+
+    /// [`find`](Self::find) with matching flags: `ignore_case` folds case on
+    /// both sides, `whole_word` rejects matches flanked by word characters,
+    /// using the same word-character class as word movement.
+    pub fn find_with(
+        &self,
+        needle: &str,
+        from: (usize, usize),
+        ignore_case: bool,
+        whole_word: bool,
+    ) -> Option<(usize, usize)> {
+        if needle.is_empty() {
+            return None;
+        }
+        let needle: Vec<char> = needle.chars().collect();
+        let total = self.lines.len();
+        for step in 0..=total {
+            let line_idx = (from.0 + step) % total;
+            let chars: Vec<char> = self.lines[line_idx].chars().collect();
+            if chars.len() < needle.len() {
+                continue;
+            }
+            // On the wrapped-around visit of the starting line, search the
+            // part before `from` as well.
+            let search_from = if step == 0 { from.1 } else { 0 };
+            for col in search_from..=chars.len() - needle.len() {
+                if step == total && col >= from.1 {
+                    break;
+                }
+                if !Self::chars_match(&chars[col..col + needle.len()], &needle, ignore_case) {
+                    continue;
+                }
+                if whole_word && !Self::word_bounded(&chars, col, col + needle.len()) {
+                    continue;
+                }
+                return Some((line_idx, col));
+            }
+        }
+        None
+    }
+
+    /// Whether `hay` equals `needle`, optionally folding case.
+    fn chars_match(hay: &[char], needle: &[char], ignore_case: bool) -> bool {
+        if !ignore_case {
+            return hay == needle;
+        }
+        hay.iter()
+            .zip(needle)
+            .all(|(a, b)| a.to_lowercase().eq(b.to_lowercase()))
+    }
+
+    /// Whether `start..end` of `chars` is not flanked by word characters.
+    fn word_bounded(chars: &[char], start: usize, end: usize) -> bool {
+        let left_ok = start == 0 || !Self::is_word_char(chars[start - 1]);
+        let right_ok = end == chars.len() || !Self::is_word_char(chars[end]);
+        left_ok && right_ok
+    }
+
+    /// Find the next match of `re` at or after `from`, wrapping around like
+    /// [`find`](Self::find). Returns the match's line and start/end char
+    /// columns so the whole match can be highlighted. Matches are found per
+    /// line, so patterns cannot span newlines, and `^` only matches at true
+    /// line starts — a match is searched from `from.1` but anchors see the
+    /// whole line only when searching from column 0.
+    pub fn find_regex(&self, re: &Regex, from: (usize, usize)) -> Option<(usize, usize, usize)> {
+        let total = self.lines.len();
+        for step in 0..=total {
+            let line_idx = (from.0 + step) % total;
+            let line = &self.lines[line_idx];
+            let search_from = if step == 0 { from.1 } else { 0 };
+            // On the wrapped-around visit of the starting line, search the
+            // part before `from` as well.
+            let search_from = if step == total { 0 } else { search_from };
+            let byte_from = Self::byte_index(line, search_from);
+            if let Some(m) = re.find(&line[byte_from..]) {
+                let start = line[..byte_from + m.start()].chars().count();
+                let end = start + m.as_str().chars().count();
+                if step == total && start >= from.1 {
+                    break;
+                }
+                return Some((line_idx, start, end));
+            }
+        }
+        None
+    }
+
+    /// Replace the next occurrence of `needle` at or after the cursor
+    /// (wrapping around) with `replacement`, leaving the cursor just past the
+    /// new text. Returns false when there is no match.
+    pub fn replace_next(&mut self, needle: &str, replacement: &str) -> bool {
+        let from = (self.cursor_line, self.cursor_col);
+        let Some(pos) = self.find(needle, from) else {
+            return false;
+        };
+        self.clear_selection();
+        self.record(EditOp::Group(vec![
+            EditOp::Delete {
+                line: pos.0,
+                col: pos.1,
+                text: needle.to_string(),
+            },
+            EditOp::Insert {
+                line: pos.0,
+                col: pos.1,
+                text: replacement.to_string(),
+            },
+        ]));
+        self.apply_delete(pos.0, pos.1, needle);
+        let (line, col) = self.apply_insert(pos.0, pos.1, replacement);
+        self.set_cursor(line, col);
+        true
+    }
+
+    /// Replace every occurrence of `needle`, front to back, as a single undo
+    /// group. Returns how many replacements were made. Occurrences created
+    /// by the replacement text itself are not re-examined.
+    pub fn replace_all(&mut self, needle: &str, replacement: &str) -> usize {
+        let mut ops = Vec::new();
+        let mut from = (0, 0);
+        let mut end = from;
+        while let Some(pos) = self.find(needle, from) {
+            if pos < from {
+                // find() wrapped around; we're done with the pass.
+                break;
+            }
+            ops.push(EditOp::Delete {
+                line: pos.0,
+                col: pos.1,
+                text: needle.to_string(),
+            });
+            ops.push(EditOp::Insert {
+                line: pos.0,
+                col: pos.1,
+                text: replacement.to_string(),
+            });
+            self.apply_delete(pos.0, pos.1, needle);
+            end = self.apply_insert(pos.0, pos.1, replacement);
+            from = end;
+        }
+        let count = ops.len() / 2;
+        if count > 0 {
+            self.clear_selection();
+            self.record(EditOp::Group(ops));
+            self.set_cursor(end.0, end.1);
+        }
+        count
+    }
+
+    /// Put the selection on a search match so it renders highlighted, and
+    /// move the cursor past it.
+    pub fn select_match(&mut self, start: (usize, usize), len: usize) {
+        self.selection_anchor = Some(start);
+        self.selection_mode = SelectionMode::Normal;
+        self.cursor_line = start.0;
+        self.cursor_col = start.1 + len;
+        self.remember_desired_col();
+    }
+
+    /// Select the entire buffer: anchor at the very start, cursor at the
+    /// very end. The next draw scrolls the viewport to the cursor as usual.
+    pub fn select_all(&mut self) {
+        self.selection_anchor = Some((0, 0));
+        self.selection_mode = SelectionMode::Normal;
+        let last = self.lines.len() - 1;
+        self.cursor_line = last;
+        self.cursor_col = self.line_char_count(last);
+    }
+
+    /// Revert the most recent edit and put the cursor back where it was.
+    pub fn undo(&mut self) {
+        let Some(record) = self.undo_stack.pop() else {
+            return;
+        };
+        self.modified = true;
+        let op = record.op.clone();
+        self.revert_op(&op);
+        let (line, col) = record.cursor_before;
+        self.set_cursor(line, col);
+        self.redo_stack.push(record);
+    }
+
+    /// Set what the Tab key inserts; the `set indent` command and the
+    /// per-filetype config both funnel through here.
+    pub fn set_indent_style(&mut self, style: IndentStyle) {
+        self.indent_style = style;
+    }
+
+    /// Mark the start of an undo group. Everything recorded until the
+    /// matching [`end_undo_group`](Self::end_undo_group) collapses into a
+    /// single undo step, e.g. one macro run.
+    pub fn start_undo_group(&mut self) -> usize {
+        self.undo_stack.len()
+    }
+
+    /// Collapse all records since `mark` into one [`EditOp::Group`]. A
+    /// no-op when the region holds fewer than two records.
+    pub fn end_undo_group(&mut self, mark: usize) {
+        if self.undo_stack.len() < mark + 2 {
+            return;
+        }
+        let records = self.undo_stack.split_off(mark);
+        let cursor_before = records[0].cursor_before;
+        self.undo_stack.push(EditRecord {
+            op: EditOp::Group(records.into_iter().map(|r| r.op).collect()),
+            cursor_before,
+            typed: false,
+            at: Instant::now(),
+        });
+    }
+
+    /// Cap the undo history; the oldest records beyond `depth` are dropped
+    /// as new edits come in.
+    pub fn set_max_undo(&mut self, depth: usize) {
+        self.max_undo = depth.max(1);
+    }
+
+    /// Re-apply the most recently undone edit.
+    pub fn redo(&mut self) {
+        let Some(record) = self.redo_stack.pop() else {
+            return;
+        };
+        self.modified = true;
+        let op = record.op.clone();
+        let (line, col) = self.apply_op(&op);
+        self.set_cursor(line, col);
+        self.undo_stack.push(record);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_and_delete_round_trip() {
+        let mut buf = TextBuffer::new();
+        buf.insert_char('h');
+        buf.insert_char('i');
+        assert_eq!(buf.lines, vec!["hi"]);
+        buf.delete_char_before_cursor();
+        assert_eq!(buf.lines, vec!["h"]);
+    }
+
+    #[test]
+    fn newline_splits_line() {
+        let mut buf = TextBuffer::new();
+        buf.paste("hello");
+        buf.set_cursor(0, 2);
+        buf.insert_newline();
+        assert_eq!(buf.lines, vec!["he", "llo"]);
+        assert_eq!((buf.cursor_line, buf.cursor_col), (1, 0));
+    }
+
+    #[test]
+    fn backspace_at_line_start_merges() {
+        let mut buf = TextBuffer::new();
+        buf.paste("ab\ncd");
+        buf.set_cursor(1, 0);
+        buf.delete_char_before_cursor();
+        assert_eq!(buf.lines, vec!["abcd"]);
+        assert_eq!((buf.cursor_line, buf.cursor_col), (0, 2));
+    }
+
+    #[test]
+    fn from_str_round_trips_through_into_string() {
+        let buf = TextBuffer::from_str("one\ntwo\n");
+        assert_eq!(buf.lines, vec!["one", "two", ""]);
+        assert_eq!(buf.into_string(), "one\ntwo\n");
+        let buf = TextBuffer::from_str("no trailing newline");
+        assert_eq!(buf.lines, vec!["no trailing newline"]);
+        assert_eq!(buf.into_string(), "no trailing newline");
+        assert_eq!(TextBuffer::from_str("").lines, vec![""]);
+    }
+
+    #[test]
+    fn from_str_detects_and_restores_crlf() {
+        let buf = TextBuffer::from_str("one\r\ntwo\r\n");
+        assert_eq!(buf.lines, vec!["one", "two", ""]);
+        assert_eq!(buf.line_ending(), LineEnding::Crlf);
+        assert_eq!(buf.into_string(), "one\r\ntwo\r\n");
+    }
+
+    #[test]
+    fn set_cursor_clamps() {
+        let mut buf = TextBuffer::new();
+        buf.paste("short\nlonger line");
+        buf.set_cursor(99, 99);
+        assert_eq!(buf.cursor_line, 1);
+        assert_eq!(buf.cursor_col, "longer line".chars().count());
+    }
+
+    #[test]
+    fn shift_movement_anchors_and_extends() {
+        let mut buf = TextBuffer::new();
+        buf.paste("hello world");
+        buf.set_cursor(0, 0);
+        buf.select_right();
+        buf.select_right();
+        assert_eq!(buf.get_selection(), Some(((0, 0), (0, 2))));
+    }
+
+    #[test]
+    fn select_inside_picks_the_nearest_enclosing_pair() {
+        let mut buf = TextBuffer::new();
+        buf.paste("foo(bar(baz))");
+        // Cursor inside `baz`: only the inner pair's contents are selected.
+        buf.set_cursor(0, 9);
+        assert!(buf.select_inside_pair('(', ')'));
+        assert_eq!(buf.get_selection(), Some(((0, 8), (0, 11))));
+        // Cursor inside `bar`, outside the inner pair: the outer contents,
+        // nested pair included.
+        buf.set_cursor(0, 5);
+        assert!(buf.select_inside_pair('(', ')'));
+        assert_eq!(buf.get_selection(), Some(((0, 4), (0, 12))));
+        // Cursor directly on the inner opener selects that pair's inside.
+        buf.set_cursor(0, 7);
+        assert!(buf.select_inside_pair('(', ')'));
+        assert_eq!(buf.get_selection(), Some(((0, 8), (0, 11))));
+        // No enclosing pair before `foo`.
+        buf.set_cursor(0, 1);
+        assert!(!buf.select_inside_pair('{', '}'));
+    }
+
+    #[test]
+    fn select_to_matching_bracket_includes_both_ends() {
+        let mut buf = TextBuffer::new();
+        buf.paste("foo(bar)");
+        buf.set_cursor(0, 3);
+        assert!(buf.select_to_matching_bracket());
+        assert_eq!(buf.get_selection(), Some(((0, 3), (0, 8))));
+        // And the same range when starting from the closer.
+        buf.set_cursor(0, 7);
+        assert!(buf.select_to_matching_bracket());
+        assert_eq!(buf.get_selection(), Some(((0, 3), (0, 8))));
+    }
+
+    #[test]
+    fn word_boundaries_stop_at_punctuation_runs() {
+        let mut buf = TextBuffer::new();
+        buf.paste("foo.bar_baz");
+        // Rightward stops: after `foo`, after `.`, after `bar_baz`.
+        assert_eq!(buf.word_boundary_right((0, 0)), 3);
+        assert_eq!(buf.word_boundary_right((0, 3)), 4);
+        assert_eq!(buf.word_boundary_right((0, 4)), 11);
+        // And the same stops coming back.
+        assert_eq!(buf.word_boundary_left((0, 11)), 4);
+        assert_eq!(buf.word_boundary_left((0, 4)), 3);
+        assert_eq!(buf.word_boundary_left((0, 3)), 0);
+    }
+
+    #[test]
+    fn word_movement_crosses_line_boundaries() {
+        let mut buf = TextBuffer::new();
+        buf.paste("alpha beta\ngamma");
+        buf.set_cursor(1, 0);
+        // From the start of `gamma`, one step back lands on the end of the
+        // previous line, the next on the start of `beta`.
+        buf.move_word_left();
+        assert_eq!((buf.cursor_line, buf.cursor_col), (0, 10));
+        buf.move_word_left();
+        assert_eq!((buf.cursor_line, buf.cursor_col), (0, 6));
+        // Word movement never creates a selection.
+        assert_eq!(buf.get_selection(), None);
+    }
+
+    #[test]
+    fn word_selection_extends_word_by_word() {
+        let mut buf = TextBuffer::new();
+        buf.paste("one two");
+        buf.set_cursor(0, 0);
+        buf.select_word_right();
+        assert_eq!(buf.get_selection(), Some(((0, 0), (0, 3))));
+        // The second step swallows the space and the next word together.
+        buf.select_word_right();
+        assert_eq!(buf.get_selection(), Some(((0, 0), (0, 7))));
+    }
+
+    #[test]
+    fn block_insert_hits_every_selected_row() {
+        let mut buf = TextBuffer::new();
+        buf.paste("one\ntwo\nthree\nfour\nfive");
+        buf.set_cursor(0, 0);
+        for _ in 0..4 {
+            buf.select_block_down();
+        }
+        assert_eq!(buf.selection_mode(), SelectionMode::Block);
+        buf.block_insert("// ");
+        assert_eq!(
+            buf.lines,
+            vec!["// one", "// two", "// three", "// four", "// five"]
+        );
+        // The whole block edit is one undo step.
+        buf.undo();
+        assert_eq!(buf.lines, vec!["one", "two", "three", "four", "five"]);
+    }
+
+    #[test]
+    fn block_delete_skips_rows_shorter_than_the_block() {
+        let mut buf = TextBuffer::new();
+        buf.paste("aXXb\nc\neXXf");
+        buf.set_cursor(0, 1);
+        buf.select_block_down();
+        buf.select_block_down();
+        buf.select_block_right();
+        buf.select_block_right();
+        buf.block_delete();
+        assert_eq!(buf.lines, vec!["ab", "c", "ef"]);
+        assert_eq!((buf.cursor_line, buf.cursor_col), (0, 1));
+    }
+
+    #[test]
+    fn backwards_selection_is_normalized() {
+        let mut buf = TextBuffer::new();
+        buf.paste("one\ntwo");
+        buf.set_cursor(1, 2);
+        buf.select_up();
+        assert_eq!(buf.get_selection(), Some(((0, 2), (1, 2))));
+    }
+
+    #[test]
+    fn plain_movement_clears_selection() {
+        let mut buf = TextBuffer::new();
+        buf.paste("hello");
+        buf.set_cursor(0, 0);
+        buf.select_right();
+        assert!(buf.get_selection().is_some());
+        buf.move_left();
+        assert_eq!(buf.get_selection(), None);
+    }
+
+    #[test]
+    fn empty_selection_reports_none() {
+        let mut buf = TextBuffer::new();
+        buf.paste("ab");
+        buf.set_cursor(0, 1);
+        buf.select_right();
+        buf.select_left();
+        assert_eq!(buf.get_selection(), None);
+    }
+
+    #[test]
+    fn copy_single_line_selection() {
+        let mut buf = TextBuffer::new();
+        buf.paste("hello world");
+        buf.set_cursor(0, 6);
+        for _ in 0..5 {
+            buf.select_right();
+        }
+        assert_eq!(buf.copy_selected_text(), "world");
+    }
+
+    #[test]
+    fn copy_multi_line_selection() {
+        let mut buf = TextBuffer::new();
+        buf.paste("one\ntwo\nthree");
+        buf.set_cursor(0, 2);
+        buf.select_down();
+        buf.select_down();
+        assert_eq!(buf.copy_selected_text(), "e\ntwo\nth");
+    }
+
+    #[test]
+    fn selection_text_joins_lines_and_is_none_when_empty() {
+        let mut buf = TextBuffer::new();
+        buf.paste("one\ntwo");
+        assert_eq!(buf.selection_text(), None);
+        buf.set_cursor(0, 1);
+        buf.select_down();
+        assert_eq!(buf.selection_text(), Some("ne\nt".to_string()));
+        buf.clear_selection();
+        assert_eq!(buf.selection_text(), None);
+    }
+
+    #[test]
+    fn copy_without_selection_takes_current_line() {
+        let mut buf = TextBuffer::new();
+        buf.paste("one\ntwo");
+        buf.set_cursor(1, 1);
+        assert_eq!(buf.copy_selected_text(), "two");
+    }
+
+    #[test]
+    fn cut_multi_line_partial_selection() {
+        let mut buf = TextBuffer::new();
+        buf.paste("line one\nline two\nline three\nline four\nline five");
+        // Select from mid-line 2 to mid-line 5 (0-based lines 1..4).
+        buf.set_cursor(1, 5);
+        for _ in 0..3 {
+            buf.select_down();
+        }
+        assert_eq!(buf.cut_selected_text(), "two\nline three\nline four\nline ");
+        assert_eq!(buf.lines, vec!["line one", "line five"]);
+        assert_eq!((buf.cursor_line, buf.cursor_col), (1, 5));
+        buf.undo();
+        assert_eq!(
+            buf.lines,
+            vec![
+                "line one",
+                "line two",
+                "line three",
+                "line four",
+                "line five"
+            ]
+        );
+    }
+
+    #[test]
+    fn cut_without_selection_cuts_current_line() {
+        let mut buf = TextBuffer::new();
+        buf.paste("one\ntwo");
+        buf.set_cursor(0, 1);
+        assert_eq!(buf.cut_selected_text(), "one");
+        assert_eq!(buf.lines, vec!["two"]);
+    }
+
+    #[test]
+    fn select_all_covers_everything() {
+        let mut buf = TextBuffer::new();
+        buf.paste("one\ntwo");
+        buf.set_cursor(0, 0);
+        buf.select_all();
+        assert_eq!(buf.get_selection(), Some(((0, 0), (1, 3))));
+        assert_eq!(buf.copy_selected_text(), "one\ntwo");
+    }
+
+    #[test]
+    fn select_all_on_empty_buffer_selects_nothing() {
+        let mut buf = TextBuffer::new();
+        buf.select_all();
+        assert_eq!(buf.get_selection(), None);
+    }
+
+    #[test]
+    fn from_file_keeps_last_line_without_trailing_newline() {
+        let dir = std::env::temp_dir();
+        let path = dir.join("trust_test_no_trailing_newline.txt");
+        fs::write(&path, "one\ntwo").unwrap();
+        let buf = TextBuffer::from_file(&path).unwrap();
+        assert_eq!(buf.lines, vec!["one", "two"]);
+        assert_eq!(buf.filename(), Some(path.as_path()));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn the_selection_anchor_tracks_edits_made_above_it() {
+        let mut buf = TextBuffer::from_str("0\n1\n2\n3\n4\nfive\nsix\nseven\n8\n9");
+        buf.set_cursor(7, 5);
+        buf.select_to(5, 0);
+        // An edit above the selection, applied at its recorded position
+        // the way macro replay and grouped ops do; the replayer owns the
+        // cursor, the buffer owns the anchor.
+        let op = EditOp::Insert {
+            line: 0,
+            col: 0,
+            text: "x\ny\n".to_string(),
+        };
+        buf.record(op.clone());
+        buf.apply_op(&op);
+        // The selected lines 5-7 now sit on 7-9 and the anchor followed.
+        buf.set_cursor(7, 0);
+        assert_eq!(buf.get_selection(), Some(((7, 0), (9, 5))));
+        assert_eq!(buf.copy_selected_text(), "five\nsix\nseven");
+        // Undoing the insert slides the anchor back up with its text.
+        buf.undo();
+        assert_eq!(buf.get_selection(), Some(((5, 0), (7, 5))));
+    }
+
+    #[test]
+    fn sort_lines_orders_a_block_ascending_and_descending() {
+        let mut buf = TextBuffer::new();
+        buf.paste("banana\nApple\ncherry");
+        buf.sort_lines(0, 2, false, false);
+        // Plain ordering is by scalar value, so uppercase sorts first.
+        assert_eq!(buf.lines, vec!["Apple", "banana", "cherry"]);
+        assert_eq!((buf.cursor_line, buf.cursor_col), (0, 0));
+        buf.sort_lines(0, 2, true, false);
+        assert_eq!(buf.lines, vec!["cherry", "banana", "Apple"]);
+        // One undo step reverts a whole sort.
+        buf.undo();
+        assert_eq!(buf.lines, vec!["Apple", "banana", "cherry"]);
+    }
+
+    #[test]
+    fn dedup_lines_removes_adjacent_duplicates() {
+        let mut buf = TextBuffer::new();
+        buf.paste("a\na\nb\na\nb\nb");
+        let removed = buf.dedup_lines(0, 5, true, false);
+        assert_eq!(removed, 2);
+        // Only runs collapse; the scattered repeats survive.
+        assert_eq!(buf.lines, vec!["a", "b", "a", "b"]);
+        assert_eq!((buf.cursor_line, buf.cursor_col), (0, 0));
+        buf.undo();
+        assert_eq!(buf.lines, vec!["a", "a", "b", "a", "b", "b"]);
+    }
+
+    #[test]
+    fn dedup_lines_can_remove_scattered_duplicates() {
+        let mut buf = TextBuffer::new();
+        buf.paste("a\nb\na\nc\nb");
+        assert_eq!(buf.dedup_lines(0, 4, false, false), 2);
+        assert_eq!(buf.lines, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn dedup_lines_can_trim_whitespace_for_comparison() {
+        let mut buf = TextBuffer::new();
+        buf.paste("a\n  a\na \nb");
+        // Trimmed comparison collapses the variants, keeping the first
+        // occurrence verbatim; without it every line is distinct.
+        assert_eq!(buf.dedup_lines(0, 3, false, false), 0);
+        assert_eq!(buf.dedup_lines(0, 3, false, true), 2);
+        assert_eq!(buf.lines, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn trim_trailing_whitespace_is_one_undo_group() {
+        let mut buf = TextBuffer::new();
+        buf.paste("a  \nb\t\nc");
+        assert_eq!(buf.trim_trailing_whitespace(), 2);
+        assert_eq!(buf.lines, vec!["a", "b", "c"]);
+        // A second pass finds nothing and records nothing.
+        assert_eq!(buf.trim_trailing_whitespace(), 0);
+        buf.undo();
+        assert_eq!(buf.lines, vec!["a  ", "b\t", "c"]);
+    }
+
+    #[test]
+    fn content_can_skip_the_final_newline() {
+        let mut buf = TextBuffer::new();
+        buf.paste("one\ntwo");
+        assert_eq!(buf.content(), "one\ntwo\n");
+        buf.final_newline = false;
+        assert_eq!(buf.content(), "one\ntwo");
+    }
+
+    #[test]
+    fn sort_lines_can_ignore_case() {
+        let mut buf = TextBuffer::new();
+        buf.paste("b\nA\nC");
+        buf.sort_lines(0, 2, false, true);
+        assert_eq!(buf.lines, vec!["A", "b", "C"]);
+    }
+
+    #[test]
+    fn change_marks_classify_an_edited_and_an_inserted_line() {
+        let path = std::env::temp_dir().join("trust_test_change_marks.txt");
+        fs::write(&path, "one\ntwo\nthree\n").unwrap();
+        let mut buf = TextBuffer::from_file(&path).unwrap();
+        // Edit "two" in place and insert a brand-new line after "three".
+        buf.set_cursor(1, 3);
+        buf.paste("!");
+        buf.set_cursor(2, 5);
+        buf.paste("\nextra");
+        buf.refresh_change_marks();
+        assert_eq!(buf.change_mark(0), ChangeMark::Unchanged);
+        assert_eq!(buf.change_mark(1), ChangeMark::Modified);
+        assert_eq!(buf.change_mark(2), ChangeMark::Unchanged);
+        assert_eq!(buf.change_mark(3), ChangeMark::Added);
+        // Saving resets the baseline: everything reads unchanged again.
+        buf.mark_saved();
+        buf.refresh_change_marks();
+        assert_eq!(buf.change_mark(1), ChangeMark::Unchanged);
+        assert_eq!(buf.change_mark(3), ChangeMark::Unchanged);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn a_pure_deletion_marks_the_line_below_the_gap() {
+        let original: Vec<String> = ["a", "b", "c"].iter().map(|s| s.to_string()).collect();
+        let current: Vec<String> = ["a", "c"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(
+            diff_marks(&original, &current),
+            vec![ChangeMark::Unchanged, ChangeMark::DeletedAbove]
+        );
+        // Deleting the tail falls back to marking the last remaining line.
+        let tail_cut: Vec<String> = ["a"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(
+            diff_marks(&original, &tail_cut),
+            vec![ChangeMark::DeletedAbove]
+        );
+    }
+
+    #[test]
+    fn replace_range_on_a_single_line_returns_the_removed_text() {
+        let mut buf = TextBuffer::new();
+        buf.paste("hello world");
+        let removed = buf.replace_range((0, 6), (0, 11), "rust");
+        assert_eq!(removed, "world");
+        assert_eq!(buf.lines, vec!["hello rust"]);
+        assert_eq!((buf.cursor_line, buf.cursor_col), (0, 10));
+        // One undo step restores the original.
+        buf.undo();
+        assert_eq!(buf.lines, vec!["hello world"]);
+    }
+
+    #[test]
+    fn replace_range_across_lines_joins_the_remainder() {
+        let mut buf = TextBuffer::new();
+        buf.paste("one\ntwo\nthree");
+        let removed = buf.replace_range((0, 1), (2, 2), "-");
+        assert_eq!(removed, "ne\ntwo\nth");
+        assert_eq!(buf.lines, vec!["o-ree"]);
+        assert_eq!((buf.cursor_line, buf.cursor_col), (0, 2));
+    }
+
+    #[test]
+    fn replace_range_with_newlines_splits_lines() {
+        let mut buf = TextBuffer::new();
+        buf.paste("head tail");
+        buf.replace_range((0, 4), (0, 5), "\nmid\n");
+        assert_eq!(buf.lines, vec!["head", "mid", "tail"]);
+        assert_eq!((buf.cursor_line, buf.cursor_col), (2, 0));
+    }
+
+    #[test]
+    fn replace_range_with_empty_text_is_a_pure_delete() {
+        let mut buf = TextBuffer::new();
+        buf.paste("one\ntwo");
+        let removed = buf.replace_range((0, 2), (1, 1), "");
+        assert_eq!(removed, "e\nt");
+        assert_eq!(buf.lines, vec!["onwo"]);
+        assert_eq!((buf.cursor_line, buf.cursor_col), (0, 2));
+        buf.undo();
+        assert_eq!(buf.lines, vec!["one", "two"]);
+    }
+
+    #[test]
+    fn replace_contents_swaps_the_whole_buffer_as_one_undo_step() {
+        let mut buf = TextBuffer::new();
+        buf.paste("old one\nold two");
+        buf.replace_contents("new one\nnew two\nnew three");
+        assert_eq!(buf.lines, vec!["new one", "new two", "new three"]);
+        buf.undo();
+        assert_eq!(buf.lines, vec!["old one", "old two"]);
+    }
+
+    #[test]
+    fn paged_buffers_fault_lines_in_around_the_view() {
+        let dir = std::env::temp_dir();
+        let path = dir.join("trust_test_paged_buffer.txt");
+        let text: String = (1..=100).map(|i| format!("line {i}\n")).collect();
+        fs::write(&path, &text).unwrap();
+        let mut buf = TextBuffer::open_paged(&path).unwrap();
+        assert!(buf.read_only);
+        assert!(buf.is_paged());
+        assert_eq!(buf.lines.len(), 100);
+        assert!(buf.lines.iter().all(|l| l.is_empty()));
+        buf.scroll_top = 40;
+        buf.set_cursor(45, 0);
+        buf.page_in(10);
+        assert_eq!(buf.lines[40], "line 41");
+        assert_eq!(buf.lines[49], "line 50");
+        // Only the window plus its margin is resident.
+        assert!(buf.lines[0].is_empty());
+        assert!(buf.lines[99].is_empty());
+        // Scrolling away releases the old window.
+        buf.scroll_top = 0;
+        buf.set_cursor(0, 0);
+        buf.page_in(10);
+        assert_eq!(buf.lines[0], "line 1");
+        assert!(buf.lines[45].is_empty());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn transpose_swaps_around_the_cursor_and_advances() {
+        let mut buf = TextBuffer::new();
+        buf.paste("ab");
+        buf.set_cursor(0, 1);
+        buf.transpose_chars();
+        assert_eq!(buf.lines, vec!["ba"]);
+        assert_eq!((buf.cursor_line, buf.cursor_col), (0, 2));
+        // One undo step restores the original pair.
+        buf.undo();
+        assert_eq!(buf.lines, vec!["ab"]);
+    }
+
+    #[test]
+    fn transpose_at_line_end_swaps_the_last_two_chars() {
+        let mut buf = TextBuffer::new();
+        buf.paste("ab");
+        buf.transpose_chars();
+        assert_eq!(buf.lines, vec!["ba"]);
+        assert_eq!((buf.cursor_line, buf.cursor_col), (0, 2));
+        // Repeating keeps swapping in place, as in Emacs.
+        buf.transpose_chars();
+        assert_eq!(buf.lines, vec!["ab"]);
+    }
+
+    #[test]
+    fn transpose_is_a_no_op_at_line_start_and_grapheme_safe() {
+        let mut buf = TextBuffer::new();
+        buf.paste("ab");
+        buf.set_cursor(0, 0);
+        buf.transpose_chars();
+        assert_eq!(buf.lines, vec!["ab"]);
+        assert_eq!((buf.cursor_line, buf.cursor_col), (0, 0));
+        // Multibyte pairs swap whole graphemes, not bytes.
+        let mut buf = TextBuffer::new();
+        buf.paste("éü");
+        buf.set_cursor(0, 1);
+        buf.transpose_chars();
+        assert_eq!(buf.lines, vec!["üé"]);
+    }
+
+    #[test]
+    fn toggle_case_flips_every_selected_character() {
+        let mut buf = TextBuffer::new();
+        buf.paste("Foo Bar");
+        buf.select_match((0, 0), 7);
+        buf.toggle_case();
+        assert_eq!(buf.lines, vec!["fOO bAR"]);
+        // The selection still covers the transformed text.
+        assert_eq!(buf.get_selection(), Some(((0, 0), (0, 7))));
+        buf.undo();
+        assert_eq!(buf.lines, vec!["Foo Bar"]);
+    }
+
+    #[test]
+    fn upcase_without_a_selection_takes_the_word_under_the_cursor() {
+        let mut buf = TextBuffer::new();
+        buf.paste("one two");
+        buf.set_cursor(0, 5);
+        buf.upcase();
+        assert_eq!(buf.lines, vec!["one TWO"]);
+        buf.set_cursor(0, 3);
+        // Whitespace under the cursor: no word, nothing changes.
+        buf.downcase();
+        assert_eq!(buf.lines, vec!["one TWO"]);
+    }
+
+    #[test]
+    fn upcase_handles_length_changing_mappings() {
+        let mut buf = TextBuffer::new();
+        buf.paste("straße x");
+        buf.select_match((0, 0), 6);
+        buf.upcase();
+        // `ß` upcases to `SS`, growing the text by one char.
+        assert_eq!(buf.lines, vec!["STRASSE x"]);
+        assert_eq!(buf.get_selection(), Some(((0, 0), (0, 7))));
+    }
+
+    #[test]
+    fn marks_follow_their_text_through_edits_above() {
+        let mut buf = TextBuffer::new();
+        buf.paste("one\ntwo\nthree");
+        buf.set_cursor(2, 1);
+        buf.set_mark('a');
+        // Inserting lines above shifts the mark down with its text.
+        buf.set_cursor(0, 0);
+        buf.paste("zero\n");
+        assert!(buf.goto_mark('a'));
+        assert_eq!((buf.cursor_line, buf.cursor_col), (3, 1));
+        assert_eq!(&buf.lines[buf.cursor_line], "three");
+        // Deleting those lines again brings it back.
+        buf.undo();
+        assert!(buf.goto_mark('a'));
+        assert_eq!((buf.cursor_line, buf.cursor_col), (2, 1));
+        assert!(!buf.goto_mark('b'));
+    }
+
+    #[test]
+    fn marks_below_a_cut_line_slide_up_and_survive_undo() {
+        let mut buf = TextBuffer::new();
+        buf.paste("one\ntwo\nthree");
+        buf.set_cursor(2, 1);
+        buf.set_mark('a');
+        buf.set_cursor(0, 0);
+        buf.cut_lines();
+        assert_eq!(buf.lines, vec!["two", "three"]);
+        assert!(buf.goto_mark('a'));
+        assert_eq!((buf.cursor_line, buf.cursor_col), (1, 1));
+        // Undo restores the line and the mark slides back down with it.
+        buf.undo();
+        assert!(buf.goto_mark('a'));
+        assert_eq!((buf.cursor_line, buf.cursor_col), (2, 1));
+    }
+
+    #[test]
+    fn marks_follow_a_backspace_line_join_and_its_undo() {
+        let mut buf = TextBuffer::new();
+        buf.paste("one\ntwo\nthree");
+        buf.set_cursor(2, 2);
+        buf.set_mark('a');
+        // Backspace at column 0 joins "two" onto "one".
+        buf.set_cursor(1, 0);
+        buf.delete_char_before_cursor();
+        assert_eq!(buf.lines, vec!["onetwo", "three"]);
+        assert!(buf.goto_mark('a'));
+        assert_eq!((buf.cursor_line, buf.cursor_col), (1, 2));
+        buf.undo();
+        assert!(buf.goto_mark('a'));
+        assert_eq!((buf.cursor_line, buf.cursor_col), (2, 2));
+    }
+
+    #[test]
+    fn marks_slide_with_in_line_typing_and_word_deletes() {
+        let mut buf = TextBuffer::new();
+        buf.paste("hello world");
+        buf.set_cursor(0, 6);
+        buf.set_mark('a');
+        // Typing before the mark pushes it right...
+        buf.set_cursor(0, 0);
+        buf.insert_char('x');
+        assert!(buf.goto_mark('a'));
+        assert_eq!((buf.cursor_line, buf.cursor_col), (0, 7));
+        // ...and deleting the word it followed pulls it back.
+        buf.set_cursor(0, 6);
+        buf.delete_word_before_cursor();
+        assert_eq!(buf.lines, vec![" world"]);
+        assert!(buf.goto_mark('a'));
+        assert_eq!((buf.cursor_line, buf.cursor_col), (0, 1));
+        buf.undo();
+        buf.undo();
+        assert!(buf.goto_mark('a'));
+        assert_eq!((buf.cursor_line, buf.cursor_col), (0, 6));
+    }
+
+    #[test]
+    fn a_mark_on_a_deleted_line_clamps_to_the_deletion_point() {
+        let mut buf = TextBuffer::new();
+        buf.paste("one\ntwo\nthree");
+        buf.set_cursor(1, 2);
+        buf.set_mark('a');
+        buf.set_cursor(1, 0);
+        buf.select_down();
+        buf.cut_selected_text();
+        assert_eq!(buf.lines, vec!["one", "three"]);
+        assert!(buf.goto_mark('a'));
+        assert_eq!((buf.cursor_line, buf.cursor_col), (1, 0));
+    }
+
+    #[test]
+    fn fold_range_follows_the_indentation() {
+        let mut buf = TextBuffer::new();
+        buf.paste("fn main() {\n    let x = 1;\n\n    let y = 2;\n}\nfn other() {}");
+        // The block runs to the last deeper line, across the blank one.
+        assert_eq!(buf.fold_range_at(0), Some((1, 3)));
+        // A line with nothing deeper below it heads no block.
+        assert_eq!(buf.fold_range_at(1), None);
+        assert_eq!(buf.fold_range_at(4), None);
+        // Blank lines head no block either.
+        assert_eq!(buf.fold_range_at(2), None);
+    }
+
+    #[test]
+    fn folding_hides_lines_and_movement_steps_over_them() {
+        let mut buf = TextBuffer::new();
+        buf.paste("fn main() {\n    let x = 1;\n    let y = 2;\n}");
+        buf.set_cursor(0, 0);
+        assert!(buf.toggle_fold());
+        assert_eq!(buf.fold_at(0), Some(2));
+        assert!(buf.is_line_hidden(1) && buf.is_line_hidden(2));
+        assert_eq!(buf.hidden_lines_between(0, 4), 2);
+        // Down from the header lands past the fold; up comes straight back.
+        buf.move_down();
+        assert_eq!(buf.cursor_line, 3);
+        buf.move_up();
+        assert_eq!(buf.cursor_line, 0);
+        // Toggling again reopens the block.
+        assert!(buf.toggle_fold());
+        assert!(!buf.is_line_hidden(1));
+    }
+
+    #[test]
+    fn edits_shift_folds_and_jumping_inside_opens_them() {
+        let mut buf = TextBuffer::new();
+        buf.paste("a\nfn main() {\n    let x = 1;\n}");
+        buf.set_cursor(1, 0);
+        assert!(buf.toggle_fold());
+        // Inserting a line above slides the fold down with its text.
+        buf.set_cursor(0, 0);
+        buf.paste("zero\n");
+        assert_eq!(buf.fold_at(2), Some(1));
+        // Jumping into the hidden line reveals the block.
+        buf.set_cursor(3, 0);
+        assert!(!buf.is_line_hidden(3));
+        assert_eq!(buf.fold_at(2), None);
+    }
+
+    #[test]
+    fn delete_to_line_end_truncates_and_then_joins() {
+        let mut buf = TextBuffer::new();
+        buf.paste("hello world\nnext");
+        buf.set_cursor(0, 5);
+        buf.delete_to_line_end();
+        assert_eq!(buf.lines, vec!["hello", "next"]);
+        assert_eq!((buf.cursor_line, buf.cursor_col), (0, 5));
+        // At the end of the line, the newline goes instead.
+        buf.delete_to_line_end();
+        assert_eq!(buf.lines, vec!["hellonext"]);
+        buf.undo();
+        buf.undo();
+        assert_eq!(buf.lines, vec!["hello world", "next"]);
+    }
+
+    #[test]
+    fn delete_to_line_start_clears_back_to_column_zero() {
+        let mut buf = TextBuffer::new();
+        buf.paste("hello world");
+        buf.set_cursor(0, 6);
+        buf.delete_to_line_start();
+        assert_eq!(buf.lines, vec!["world"]);
+        assert_eq!(buf.cursor_col, 0);
+        // Column 0 has nothing behind it to delete.
+        buf.delete_to_line_start();
+        assert_eq!(buf.lines, vec!["world"]);
+        buf.undo();
+        assert_eq!(buf.lines, vec!["hello world"]);
+    }
+
+    #[test]
+    fn indent_lines_shifts_the_selection_with_the_text() {
+        let mut buf = TextBuffer::new();
+        buf.paste("one\ntwo\nthree");
+        buf.set_cursor(0, 1);
+        buf.anchor_selection(SelectionMode::Normal);
+        buf.set_cursor(2, 2);
+        buf.indent_style = IndentStyle::Spaces;
+        buf.indent_lines(0, 2, 4);
+        assert_eq!(buf.lines, vec!["    one", "    two", "    three"]);
+        // Both ends of the selection moved right with their lines.
+        assert_eq!(buf.get_selection(), Some(((0, 5), (2, 6))));
+    }
+
+    #[test]
+    fn indent_shifts_the_cursor_wherever_it_sits_in_the_block() {
+        // First, middle and last line of the indented range: the cursor
+        // moves by exactly what was inserted in front of it, and lines
+        // outside the range leave it alone.
+        for (line, col) in [(0, 0), (1, 2), (2, 3)] {
+            let mut buf = TextBuffer::new();
+            buf.paste("aaa\nbbb\nccc\nddd");
+            buf.set_cursor(line, col);
+            buf.indent_style = IndentStyle::Spaces;
+            buf.indent_lines(0, 2, 4);
+            assert_eq!((buf.cursor_line, buf.cursor_col), (line, col + 4));
+        }
+        let mut buf = TextBuffer::new();
+        buf.paste("aaa\nbbb\nccc\nddd");
+        buf.set_cursor(3, 1);
+        buf.indent_style = IndentStyle::Spaces;
+        buf.indent_lines(0, 2, 4);
+        assert_eq!((buf.cursor_line, buf.cursor_col), (3, 1));
+    }
+
+    #[test]
+    fn unindent_removes_one_level_from_each_line() {
+        let mut buf = TextBuffer::new();
+        buf.paste("\t\tone\n        two\nthree");
+        buf.set_cursor(0, 0);
+        buf.anchor_selection(SelectionMode::Normal);
+        buf.set_cursor(2, 5);
+        buf.unindent_lines(0, 2, 4);
+        // One tab and one level of spaces gone; the bare line is untouched.
+        assert_eq!(buf.lines, vec!["\tone", "    two", "three"]);
+        buf.unindent_lines(0, 2, 4);
+        assert_eq!(buf.lines, vec!["one", "two", "three"]);
+    }
+
+    #[test]
+    fn unindent_current_line_works_without_a_selection() {
+        let mut buf = TextBuffer::new();
+        buf.paste("    body");
+        buf.set_cursor(0, 6);
+        buf.unindent_current_line(4);
+        assert_eq!(buf.lines, vec!["body"]);
+        // The cursor stays over the same character.
+        assert_eq!(buf.cursor_col, 2);
+    }
+
+    #[test]
+    fn reload_replaces_content_and_clamps_the_cursor() {
+        let path = std::env::temp_dir().join("trust_test_reload.txt");
+        fs::write(&path, "one\ntwo\nthree\n").unwrap();
+        let mut buf = TextBuffer::from_file(&path).unwrap();
+        buf.set_cursor(2, 5);
+        buf.paste("local edit");
+        assert!(buf.is_modified());
+
+        fs::write(&path, "short\n").unwrap();
+        buf.reload_from(&path).unwrap();
+        assert_eq!(buf.lines, vec!["short"]);
+        assert!(!buf.is_modified());
+        // The old cursor pointed past the new end of the file.
+        assert_eq!((buf.cursor_line, buf.cursor_col), (0, 5));
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn disk_changed_spots_external_writes() {
+        let path = std::env::temp_dir().join("trust_test_disk_changed.txt");
+        fs::write(&path, "original\n").unwrap();
+        let mut buf = TextBuffer::from_file(&path).unwrap();
+        assert!(!buf.disk_changed());
+
+        // Push the mtime forward explicitly; two writes in the same test
+        // can land within the filesystem's timestamp granularity.
+        let file = fs::File::open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(2))
+            .unwrap();
+        assert!(buf.disk_changed());
+        buf.mark_saved();
+        assert!(!buf.disk_changed());
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn lines_iter_matches_the_saved_content() {
+        let mut buf = TextBuffer::new();
+        buf.paste("one\ntwo\nthree");
+        let iterated: Vec<&str> = buf.lines_iter().collect();
+        let content = buf.content();
+        let split: Vec<&str> = content.lines().collect();
+        assert_eq!(iterated, split);
+    }
+
+    #[test]
+    fn crlf_file_round_trips_unchanged() {
+        let path = std::env::temp_dir().join("trust_test_crlf.txt");
+        fs::write(&path, "one\r\ntwo\r\nthree\r\n").unwrap();
+        let buf = TextBuffer::from_file(&path).unwrap();
+        // No carriage returns leak into the edited lines.
+        assert_eq!(buf.lines, vec!["one", "two", "three"]);
+        assert_eq!(buf.content(), "one\r\ntwo\r\nthree\r\n");
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn lf_file_stays_lf() {
+        let path = std::env::temp_dir().join("trust_test_lf.txt");
+        fs::write(&path, "one\ntwo\n").unwrap();
+        let buf = TextBuffer::from_file(&path).unwrap();
+        assert_eq!(buf.content(), "one\ntwo\n");
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn from_file_missing_starts_empty_with_path() {
+        let path = std::env::temp_dir().join("trust_test_does_not_exist.txt");
+        let buf = TextBuffer::from_file(&path).unwrap();
+        assert_eq!(buf.lines, vec![""]);
+        assert_eq!(buf.filename(), Some(path.as_path()));
+    }
+
+    #[test]
+    fn modified_tracks_edits_and_saves() {
+        let mut buf = TextBuffer::new();
+        assert!(!buf.is_modified());
+        buf.insert_char('a');
+        assert!(buf.is_modified());
+        buf.mark_saved();
+        assert!(!buf.is_modified());
+        // A coalesced keystroke must also dirty the buffer.
+        buf.insert_char('b');
+        assert!(buf.is_modified());
+        buf.mark_saved();
+        buf.undo();
+        assert!(buf.is_modified());
+    }
+
+    #[test]
+    fn vertical_movement_remembers_desired_column() {
+        let mut buf = TextBuffer::new();
+        buf.paste("aaaaaaaaaa\nbb\naaaaaaaa");
+        buf.set_cursor(0, 8);
+        buf.move_down();
+        assert_eq!((buf.cursor_line, buf.cursor_col), (1, 2));
+        buf.move_down();
+        assert_eq!((buf.cursor_line, buf.cursor_col), (2, 8));
+        // Horizontal movement re-bases the desired column.
+        buf.move_left();
+        buf.move_up();
+        assert_eq!((buf.cursor_line, buf.cursor_col), (1, 2));
+        buf.move_up();
+        assert_eq!((buf.cursor_line, buf.cursor_col), (0, 7));
+    }
+
+    #[test]
+    fn moving_down_onto_a_tab_indented_line_keeps_the_visual_column() {
+        let mut buf = TextBuffer::new();
+        buf.paste("abcdefgh\n\twide\nabcdefgh");
+        buf.set_cursor(0, 6);
+        let before = visual_col(&buf.lines[0], buf.cursor_col, buf.tab_width);
+        buf.move_down();
+        // Char column 6 on the plain line is visual column 6; on the
+        // tab-indented line that is char column 3 ("\twi|de").
+        let after = visual_col(&buf.lines[1], buf.cursor_col, buf.tab_width);
+        assert_eq!(after, before);
+        assert_eq!((buf.cursor_line, buf.cursor_col), (1, 3));
+        // Coming back down from the tab line keeps aiming for column 6.
+        buf.move_down();
+        assert_eq!((buf.cursor_line, buf.cursor_col), (2, 6));
+    }
+
+    #[test]
+    fn overwrite_replaces_character_under_cursor() {
+        let mut buf = TextBuffer::new();
+        buf.paste("abc");
+        buf.set_cursor(0, 1);
+        buf.overwrite_char('X');
+        assert_eq!(buf.lines, vec!["aXc"]);
+        assert_eq!(buf.cursor_col, 2);
+        buf.undo();
+        buf.undo();
+        assert_eq!(buf.lines, vec!["abc"]);
+    }
+
+    #[test]
+    fn overwrite_at_end_of_line_appends() {
+        let mut buf = TextBuffer::new();
+        buf.paste("ab");
+        buf.overwrite_char('c');
+        assert_eq!(buf.lines, vec!["abc"]);
+    }
+
+    #[test]
+    fn find_searches_forward_and_wraps() {
+        let mut buf = TextBuffer::new();
+        buf.paste("alpha\nbeta\ngamma");
+        assert_eq!(buf.find("beta", (0, 0)), Some((1, 0)));
+        assert_eq!(buf.find("ma", (1, 0)), Some((2, 3)));
+        // Wraps past the end back to the first line.
+        assert_eq!(buf.find("alpha", (2, 0)), Some((0, 0)));
+        assert_eq!(buf.find("missing", (0, 0)), None);
+    }
+
+    #[test]
+    fn find_with_honors_every_flag_combination() {
+        let mut buf = TextBuffer::new();
+        buf.paste("Foo foo foobar");
+        // Exact: the capitalized first word doesn't match.
+        assert_eq!(buf.find_with("foo", (0, 0), false, false), Some((0, 4)));
+        // Case-insensitive: it does.
+        assert_eq!(buf.find_with("foo", (0, 0), true, false), Some((0, 0)));
+        // Whole-word: `foobar`'s prefix is skipped, wrapping back around.
+        assert_eq!(buf.find_with("foo", (0, 5), false, true), Some((0, 4)));
+        assert_eq!(buf.find_with("bar", (0, 0), false, true), None);
+        // Both flags: `Foo` and `foo` match, `foobar` still doesn't.
+        assert_eq!(buf.find_with("FOO", (0, 1), true, true), Some((0, 4)));
+        assert_eq!(buf.find_with("FOO", (0, 5), true, true), Some((0, 0)));
+    }
+
+    #[test]
+    fn find_regex_matches_patterns_and_wraps() {
+        let mut buf = TextBuffer::new();
+        buf.paste("// intro\nfn main() {\n    fn helper() {}\n}");
+        let re = Regex::new(r"\bfn\s+\w+").unwrap();
+        assert_eq!(buf.find_regex(&re, (0, 0)), Some((1, 0, 7)));
+        // A match strictly after `from` on a later line.
+        assert_eq!(buf.find_regex(&re, (1, 1)), Some((2, 4, 13)));
+        // Wraps past the end back to the first match.
+        assert_eq!(buf.find_regex(&re, (3, 0)), Some((1, 0, 7)));
+        let none = Regex::new(r"\bstruct\b").unwrap();
+        assert_eq!(buf.find_regex(&none, (0, 0)), None);
+    }
+
+    #[test]
+    fn typing_with_two_cursors_edits_both_lines() {
+        let mut buf = TextBuffer::new();
+        buf.paste("alpha\nbeta");
+        buf.set_cursor(0, 0);
+        buf.add_cursor_below();
+        assert_eq!(buf.extra_cursors(), &[(1, 0)]);
+        buf.insert_char_smart('x');
+        assert_eq!(buf.lines, vec!["xalpha", "xbeta"]);
+        assert_eq!((buf.cursor_line, buf.cursor_col), (0, 1));
+        assert_eq!(buf.extra_cursors(), &[(1, 1)]);
+        // One undo reverts the whole multi-cursor edit.
+        buf.undo();
+        assert_eq!(buf.lines, vec!["alpha", "beta"]);
+    }
+
+    #[test]
+    fn backspace_with_two_cursors_deletes_on_both_lines() {
+        let mut buf = TextBuffer::new();
+        buf.paste("alpha\nbeta");
+        buf.set_cursor(0, 2);
+        buf.add_cursor_below();
+        buf.delete_char_before_cursor();
+        assert_eq!(buf.lines, vec!["apha", "bta"]);
+        assert_eq!((buf.cursor_line, buf.cursor_col), (0, 1));
+        assert_eq!(buf.extra_cursors(), &[(1, 1)]);
+    }
+
+    #[test]
+    fn add_cursor_at_next_match_follows_the_selection() {
+        let mut buf = TextBuffer::new();
+        buf.paste("foo bar\nfoo baz");
+        buf.select_match((0, 0), 3);
+        assert!(buf.add_cursor_at_next_match());
+        assert_eq!(buf.extra_cursors(), &[(1, 3)]);
+        // The only other occurrence already has a cursor.
+        assert!(!buf.add_cursor_at_next_match());
+        buf.collapse_cursors();
+        assert!(buf.extra_cursors().is_empty());
+    }
+
+    #[test]
+    fn find_starts_at_the_given_column() {
+        let mut buf = TextBuffer::new();
+        buf.paste("aba");
+        assert_eq!(buf.find("a", (0, 1)), Some((0, 2)));
+        // And wraps back to the earlier occurrence.
+        assert_eq!(buf.find("b", (0, 2)), Some((0, 1)));
+    }
+
+    #[test]
+    fn replace_next_moves_past_the_replacement() {
+        let mut buf = TextBuffer::new();
+        buf.paste("foo bar foo");
+        buf.set_cursor(0, 0);
+        assert!(buf.replace_next("foo", "qux"));
+        assert_eq!(buf.lines, vec!["qux bar foo"]);
+        assert_eq!((buf.cursor_line, buf.cursor_col), (0, 3));
+        assert!(buf.replace_next("foo", "qux"));
+        assert_eq!(buf.lines, vec!["qux bar qux"]);
+        assert!(!buf.replace_next("foo", "qux"));
+        buf.undo();
+        assert_eq!(buf.lines, vec!["qux bar foo"]);
+    }
+
+    #[test]
+    fn replace_all_does_not_rescan_replacements() {
+        let mut buf = TextBuffer::new();
+        buf.paste("aaaa");
+        assert_eq!(buf.replace_all("aa", "b"), 2);
+        assert_eq!(buf.lines, vec!["bb"]);
+
+        let mut buf = TextBuffer::new();
+        buf.paste("aaaa");
+        // A replacement containing the needle must not loop forever.
+        assert_eq!(buf.replace_all("aa", "aaa"), 2);
+        assert_eq!(buf.lines, vec!["aaaaaa"]);
+    }
+
+    #[test]
+    fn replace_all_is_one_undo_group() {
+        let mut buf = TextBuffer::new();
+        buf.paste("x y x y");
+        assert_eq!(buf.replace_all("x", "z"), 2);
+        assert_eq!(buf.lines, vec!["z y z y"]);
+        buf.undo();
+        assert_eq!(buf.lines, vec!["x y x y"]);
+        buf.redo();
+        assert_eq!(buf.lines, vec!["z y z y"]);
+    }
+
+    #[test]
+    fn replace_with_newline_splits_the_line() {
+        let mut buf = TextBuffer::new();
+        buf.paste("one,two");
+        buf.set_cursor(0, 0);
+        assert!(buf.replace_next(",", "\n"));
+        assert_eq!(buf.lines, vec!["one", "two"]);
+        buf.undo();
+        assert_eq!(buf.lines, vec!["one,two"]);
+    }
+
+    #[test]
+    fn lines_in_range_clamps_to_buffer() {
+        let mut buf = TextBuffer::new();
+        buf.paste("a\nb\nc");
+        assert_eq!(buf.lines_in_range(1, 3), ["b", "c"]);
+        assert_eq!(buf.lines_in_range(1, 99), ["b", "c"]);
+        assert_eq!(buf.lines_in_range(99, 100), [""; 0]);
+    }
+
+    /// Rough insert-latency check on a ~1MB buffer. Run explicitly with
+    /// `cargo test -- --ignored bench_insert`; results land in
+    /// bench_output.txt.
+    #[test]
+    #[ignore]
+    fn bench_insert_latency_1mb() {
+        use std::fmt::Write as _;
+        use std::time::Instant;
+
+        let line = "x".repeat(79);
+        let mut buf = TextBuffer::new();
+        buf.lines = vec![line; 13_000]; // ~1MB of text
+        buf.set_cursor(6_500, 40);
+
+        let start = Instant::now();
+        for _ in 0..1_000 {
+            buf.insert_char('y');
+        }
+        let elapsed = start.elapsed();
+
+        let mut report = String::new();
+        writeln!(
+            report,
+            "1000 mid-buffer inserts into 1MB: {elapsed:?} ({:?}/insert)",
+            elapsed / 1_000
+        )
+        .unwrap();
+        fs::write("bench_output.txt", report).unwrap();
+    }
+
+    #[test]
+    fn undo_removes_a_typed_run_as_one_unit() {
+        let mut buf = TextBuffer::new();
+        buf.paste("base");
+        for c in "word".chars() {
+            buf.insert_char(c);
+        }
+        assert_eq!(buf.lines, vec!["baseword"]);
+        buf.undo();
+        assert_eq!(buf.lines, vec!["base"]);
+        assert_eq!((buf.cursor_line, buf.cursor_col), (0, 4));
+    }
+
+    #[test]
+    fn insert_into_a_multibyte_line_lands_on_the_char_column() {
+        let mut buf = TextBuffer::new();
+        buf.paste("héllo");
+        buf.set_cursor(0, 2);
+        buf.insert_char('x');
+        assert_eq!(buf.lines, vec!["héxllo"]);
+        assert_eq!(buf.cursor_col, 3);
+    }
+
+    #[test]
+    fn multiline_insert_splices_into_the_middle_of_a_line() {
+        let mut buf = TextBuffer::new();
+        buf.paste("hello world");
+        buf.set_cursor(0, 5);
+        buf.paste("a\nb\nc");
+        assert_eq!(buf.lines, vec!["helloa", "b", "c world"]);
+        // The cursor lands at the end of the inserted text.
+        assert_eq!((buf.cursor_line, buf.cursor_col), (2, 1));
+    }
+
+    #[test]
+    fn paste_bypasses_auto_indent_and_auto_pairs() {
+        let mut buf = TextBuffer::new();
+        buf.auto_indent = true;
+        buf.auto_pairs = true;
+        buf.paste("    if (x) {\n\tdone");
+        assert_eq!(buf.lines, vec!["    if (x) {", "\tdone"]);
+    }
+
+    #[test]
+    fn paste_reindent_aligns_a_block_to_the_cursor_line() {
+        let mut buf = TextBuffer::new();
+        buf.paste("        body");
+        buf.set_cursor(0, 0);
+        buf.paste_reindent("if a {\n    b();\n}\n");
+        assert_eq!(
+            buf.lines,
+            vec![
+                "        if a {",
+                "            b();",
+                "        }",
+                "        body"
+            ]
+        );
+    }
+
+    #[test]
+    fn paste_reindent_mid_line_leaves_the_first_line_alone() {
+        let mut buf = TextBuffer::new();
+        buf.paste("    head");
+        buf.set_cursor(0, 8);
+        buf.paste_reindent("a {\n        b\n}");
+        // The first pasted line continues the cursor line; the rest shift
+        // by the same amount it did (indent 0 -> 4).
+        assert_eq!(buf.lines, vec!["    heada {", "            b", "    }"]);
+    }
+
+    #[test]
+    fn reindent_lines_shifts_a_block_preserving_relative_indent() {
+        let mut buf = TextBuffer::new();
+        buf.paste("    a\n        b\nc");
+        buf.reindent_lines(0, 2, 0);
+        // `c` was already left of the block's base and cannot go negative.
+        assert_eq!(buf.lines, vec!["a", "    b", "c"]);
+        buf.undo();
+        assert_eq!(buf.lines, vec!["    a", "        b", "c"]);
+    }
+
+    #[test]
+    fn undo_group_collapses_a_macro_run() {
+        let mut buf = TextBuffer::new();
+        buf.paste("a\nb\nc");
+        // "Insert X, move down" replayed over three lines.
+        let mark = buf.start_undo_group();
+        for line in 0..3 {
+            buf.set_cursor(line, 0);
+            buf.insert_char('X');
+        }
+        buf.end_undo_group(mark);
+        assert_eq!(buf.lines, vec!["Xa", "Xb", "Xc"]);
+        buf.undo();
+        assert_eq!(buf.lines, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn max_undo_discards_the_oldest_edits() {
+        let mut buf = TextBuffer::new();
+        buf.set_max_undo(2);
+        buf.paste("a");
+        buf.paste("b");
+        buf.paste("c");
+        assert_eq!(buf.lines, vec!["abc"]);
+        // Only the two newest records survive; undoing past them is a no-op.
+        buf.undo();
+        buf.undo();
+        buf.undo();
+        assert_eq!(buf.lines, vec!["a"]);
+    }
+
+    #[test]
+    fn undo_redo_round_trips_newline() {
+        let mut buf = TextBuffer::new();
+        buf.paste("hello");
+        buf.set_cursor(0, 2);
+        buf.insert_newline();
+        buf.undo();
+        assert_eq!(buf.lines, vec!["hello"]);
+        assert_eq!((buf.cursor_line, buf.cursor_col), (0, 2));
+        buf.redo();
+        assert_eq!(buf.lines, vec!["he", "llo"]);
+        assert_eq!((buf.cursor_line, buf.cursor_col), (1, 0));
+    }
+
+    #[test]
+    fn undo_restores_backspaced_text() {
+        let mut buf = TextBuffer::new();
+        buf.paste("ab\ncd");
+        buf.set_cursor(1, 0);
+        buf.delete_char_before_cursor();
+        assert_eq!(buf.lines, vec!["abcd"]);
+        buf.undo();
+        assert_eq!(buf.lines, vec!["ab", "cd"]);
+        assert_eq!((buf.cursor_line, buf.cursor_col), (1, 0));
+    }
+
+    #[test]
+    fn undo_redo_round_trips_multiline_paste() {
+        let mut buf = TextBuffer::new();
+        buf.paste("start");
+        buf.set_cursor(0, 5);
+        buf.paste("\none\ntwo");
+        assert_eq!(buf.lines, vec!["start", "one", "two"]);
+        buf.undo();
+        assert_eq!(buf.lines, vec!["start"]);
+        buf.redo();
+        assert_eq!(buf.lines, vec!["start", "one", "two"]);
+        assert_eq!((buf.cursor_line, buf.cursor_col), (2, 3));
+    }
+
+    #[test]
+    fn undo_restores_cut_line() {
+        let mut buf = TextBuffer::new();
+        buf.paste("one\ntwo\nthree");
+        buf.set_cursor(1, 0);
+        buf.cut_lines();
+        assert_eq!(buf.lines, vec!["one", "three"]);
+        buf.undo();
+        assert_eq!(buf.lines, vec!["one", "two", "three"]);
+    }
+
+    #[test]
+    fn new_edit_clears_redo() {
+        let mut buf = TextBuffer::new();
+        buf.insert_char('a');
+        buf.undo();
+        buf.insert_char('b');
+        buf.redo();
+        assert_eq!(buf.lines, vec!["b"]);
+    }
+
+    #[test]
+    fn newline_copies_leading_whitespace() {
+        let mut buf = TextBuffer::new();
+        buf.paste("    foo");
+        buf.insert_newline();
+        assert_eq!(buf.lines, vec!["    foo", "    "]);
+        assert_eq!((buf.cursor_line, buf.cursor_col), (1, 4));
+        buf.undo();
+        assert_eq!(buf.lines, vec!["    foo"]);
+    }
+
+    #[test]
+    fn newline_inside_indentation_keeps_what_was_typed() {
+        let mut buf = TextBuffer::new();
+        buf.paste("    foo");
+        buf.set_cursor(0, 2);
+        buf.insert_newline();
+        // Only the two spaces left of the cursor carry over.
+        assert_eq!(buf.lines, vec!["  ", "    foo"]);
+        assert_eq!((buf.cursor_line, buf.cursor_col), (1, 2));
+    }
+
+    #[test]
+    fn auto_indent_can_be_disabled() {
+        let mut buf = TextBuffer::new();
+        buf.auto_indent = false;
+        buf.paste("    foo");
+        buf.insert_newline();
+        assert_eq!(buf.lines, vec!["    foo", ""]);
+        assert_eq!((buf.cursor_line, buf.cursor_col), (1, 0));
+    }
+
+    #[test]
+    fn join_collapses_boundary_whitespace() {
+        let mut buf = TextBuffer::new();
+        buf.paste("foo \n  bar");
+        buf.set_cursor(0, 0);
+        buf.join_line_below();
+        assert_eq!(buf.lines, vec!["foo bar"]);
+        assert_eq!((buf.cursor_line, buf.cursor_col), (0, 4));
+        buf.undo();
+        assert_eq!(buf.lines, vec!["foo ", "  bar"]);
+    }
+
+    #[test]
+    fn join_inserts_a_space_when_needed() {
+        let mut buf = TextBuffer::new();
+        buf.paste("foo\nbar");
+        buf.set_cursor(0, 0);
+        buf.join_line_below();
+        assert_eq!(buf.lines, vec!["foo bar"]);
+        buf.undo();
+        assert_eq!(buf.lines, vec!["foo", "bar"]);
+    }
+
+    #[test]
+    fn join_on_the_last_line_does_nothing() {
+        let mut buf = TextBuffer::new();
+        buf.paste("only");
+        buf.join_line_below();
+        assert_eq!(buf.lines, vec!["only"]);
+    }
+
+    #[test]
+    fn stats_count_graphemes_not_bytes() {
+        let mut buf = TextBuffer::new();
+        buf.paste("h\u{e9}llo world\nsecond line");
+        let stats = buf.stats();
+        assert_eq!(stats.lines, 2);
+        assert_eq!(stats.words, 4);
+        // The accented e is one char but two bytes.
+        assert_eq!(stats.chars, 22);
+        assert_eq!(stats.bytes, 23);
+    }
+
+    #[test]
+    fn selection_stats_cover_only_the_selected_text() {
+        let stats = BufferStats::of_text("one two");
+        assert_eq!(stats.lines, 1);
+        assert_eq!(stats.words, 2);
+        assert_eq!(stats.chars, 7);
+    }
+
+    #[test]
+    fn home_toggles_between_indent_and_column_zero() {
+        let mut buf = TextBuffer::new();
+        buf.paste("    foo");
+        buf.move_line_start_smart();
+        assert_eq!(buf.cursor_col, 4);
+        buf.move_line_start_smart();
+        assert_eq!(buf.cursor_col, 0);
+        buf.move_line_start_smart();
+        assert_eq!(buf.cursor_col, 4);
+    }
+
+    #[test]
+    fn smart_home_on_blank_only_lines_goes_to_zero() {
+        let mut buf = TextBuffer::new();
+        buf.paste("   ");
+        // The whole line is whitespace: its end is the "first non-blank".
+        buf.move_line_start_smart();
+        assert_eq!(buf.cursor_col, 0);
+    }
+
+    #[test]
+    fn expanding_tabs_rewrites_them_as_spaces() {
+        let mut buf = TextBuffer::new();
+        buf.paste("\tx\na\tb");
+        buf.set_cursor(0, 1);
+        buf.expand_tabs(4);
+        assert_eq!(buf.lines, vec!["    x", "a   b"]);
+        // Cursor stays on the `x`, visual column 4.
+        assert_eq!(buf.cursor_col, 4);
+        buf.undo();
+        assert_eq!(buf.lines, vec!["\tx", "a\tb"]);
+    }
+
+    #[test]
+    fn unexpanding_converts_only_leading_spaces() {
+        let mut buf = TextBuffer::new();
+        buf.paste("        a  b\n  c");
+        buf.unexpand_leading_tabs(4);
+        // Two full stops become tabs; inner spacing is untouched, and the
+        // two-space line is too short to convert.
+        assert_eq!(buf.lines, vec!["\t\ta  b", "  c"]);
+    }
+
+    #[test]
+    fn indent_style_controls_the_tab_key() {
+        let mut buf = TextBuffer::new();
+        buf.paste("ab");
+        buf.set_cursor(0, 1);
+        buf.insert_indent(4);
+        assert_eq!(buf.lines, vec!["a\tb"]);
+        buf.undo();
+        buf.indent_style = IndentStyle::Spaces;
+        buf.set_cursor(0, 1);
+        buf.insert_indent(4);
+        // Three spaces reach the next tab stop from column 1.
+        assert_eq!(buf.lines, vec!["a   b"]);
+        assert_eq!(buf.cursor_col, 4);
+    }
+
+    #[test]
+    fn mixed_block_gets_fully_commented_first() {
+        let mut buf = TextBuffer::new();
+        buf.paste("    foo\n    // bar\n    baz");
+        buf.toggle_comment("//", 0, 2);
+        assert_eq!(buf.lines, vec!["    // foo", "    // // bar", "    // baz"]);
+        // Now everything is commented, so toggling again uncomments.
+        buf.toggle_comment("//", 0, 2);
+        assert_eq!(buf.lines, vec!["    foo", "    // bar", "    baz"]);
+        buf.undo();
+        buf.undo();
+        assert_eq!(buf.lines, vec!["    foo", "    // bar", "    baz"]);
+    }
+
+    #[test]
+    fn uncomment_handles_prefixes_without_a_space() {
+        let mut buf = TextBuffer::new();
+        buf.paste("//foo\n// bar");
+        buf.toggle_comment("//", 0, 1);
+        assert_eq!(buf.lines, vec!["foo", "bar"]);
+    }
+
+    #[test]
+    fn openers_bring_their_closer_along() {
+        let mut buf = TextBuffer::new();
+        buf.insert_char_smart('(');
+        assert_eq!(buf.lines, vec!["()"]);
+        assert_eq!(buf.cursor_col, 1);
+        buf.undo();
+        assert_eq!(buf.lines, vec![""]);
+    }
+
+    #[test]
+    fn typing_the_closer_steps_over_it() {
+        let mut buf = TextBuffer::new();
+        buf.insert_char_smart('(');
+        buf.insert_char_smart(')');
+        assert_eq!(buf.lines, vec!["()"]);
+        assert_eq!(buf.cursor_col, 2);
+    }
+
+    #[test]
+    fn typing_an_opener_wraps_the_selection() {
+        let mut buf = TextBuffer::new();
+        buf.paste("abc");
+        buf.select_all();
+        buf.insert_char_smart('[');
+        assert_eq!(buf.lines, vec!["[abc]"]);
+        // The wrapped text stays selected inside the pair.
+        assert_eq!(buf.get_selection(), Some(((0, 1), (0, 4))));
+        buf.undo();
+        assert_eq!(buf.lines, vec!["abc"]);
+    }
+
+    #[test]
+    fn auto_pairs_off_inserts_plainly() {
+        let mut buf = TextBuffer::new();
+        buf.auto_pairs = false;
+        buf.insert_char_smart('(');
+        assert_eq!(buf.lines, vec!["("]);
+    }
+
+    #[test]
+    fn brackets_match_through_nesting() {
+        let mut buf = TextBuffer::new();
+        buf.paste("([{}])");
+        assert_eq!(buf.matching_bracket((0, 0)), Some((0, 5)));
+        assert_eq!(buf.matching_bracket((0, 5)), Some((0, 0)));
+        assert_eq!(buf.matching_bracket((0, 2)), Some((0, 3)));
+        assert_eq!(buf.matching_bracket((0, 1)), Some((0, 4)));
+    }
+
+    #[test]
+    fn brackets_match_across_lines() {
+        let mut buf = TextBuffer::new();
+        buf.paste("fn main() {\n    (a);\n}");
+        assert_eq!(buf.matching_bracket((0, 10)), Some((2, 0)));
+        assert_eq!(buf.matching_bracket((2, 0)), Some((0, 10)));
+    }
+
+    #[test]
+    fn unbalanced_or_non_brackets_return_none() {
+        let mut buf = TextBuffer::new();
+        buf.paste("((a)\nx");
+        assert_eq!(buf.matching_bracket((0, 0)), None);
+        assert_eq!(buf.matching_bracket((1, 0)), None);
+        assert_eq!(buf.matching_bracket((0, 9)), None);
+    }
+
+    #[test]
+    fn move_line_up_swaps_with_the_line_above() {
+        let mut buf = TextBuffer::new();
+        buf.paste("one\ntwo\nthree");
+        buf.set_cursor(1, 2);
+        buf.move_line_up();
+        assert_eq!(buf.lines, vec!["two", "one", "three"]);
+        assert_eq!((buf.cursor_line, buf.cursor_col), (0, 2));
+        buf.undo();
+        assert_eq!(buf.lines, vec!["one", "two", "three"]);
+        assert_eq!((buf.cursor_line, buf.cursor_col), (1, 2));
+    }
+
+    #[test]
+    fn move_line_down_swaps_with_the_line_below() {
+        let mut buf = TextBuffer::new();
+        buf.paste("one\ntwo");
+        buf.set_cursor(0, 0);
+        buf.move_line_down();
+        assert_eq!(buf.lines, vec!["two", "one"]);
+        assert_eq!(buf.cursor_line, 1);
+        buf.undo();
+        buf.redo();
+        assert_eq!(buf.lines, vec!["two", "one"]);
+    }
+
+    #[test]
+    fn line_moves_are_noops_at_the_edges() {
+        let mut buf = TextBuffer::new();
+        buf.paste("one\ntwo");
+        buf.set_cursor(0, 0);
+        buf.move_line_up();
+        assert_eq!(buf.lines, vec!["one", "two"]);
+        buf.set_cursor(1, 0);
+        buf.move_line_down();
+        assert_eq!(buf.lines, vec!["one", "two"]);
+    }
+
+    #[test]
+    fn duplicate_line_is_one_undo_step() {
+        let mut buf = TextBuffer::new();
+        buf.paste("abc\ndef");
+        buf.set_cursor(0, 1);
+        buf.duplicate_line();
+        assert_eq!(buf.lines, vec!["abc", "abc", "def"]);
+        assert_eq!((buf.cursor_line, buf.cursor_col), (1, 1));
+        buf.undo();
+        assert_eq!(buf.lines, vec!["abc", "def"]);
+    }
+
+    #[test]
+    fn delete_word_left_removes_the_previous_word() {
+        let mut buf = TextBuffer::new();
+        buf.paste("hello world");
+        buf.delete_word_before_cursor();
+        assert_eq!(buf.lines, vec!["hello "]);
+        buf.delete_word_before_cursor();
+        assert_eq!(buf.lines, vec!["hello"]);
+        buf.undo();
+        buf.undo();
+        assert_eq!(buf.lines, vec!["hello world"]);
+    }
+
+    #[test]
+    fn delete_word_left_inside_whitespace_eats_the_run() {
+        let mut buf = TextBuffer::new();
+        buf.paste("a    b");
+        buf.set_cursor(0, 5);
+        buf.delete_word_before_cursor();
+        assert_eq!(buf.lines, vec!["ab"]);
+        assert_eq!(buf.cursor_col, 1);
+    }
+
+    #[test]
+    fn delete_word_left_at_line_start_merges_lines() {
+        let mut buf = TextBuffer::new();
+        buf.paste("one\ntwo");
+        buf.set_cursor(1, 0);
+        buf.delete_word_before_cursor();
+        assert_eq!(buf.lines, vec!["onetwo"]);
+        assert_eq!((buf.cursor_line, buf.cursor_col), (0, 3));
+    }
+
+    #[test]
+    fn delete_word_right_stops_at_boundaries() {
+        let mut buf = TextBuffer::new();
+        buf.paste("foo_bar(baz)");
+        buf.set_cursor(0, 0);
+        buf.delete_word_after_cursor();
+        assert_eq!(buf.lines, vec!["(baz)"]);
+        buf.delete_word_after_cursor();
+        assert_eq!(buf.lines, vec!["baz)"]);
+    }
+
+    #[test]
+    fn delete_word_right_at_line_end_merges_lines() {
+        let mut buf = TextBuffer::new();
+        buf.paste("one\ntwo");
+        buf.set_cursor(0, 3);
+        buf.delete_word_after_cursor();
+        assert_eq!(buf.lines, vec!["onetwo"]);
+    }
+
+    #[test]
+    fn cursor_crosses_family_emoji_as_one_step() {
+        let mut buf = TextBuffer::new();
+        buf.paste("a\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}b");
+        buf.set_cursor(0, 0);
+        buf.move_right();
+        assert_eq!(buf.cursor_col, 1);
+        buf.move_right();
+        // Past the whole 5-char ZWJ sequence in one step.
+        assert_eq!(buf.cursor_col, 6);
+        buf.move_left();
+        assert_eq!(buf.cursor_col, 1);
+    }
+
+    #[test]
+    fn backspace_removes_a_whole_cluster() {
+        let mut buf = TextBuffer::new();
+        buf.paste("a\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}b");
+        buf.set_cursor(0, 6);
+        buf.delete_char_before_cursor();
+        assert_eq!(buf.lines, vec!["ab"]);
+        assert_eq!(buf.cursor_col, 1);
+        buf.undo();
+        assert_eq!(
+            buf.lines,
+            vec!["a\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}b"]
+        );
+    }
+
+    #[test]
+    fn delete_removes_combining_sequence() {
+        let mut buf = TextBuffer::new();
+        // 'e' followed by a combining acute accent.
+        buf.paste("xe\u{0301}y");
+        buf.set_cursor(0, 1);
+        buf.delete_char_at_cursor();
+        assert_eq!(buf.lines, vec!["xy"]);
+    }
+
+    #[test]
+    fn multibyte_insert_keeps_char_boundaries() {
+        let mut buf = TextBuffer::new();
+        buf.insert_char('é');
+        buf.insert_char('x');
+        buf.set_cursor(0, 1);
+        buf.insert_char('ö');
+        assert_eq!(buf.lines, vec!["éöx"]);
+    }
+}
@@ -0,0 +1,92 @@
+/// The editor clipboard. With the `system-clipboard` feature enabled it
+/// mirrors every copy to the OS clipboard and prefers OS contents on paste,
+/// so text moves between the editor and other applications. Without the
+/// feature — or when no display is available — it falls back to an internal
+/// buffer, so headless builds and SSH sessions keep working.
+pub struct Clipboard {
+    fallback: String,
+    #[cfg(feature = "system-clipboard")]
+    system: Option<arboard::Clipboard>,
+}
+
+#[cfg(any(test, feature = "system-clipboard"))]
+/// Join lines with the buffer's own ending for other applications, which on
+/// Windows expect CRLF.
+fn to_system(text: &str, line_ending: &str) -> String {
+    if line_ending == "\n" {
+        text.to_string()
+    } else {
+        text.replace('\n', line_ending)
+    }
+}
+
+#[cfg(any(test, feature = "system-clipboard"))]
+/// Re-split pasted text on `\n` regardless of where it came from: strip the
+/// `\r` of CRLF pairs so they don't end up inside buffer lines.
+fn from_system(text: &str) -> String {
+    text.replace("\r\n", "\n")
+}
+
+impl Clipboard {
+    pub fn new() -> Self {
+        Clipboard {
+            fallback: String::new(),
+            #[cfg(feature = "system-clipboard")]
+            system: arboard::Clipboard::new().ok(),
+        }
+    }
+
+    /// Store `text` (with `\n` separators), mirroring it to the OS clipboard
+    /// using `line_ending` when one is connected.
+    pub fn set(&mut self, text: String, line_ending: &str) {
+        #[cfg(feature = "system-clipboard")]
+        if let Some(system) = &mut self.system {
+            // Best effort: a clipboard that went away mid-session shouldn't
+            // break in-editor copy/paste.
+            let _ = system.set_text(to_system(&text, line_ending));
+        }
+        #[cfg(not(feature = "system-clipboard"))]
+        let _ = line_ending;
+        self.fallback = text;
+    }
+
+    /// The current clipboard contents with `\n` separators. Prefers the OS
+    /// clipboard so text copied in another application can be pasted here.
+    pub fn get(&mut self) -> String {
+        #[cfg(feature = "system-clipboard")]
+        if let Some(system) = &mut self.system {
+            if let Ok(text) = system.get_text() {
+                return from_system(&text);
+            }
+        }
+        self.fallback.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crlf_buffers_copy_with_crlf_separators() {
+        assert_eq!(to_system("one\ntwo\n", "\r\n"), "one\r\ntwo\r\n");
+        assert_eq!(to_system("one\ntwo\n", "\n"), "one\ntwo\n");
+    }
+
+    #[test]
+    fn pasted_text_is_resplit_on_plain_newlines() {
+        assert_eq!(from_system("one\r\ntwo"), "one\ntwo");
+        assert_eq!(from_system("one\ntwo"), "one\ntwo");
+    }
+
+    #[test]
+    fn fallback_round_trips_without_a_display() {
+        let mut clip = Clipboard {
+            fallback: String::new(),
+            #[cfg(feature = "system-clipboard")]
+            system: None,
+        };
+        clip.set("a\nb".to_string(), "\r\n");
+        assert_eq!(clip.get(), "a\nb");
+    }
+}
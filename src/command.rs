@@ -0,0 +1,374 @@
+use crate::buffer::IndentStyle;
+use crate::printer::NumberMode;
+
+/// Commands entered on the status line, in the `:w` / `:q` tradition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Write,
+    Quit,
+    /// Quit without the unsaved-changes check.
+    ForceQuit,
+    WriteQuit,
+    /// 1-based line number.
+    Goto(usize),
+    /// Open a file in a new buffer (or switch to it).
+    Edit(String),
+    /// Re-read the active buffer's file, discarding unsaved changes.
+    Reload,
+    BufferNext,
+    BufferPrev,
+    /// List the open buffers on the status line.
+    Buffers,
+    /// Split the screen horizontally into two panes.
+    Split,
+    /// Close the split, keeping only the focused pane.
+    CloseSplit,
+    SetTabWidth(usize),
+    SetLineNumbers(bool),
+    /// Absolute, relative or hybrid gutter numbering (implies numbers on).
+    SetNumberMode(NumberMode),
+    SetAutoIndent(bool),
+    /// Show spaces and tabs as visible markers.
+    SetShowWhitespace(bool),
+    /// Flag trailing whitespace even when markers are off.
+    SetTrailingWhitespace(bool),
+    /// Visual columns to draw a vertical guide at; empty clears them.
+    SetRulers(Vec<usize>),
+    /// Rows of context to keep between the cursor and the viewport edges.
+    SetScrollOff(usize),
+    /// Soft-wrap long lines instead of scrolling horizontally.
+    SetWrap(bool),
+    /// App-wide read-only mode: navigation and search work, edits flash a
+    /// message. `None` toggles the current state.
+    SetReadOnly(Option<bool>),
+    /// Scroll so the cursor's line sits in the middle of its pane.
+    Center,
+    SetIndentStyle(IndentStyle),
+    /// Rewrite every tab as spaces.
+    ExpandTabs,
+    /// Rewrite leading spaces as tabs.
+    UnexpandTabs,
+    /// Report line/word/char/byte counts on the status line.
+    Stats,
+    /// Upper-case the selection or the word under the cursor.
+    Upcase,
+    /// Lower-case the selection or the word under the cursor.
+    Downcase,
+    /// Flip the case of the selection or the word under the cursor.
+    ToggleCase,
+    /// Sort the selected lines, or the whole buffer without a selection.
+    Sort {
+        descending: bool,
+        case_insensitive: bool,
+    },
+    /// Remove duplicate lines in the selection or the whole buffer;
+    /// consecutive runs only unless `all` is set.
+    Dedup {
+        all: bool,
+        trim: bool,
+    },
+    /// Drop a named mark on the cursor (`m{char}`).
+    SetMark(char),
+    /// Jump to a named mark (`` `{char} ``).
+    GotoMark(char),
+    /// Write the buffer as highlighted HTML to the given path.
+    ExportHtml(String),
+    /// Show the key-binding help overlay.
+    Help,
+}
+
+impl Command {
+    /// Whether running this command rewrites buffer text, so a read-only
+    /// buffer can reject it up front.
+    pub fn edits(&self) -> bool {
+        matches!(
+            self,
+            Command::ExpandTabs
+                | Command::UnexpandTabs
+                | Command::Upcase
+                | Command::Downcase
+                | Command::ToggleCase
+                | Command::Sort { .. }
+                | Command::Dedup { .. }
+        )
+    }
+}
+
+/// Parse one command line, e.g. `w`, `wq`, `goto 42` or `set tabwidth 2`.
+/// Errors are user-facing status-line messages.
+pub fn parse(input: &str) -> Result<Command, String> {
+    let mut words = input.split_whitespace();
+    let Some(head) = words.next() else {
+        return Err("empty command".to_string());
+    };
+    let command = match head {
+        "w" | "write" => Command::Write,
+        "q" | "quit" => Command::Quit,
+        "q!" => Command::ForceQuit,
+        "wq" | "x" => Command::WriteQuit,
+        "goto" => {
+            let line = words
+                .next()
+                .and_then(|w| w.parse::<usize>().ok())
+                .filter(|&n| n > 0)
+                .ok_or("usage: goto <line>")?;
+            Command::Goto(line)
+        }
+        "e" | "edit" => {
+            let path = words.next().ok_or("usage: e <path>")?;
+            Command::Edit(path.to_string())
+        }
+        "e!" | "reload" => Command::Reload,
+        "bn" => Command::BufferNext,
+        "bp" => Command::BufferPrev,
+        "ls" | "buffers" => Command::Buffers,
+        "sp" | "split" => Command::Split,
+        "only" => Command::CloseSplit,
+        "center" => Command::Center,
+        "set" => parse_set(words.next(), words.next())?,
+        "expandtabs" => Command::ExpandTabs,
+        "unexpandtabs" => Command::UnexpandTabs,
+        "stats" | "wc" => Command::Stats,
+        "help" => Command::Help,
+        "upcase" => Command::Upcase,
+        "downcase" => Command::Downcase,
+        "togglecase" => Command::ToggleCase,
+        "sort" => {
+            let mut descending = false;
+            let mut case_insensitive = false;
+            for flag in words.by_ref() {
+                match flag {
+                    "desc" => descending = true,
+                    "nocase" => case_insensitive = true,
+                    _ => return Err("usage: sort [desc] [nocase]".to_string()),
+                }
+            }
+            Command::Sort {
+                descending,
+                case_insensitive,
+            }
+        }
+        "dedup" => {
+            let mut all = false;
+            let mut trim = false;
+            for flag in words.by_ref() {
+                match flag {
+                    "all" => all = true,
+                    "trim" => trim = true,
+                    _ => return Err("usage: dedup [all] [trim]".to_string()),
+                }
+            }
+            Command::Dedup { all, trim }
+        }
+        "export" => match (words.next(), words.next()) {
+            (Some("html"), Some(path)) => Command::ExportHtml(path.to_string()),
+            _ => return Err("usage: export html <path>".to_string()),
+        },
+        mark if mark.chars().count() == 2 && mark.starts_with('m') => {
+            Command::SetMark(mark.chars().nth(1).expect("length checked"))
+        }
+        mark if mark.chars().count() == 2 && mark.starts_with('`') => {
+            Command::GotoMark(mark.chars().nth(1).expect("length checked"))
+        }
+        other => return Err(format!("unknown command: {other}")),
+    };
+    if words.next().is_some() {
+        return Err(format!("trailing input after {head}"));
+    }
+    Ok(command)
+}
+
+fn parse_set(option: Option<&str>, value: Option<&str>) -> Result<Command, String> {
+    match option {
+        Some("tabwidth") => {
+            let width = value
+                .and_then(|v| v.parse::<usize>().ok())
+                .filter(|&w| w > 0)
+                .ok_or("usage: set tabwidth <columns>")?;
+            Ok(Command::SetTabWidth(width))
+        }
+        Some("numbers") => match value {
+            Some("absolute") => Ok(Command::SetNumberMode(NumberMode::Absolute)),
+            Some("relative") => Ok(Command::SetNumberMode(NumberMode::Relative)),
+            Some("hybrid") => Ok(Command::SetNumberMode(NumberMode::Hybrid)),
+            _ => Ok(Command::SetLineNumbers(parse_switch(value).map_err(
+                |_| "expected on, off, absolute, relative or hybrid".to_string(),
+            )?)),
+        },
+        Some("indent") => match value {
+            Some("tabs") => Ok(Command::SetIndentStyle(IndentStyle::Tabs)),
+            Some("spaces") => Ok(Command::SetIndentStyle(IndentStyle::Spaces)),
+            _ => Err("expected tabs or spaces".to_string()),
+        },
+        Some("autoindent") => Ok(Command::SetAutoIndent(parse_switch(value)?)),
+        Some("scrolloff") => {
+            let rows = value
+                .and_then(|v| v.parse::<usize>().ok())
+                .ok_or("usage: set scrolloff <rows>")?;
+            Ok(Command::SetScrollOff(rows))
+        }
+        Some("wrap") => Ok(Command::SetWrap(parse_switch(value)?)),
+        // A bare `set readonly` toggles, so the same command turns it off.
+        Some("readonly") => match value {
+            None => Ok(Command::SetReadOnly(None)),
+            Some(_) => Ok(Command::SetReadOnly(Some(parse_switch(value)?))),
+        },
+        Some("whitespace") => Ok(Command::SetShowWhitespace(parse_switch(value)?)),
+        Some("trailing") => Ok(Command::SetTrailingWhitespace(parse_switch(value)?)),
+        Some("rulers") => match value {
+            Some("off") | Some("none") => Ok(Command::SetRulers(Vec::new())),
+            Some(list) => list
+                .split(',')
+                .map(|col| col.trim().parse::<usize>())
+                .collect::<Result<Vec<_>, _>>()
+                .map(Command::SetRulers)
+                .map_err(|_| "usage: set rulers <col>[,<col>] or off".to_string()),
+            None => Err("usage: set rulers <col>[,<col>] or off".to_string()),
+        },
+        Some(other) => Err(format!("unknown option: {other}")),
+        None => Err("usage: set <option> <value>".to_string()),
+    }
+}
+
+fn parse_switch(value: Option<&str>) -> Result<bool, String> {
+    match value {
+        Some("on") | Some("true") => Ok(true),
+        Some("off") | Some("false") => Ok(false),
+        _ => Err("expected on or off".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_and_quit_forms_parse() {
+        assert_eq!(parse("w"), Ok(Command::Write));
+        assert_eq!(parse("write"), Ok(Command::Write));
+        assert_eq!(parse("q"), Ok(Command::Quit));
+        assert_eq!(parse("q!"), Ok(Command::ForceQuit));
+        assert_eq!(parse("wq"), Ok(Command::WriteQuit));
+        assert_eq!(parse("x"), Ok(Command::WriteQuit));
+    }
+
+    #[test]
+    fn goto_takes_a_positive_line_number() {
+        assert_eq!(parse("goto 42"), Ok(Command::Goto(42)));
+        assert!(parse("goto").is_err());
+        assert!(parse("goto 0").is_err());
+        assert!(parse("goto forty").is_err());
+    }
+
+    #[test]
+    fn buffer_commands_parse() {
+        assert_eq!(parse("e foo.rs"), Ok(Command::Edit("foo.rs".to_string())));
+        assert!(parse("e").is_err());
+        assert_eq!(parse("reload"), Ok(Command::Reload));
+        assert_eq!(parse("e!"), Ok(Command::Reload));
+        assert_eq!(parse("bn"), Ok(Command::BufferNext));
+        assert_eq!(parse("bp"), Ok(Command::BufferPrev));
+        assert_eq!(parse("ls"), Ok(Command::Buffers));
+    }
+
+    #[test]
+    fn split_commands_parse() {
+        assert_eq!(parse("sp"), Ok(Command::Split));
+        assert_eq!(parse("split"), Ok(Command::Split));
+        assert_eq!(parse("only"), Ok(Command::CloseSplit));
+    }
+
+    #[test]
+    fn set_forms_parse() {
+        assert_eq!(parse("set tabwidth 2"), Ok(Command::SetTabWidth(2)));
+        assert_eq!(parse("set numbers off"), Ok(Command::SetLineNumbers(false)));
+        assert_eq!(
+            parse("set numbers relative"),
+            Ok(Command::SetNumberMode(NumberMode::Relative))
+        );
+        assert_eq!(
+            parse("set numbers hybrid"),
+            Ok(Command::SetNumberMode(NumberMode::Hybrid))
+        );
+        assert_eq!(parse("set autoindent on"), Ok(Command::SetAutoIndent(true)));
+        assert_eq!(
+            parse("set whitespace on"),
+            Ok(Command::SetShowWhitespace(true))
+        );
+        assert_eq!(
+            parse("set trailing off"),
+            Ok(Command::SetTrailingWhitespace(false))
+        );
+        assert_eq!(
+            parse("set rulers 80,120"),
+            Ok(Command::SetRulers(vec![80, 120]))
+        );
+        assert_eq!(parse("set rulers off"), Ok(Command::SetRulers(Vec::new())));
+        assert!(parse("set rulers eighty").is_err());
+        assert_eq!(
+            parse("set indent spaces"),
+            Ok(Command::SetIndentStyle(IndentStyle::Spaces))
+        );
+        assert_eq!(parse("set scrolloff 3"), Ok(Command::SetScrollOff(3)));
+        assert!(parse("set scrolloff three").is_err());
+        assert_eq!(parse("center"), Ok(Command::Center));
+        assert_eq!(parse("expandtabs"), Ok(Command::ExpandTabs));
+        assert_eq!(parse("wc"), Ok(Command::Stats));
+        assert_eq!(parse("help"), Ok(Command::Help));
+        assert_eq!(
+            parse("sort desc nocase"),
+            Ok(Command::Sort {
+                descending: true,
+                case_insensitive: true
+            })
+        );
+        assert!(parse("sort backwards").is_err());
+        assert_eq!(
+            parse("dedup all"),
+            Ok(Command::Dedup {
+                all: true,
+                trim: false
+            })
+        );
+        assert_eq!(parse("set wrap on"), Ok(Command::SetWrap(true)));
+        assert_eq!(parse("set readonly"), Ok(Command::SetReadOnly(None)));
+        assert_eq!(
+            parse("set readonly off"),
+            Ok(Command::SetReadOnly(Some(false)))
+        );
+        assert!(parse("set tabwidth 0").is_err());
+        assert!(parse("set flashing on").is_err());
+    }
+
+    #[test]
+    fn marks_parse_as_vim_style_pairs() {
+        assert_eq!(parse("ma"), Ok(Command::SetMark('a')));
+        assert_eq!(parse("`a"), Ok(Command::GotoMark('a')));
+        assert!(parse("m").is_err());
+        assert!(parse("mark").is_err());
+    }
+
+    #[test]
+    fn case_commands_parse() {
+        assert_eq!(parse("upcase"), Ok(Command::Upcase));
+        assert_eq!(parse("downcase"), Ok(Command::Downcase));
+        assert_eq!(parse("togglecase"), Ok(Command::ToggleCase));
+    }
+
+    #[test]
+    fn export_takes_a_format_and_a_path() {
+        assert_eq!(
+            parse("export html out.html"),
+            Ok(Command::ExportHtml("out.html".to_string()))
+        );
+        assert!(parse("export html").is_err());
+        assert!(parse("export pdf out.pdf").is_err());
+    }
+
+    #[test]
+    fn junk_is_rejected_with_a_message() {
+        assert!(parse("").is_err());
+        assert!(parse("launch").unwrap_err().contains("unknown command"));
+        assert!(parse("w now").unwrap_err().contains("trailing"));
+    }
+}
@@ -0,0 +1,355 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::buffer::IndentStyle;
+use crate::printer::{CursorShape, NumberMode};
+
+/// Editor settings loaded once at startup. Every field has a default, so
+/// the editor works without any file; a `config.toml` next to the keymap
+/// overrides whichever settings it names.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EditorConfig {
+    pub tab_width: usize,
+    pub show_line_numbers: bool,
+    pub number_mode: NumberMode,
+    pub auto_indent: bool,
+    pub auto_pairs: bool,
+    pub indent_style: IndentStyle,
+    pub show_whitespace: bool,
+    /// Soft-wrap long lines instead of scrolling horizontally.
+    pub wrap: bool,
+    pub show_trailing_whitespace: bool,
+    /// Give the row the cursor is on a subtle background highlight.
+    pub cursor_line_highlight: bool,
+    /// Cursor shape while inserting; a bar by default.
+    pub insert_cursor: CursorShape,
+    /// Cursor shape while overwriting; a block by default.
+    pub overwrite_cursor: CursorShape,
+    pub rulers: Vec<usize>,
+    /// Rows of context scrolling keeps between the cursor and the
+    /// viewport's edges.
+    pub scroll_off: usize,
+    /// Undo records a buffer keeps before discarding the oldest.
+    pub max_undo: usize,
+    /// Line-comment prefix override; `None` falls back to the built-in
+    /// per-language table in [`syntax`](crate::syntax).
+    pub comment_prefix: Option<String>,
+    /// Remember cursor positions across runs via the session file.
+    pub save_session: bool,
+    /// Per-filetype overrides, keyed as in [`filetype_key`].
+    filetypes: HashMap<String, FiletypeConfig>,
+}
+
+/// The settings a `[filetype.<key>]` section may override.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+struct FiletypeConfig {
+    indent_style: Option<IndentStyle>,
+    tab_width: Option<usize>,
+    comment_prefix: Option<String>,
+}
+
+/// The filetype key for `path`: its extension (`rs`, `py`), or for
+/// extensionless files like `Makefile` the lowercased file name.
+fn filetype_key(path: &Path) -> Option<String> {
+    path.extension()
+        .or_else(|| path.file_name())
+        .and_then(|s| s.to_str())
+        .map(|s| s.to_ascii_lowercase())
+}
+
+impl Default for EditorConfig {
+    fn default() -> Self {
+        EditorConfig {
+            tab_width: 4,
+            show_line_numbers: true,
+            number_mode: NumberMode::Absolute,
+            auto_indent: true,
+            auto_pairs: true,
+            indent_style: IndentStyle::Tabs,
+            show_whitespace: false,
+            wrap: false,
+            show_trailing_whitespace: false,
+            cursor_line_highlight: false,
+            insert_cursor: CursorShape::Bar,
+            overwrite_cursor: CursorShape::Block,
+            rulers: Vec::new(),
+            scroll_off: 0,
+            max_undo: 1000,
+            comment_prefix: None,
+            save_session: true,
+            filetypes: HashMap::new(),
+        }
+    }
+}
+
+impl EditorConfig {
+    /// The defaults overridden by the user's config file, if one exists.
+    /// A missing file is not an error; problems inside the file come back
+    /// as warnings for the status line rather than stopping startup.
+    pub fn load() -> (Self, Vec<String>) {
+        let Some(path) = Self::config_path() else {
+            return (EditorConfig::default(), Vec::new());
+        };
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text),
+            Err(_) => (EditorConfig::default(), Vec::new()),
+        }
+    }
+
+    /// `$XDG_CONFIG_HOME/trust/config.toml`, falling back to
+    /// `~/.config/trust/config.toml`.
+    fn config_path() -> Option<PathBuf> {
+        let base = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))?;
+        Some(base.join("trust").join("config.toml"))
+    }
+
+    /// Parse `key = value` lines and `[filetype.<key>]` sections, the same
+    /// subset of TOML the keymap uses. Unknown keys and unusable values are
+    /// skipped with a warning, so a typo never takes the rest of the config
+    /// down with it.
+    pub fn parse(text: &str) -> (Self, Vec<String>) {
+        let mut config = EditorConfig::default();
+        let mut warnings = Vec::new();
+        // `None` is the top-level section; `Some(None)` an unknown section
+        // whose keys are skipped; `Some(Some(key))` a filetype block.
+        let mut section: Option<Option<String>> = None;
+        for (idx, raw) in text.lines().enumerate() {
+            let line = raw.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = match name.trim().strip_prefix("filetype.") {
+                    Some(key) if !key.is_empty() => Some(Some(key.to_ascii_lowercase())),
+                    _ => {
+                        warnings.push(format!(
+                            "config line {}: unknown section `[{name}]`",
+                            idx + 1
+                        ));
+                        Some(None)
+                    }
+                };
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                warnings.push(format!("config line {}: expected `key = value`", idx + 1));
+                continue;
+            };
+            let key = key.trim();
+            let value = value.trim().trim_matches('"');
+            let applied = match &section {
+                None => config.set(key, value),
+                Some(None) => Ok(()),
+                Some(Some(filetype)) => config
+                    .filetypes
+                    .entry(filetype.clone())
+                    .or_default()
+                    .set(key, value),
+            };
+            if let Err(problem) = applied {
+                warnings.push(format!("config line {}: {problem}", idx + 1));
+            }
+        }
+        (config, warnings)
+    }
+
+    /// The settings for a file at `path`: the base config with any matching
+    /// `[filetype.*]` overrides folded in.
+    pub fn for_path(&self, path: Option<&Path>) -> EditorConfig {
+        let mut resolved = self.clone();
+        let overrides = path
+            .and_then(filetype_key)
+            .and_then(|key| self.filetypes.get(&key));
+        if let Some(ft) = overrides {
+            if let Some(style) = ft.indent_style {
+                resolved.indent_style = style;
+            }
+            if let Some(width) = ft.tab_width {
+                resolved.tab_width = width;
+            }
+            if ft.comment_prefix.is_some() {
+                resolved.comment_prefix = ft.comment_prefix.clone();
+            }
+        }
+        resolved
+    }
+
+    /// Apply one `key = value` setting, or say what was wrong with it.
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "tab_width" => self.tab_width = parse_tab_width(value)?,
+            "line_numbers" => match value {
+                "absolute" => self.number_mode = NumberMode::Absolute,
+                "relative" => self.number_mode = NumberMode::Relative,
+                "hybrid" => self.number_mode = NumberMode::Hybrid,
+                _ => self.show_line_numbers = parse_bool(key, value)?,
+            },
+            "auto_indent" => self.auto_indent = parse_bool(key, value)?,
+            "auto_pairs" => self.auto_pairs = parse_bool(key, value)?,
+            "indent_style" => self.indent_style = parse_indent_style(value)?,
+            "comment_prefix" => self.comment_prefix = Some(value.to_string()),
+            "show_whitespace" => self.show_whitespace = parse_bool(key, value)?,
+            "save_session" => self.save_session = parse_bool(key, value)?,
+            "wrap" => self.wrap = parse_bool(key, value)?,
+            "show_trailing_whitespace" => {
+                self.show_trailing_whitespace = parse_bool(key, value)?;
+            }
+            "cursor_line_highlight" => {
+                self.cursor_line_highlight = parse_bool(key, value)?;
+            }
+            "insert_cursor" => self.insert_cursor = parse_cursor_shape(key, value)?,
+            "overwrite_cursor" => self.overwrite_cursor = parse_cursor_shape(key, value)?,
+            "max_undo" => {
+                self.max_undo = value
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .ok_or_else(|| format!("bad max_undo `{value}`"))?;
+            }
+            "scroll_off" => {
+                self.scroll_off = value
+                    .parse::<usize>()
+                    .map_err(|_| format!("bad scroll_off `{value}`"))?;
+            }
+            "rulers" => {
+                self.rulers = value
+                    .split(',')
+                    .map(|col| col.trim().parse::<usize>())
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| format!("bad rulers `{value}`"))?;
+            }
+            other => return Err(format!("unknown setting `{other}`")),
+        }
+        Ok(())
+    }
+}
+
+impl FiletypeConfig {
+    /// Apply one override inside a `[filetype.*]` section.
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "indent_style" => self.indent_style = Some(parse_indent_style(value)?),
+            "tab_width" => self.tab_width = Some(parse_tab_width(value)?),
+            "comment_prefix" => self.comment_prefix = Some(value.to_string()),
+            other => return Err(format!("unknown filetype setting `{other}`")),
+        }
+        Ok(())
+    }
+}
+
+fn parse_tab_width(value: &str) -> Result<usize, String> {
+    value
+        .parse::<usize>()
+        .ok()
+        .filter(|&w| w > 0)
+        .ok_or_else(|| format!("bad tab_width `{value}`"))
+}
+
+fn parse_indent_style(value: &str) -> Result<IndentStyle, String> {
+    match value {
+        "tabs" => Ok(IndentStyle::Tabs),
+        "spaces" => Ok(IndentStyle::Spaces),
+        _ => Err(format!("bad indent_style `{value}`")),
+    }
+}
+
+fn parse_cursor_shape(key: &str, value: &str) -> Result<CursorShape, String> {
+    match value {
+        "bar" => Ok(CursorShape::Bar),
+        "block" => Ok(CursorShape::Block),
+        "underline" => Ok(CursorShape::Underline),
+        "default" => Ok(CursorShape::Default),
+        _ => Err(format!("bad {key} `{value}`")),
+    }
+}
+
+fn parse_bool(key: &str, value: &str) -> Result<bool, String> {
+    match value {
+        "on" | "true" => Ok(true),
+        "off" | "false" => Ok(false),
+        _ => Err(format!("bad {key} `{value}`")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_input_yields_the_defaults() {
+        let (config, warnings) = EditorConfig::parse("");
+        assert_eq!(config, EditorConfig::default());
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn sample_config_overrides_the_named_settings() {
+        let text = "\
+# my settings
+tab_width = 2
+indent_style = \"spaces\"
+line_numbers = \"relative\"
+show_trailing_whitespace = true
+rulers = \"80,100\"
+insert_cursor = \"underline\"
+";
+        let (config, warnings) = EditorConfig::parse(text);
+        assert!(warnings.is_empty(), "{warnings:?}");
+        assert_eq!(config.tab_width, 2);
+        assert_eq!(config.indent_style, IndentStyle::Spaces);
+        assert_eq!(config.number_mode, NumberMode::Relative);
+        assert!(config.show_trailing_whitespace);
+        assert_eq!(config.rulers, vec![80, 100]);
+        assert_eq!(config.insert_cursor, CursorShape::Underline);
+        // Untouched settings keep their defaults.
+        assert!(config.auto_indent);
+        assert_eq!(config.overwrite_cursor, CursorShape::Block);
+    }
+
+    #[test]
+    fn filetype_sections_override_by_extension_or_name() {
+        let text = "\
+indent_style = \"tabs\"
+[filetype.rs]
+indent_style = \"spaces\"
+tab_width = 4
+[filetype.makefile]
+indent_style = \"tabs\"
+comment_prefix = \"#\"
+";
+        let (config, warnings) = EditorConfig::parse(text);
+        assert!(warnings.is_empty(), "{warnings:?}");
+        let rust = config.for_path(Some(Path::new("src/foo.rs")));
+        assert_eq!(rust.indent_style, IndentStyle::Spaces);
+        assert_eq!(rust.tab_width, 4);
+        let make = config.for_path(Some(Path::new("Makefile")));
+        assert_eq!(make.indent_style, IndentStyle::Tabs);
+        assert_eq!(make.comment_prefix.as_deref(), Some("#"));
+        // Files without an override see the base settings.
+        let plain = config.for_path(Some(Path::new("notes.txt")));
+        assert_eq!(plain.indent_style, IndentStyle::Tabs);
+        assert_eq!(plain.comment_prefix, None);
+    }
+
+    #[test]
+    fn unknown_keys_warn_but_do_not_fail() {
+        let (config, warnings) = EditorConfig::parse("theme = \"dark\"\ntab_width = 8\n");
+        assert_eq!(config.tab_width, 8);
+        assert_eq!(warnings.len(), 1);
+        assert!(
+            warnings[0].contains("unknown setting `theme`"),
+            "{warnings:?}"
+        );
+    }
+
+    #[test]
+    fn bad_values_warn_with_their_line_number() {
+        let (config, warnings) = EditorConfig::parse("tab_width = zero\n");
+        assert_eq!(config.tab_width, EditorConfig::default().tab_width);
+        assert!(warnings[0].contains("line 1"), "{warnings:?}");
+    }
+}
@@ -0,0 +1,300 @@
+//! Minimal [EditorConfig](https://editorconfig.org) support: walk up from
+//! the opened file, parse every `.editorconfig` on the way, and resolve the
+//! handful of properties the editor can honor. Globs cover the common
+//! forms — `*`, `**`, `?`, `[abc]` and `{a,b}` — which is what real-world
+//! files use; an unparseable pattern simply matches nothing.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+use crate::buffer::{IndentStyle, LineEnding, TextBuffer};
+use crate::config::EditorConfig;
+
+/// The properties resolved for one file. `None` fields were not mentioned
+/// by any matching section, so the editor's own settings stand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Settings {
+    pub indent_style: Option<IndentStyle>,
+    /// From `indent_size` or `tab_width`; EditorConfig splits them, the
+    /// editor has a single tab stop.
+    pub tab_width: Option<usize>,
+    pub end_of_line: Option<LineEnding>,
+    pub trim_trailing_whitespace: Option<bool>,
+    pub insert_final_newline: Option<bool>,
+}
+
+impl Settings {
+    /// Lay `over` on top: its set fields win, like a closer `.editorconfig`
+    /// overriding one further up the tree.
+    fn override_with(&mut self, over: Settings) {
+        self.indent_style = over.indent_style.or(self.indent_style);
+        self.tab_width = over.tab_width.or(self.tab_width);
+        self.end_of_line = over.end_of_line.or(self.end_of_line);
+        self.trim_trailing_whitespace = over
+            .trim_trailing_whitespace
+            .or(self.trim_trailing_whitespace);
+        self.insert_final_newline = over.insert_final_newline.or(self.insert_final_newline);
+    }
+
+    /// Fold the resolved properties into the per-file config and the
+    /// buffer: indent and tab width go through the config so the printer
+    /// follows along, the newline and save-time behavior sit on the buffer.
+    pub fn apply(&self, config: &mut EditorConfig, buffer: &mut TextBuffer) {
+        if let Some(style) = self.indent_style {
+            config.indent_style = style;
+        }
+        if let Some(width) = self.tab_width {
+            config.tab_width = width;
+        }
+        if let Some(ending) = self.end_of_line {
+            buffer.set_line_ending(ending);
+        }
+        if let Some(trim) = self.trim_trailing_whitespace {
+            buffer.trim_on_save = trim;
+        }
+        if let Some(newline) = self.insert_final_newline {
+            buffer.final_newline = newline;
+        }
+    }
+}
+
+/// Resolve the settings for `path`: collect every `.editorconfig` from its
+/// directory up to the filesystem root (or the first one marked
+/// `root = true`), then fold them together with the closest file winning.
+pub fn lookup(path: &Path) -> Settings {
+    let abs = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir().unwrap_or_default().join(path)
+    };
+    // Innermost first, the order the walk finds them in.
+    let mut chain: Vec<(PathBuf, String)> = Vec::new();
+    let mut dir = abs.parent();
+    while let Some(d) = dir {
+        if let Ok(text) = fs::read_to_string(d.join(".editorconfig")) {
+            let root = is_root(&text);
+            chain.push((d.to_path_buf(), text));
+            if root {
+                break;
+            }
+        }
+        dir = d.parent();
+    }
+    let mut settings = Settings::default();
+    for (dir, text) in chain.iter().rev() {
+        let Ok(rel) = abs.strip_prefix(dir) else {
+            continue;
+        };
+        settings.override_with(resolve(text, &rel.to_string_lossy()));
+    }
+    settings
+}
+
+/// True when the file's preamble says `root = true`, stopping the upward
+/// walk at this directory.
+fn is_root(text: &str) -> bool {
+    for raw in text.lines() {
+        let line = raw.trim();
+        if line.starts_with('[') {
+            // `root` only counts in the preamble, before any section.
+            return false;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            if key.trim().eq_ignore_ascii_case("root") {
+                return value.trim().eq_ignore_ascii_case("true");
+            }
+        }
+    }
+    false
+}
+
+/// The settings one `.editorconfig` yields for the file at `rel` (the path
+/// relative to the directory holding it). Later matching sections override
+/// earlier ones, as the format specifies.
+fn resolve(text: &str, rel: &str) -> Settings {
+    let mut settings = Settings::default();
+    // The preamble holds only `root`; no section has matched yet.
+    let mut applies = false;
+    for raw in text.lines() {
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(pattern) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            applies = section_matches(pattern.trim(), rel);
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        if !applies {
+            continue;
+        }
+        let value = value.trim().to_ascii_lowercase();
+        match key.trim().to_ascii_lowercase().as_str() {
+            "indent_style" => match value.as_str() {
+                "tab" => settings.indent_style = Some(IndentStyle::Tabs),
+                "space" => settings.indent_style = Some(IndentStyle::Spaces),
+                _ => {}
+            },
+            // `indent_size = tab` defers to `tab_width`; only numbers land.
+            "indent_size" | "tab_width" => {
+                if let Some(width) = value.parse::<usize>().ok().filter(|&w| w > 0) {
+                    settings.tab_width = Some(width);
+                }
+            }
+            "end_of_line" => match value.as_str() {
+                "lf" => settings.end_of_line = Some(LineEnding::Lf),
+                "crlf" => settings.end_of_line = Some(LineEnding::Crlf),
+                // Bare-CR files went out with Classic Mac OS.
+                _ => {}
+            },
+            "trim_trailing_whitespace" => {
+                settings.trim_trailing_whitespace = parse_bool(&value);
+            }
+            "insert_final_newline" => {
+                settings.insert_final_newline = parse_bool(&value);
+            }
+            _ => {}
+        }
+    }
+    settings
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+/// Whether a `[pattern]` section applies to the file at `rel`. Patterns
+/// with a `/` match against the whole relative path, anything else against
+/// the file name alone — so `*.rs` reaches into subdirectories.
+fn section_matches(pattern: &str, rel: &str) -> bool {
+    let (pattern, target) = if pattern.contains('/') {
+        (pattern.trim_start_matches('/'), rel)
+    } else {
+        (pattern, rel.rsplit('/').next().unwrap_or(rel))
+    };
+    glob_regex(pattern).is_some_and(|re| re.is_match(target))
+}
+
+/// Translate an EditorConfig glob into an anchored regex: `*` stops at
+/// path separators, `**` does not, `?` is any single character, `[abc]`
+/// passes through as a character class and `{a,b}` becomes an alternation.
+fn glob_regex(pattern: &str) -> Option<Regex> {
+    let mut re = String::from("^");
+    let mut in_braces = false;
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                re.push_str(".*");
+            }
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push_str("[^/]"),
+            '[' => {
+                re.push('[');
+                if chars.peek() == Some(&'!') {
+                    chars.next();
+                    re.push('^');
+                }
+                for inner in chars.by_ref() {
+                    if inner == ']' {
+                        break;
+                    }
+                    re.push_str(&regex::escape(&inner.to_string()));
+                }
+                re.push(']');
+            }
+            '{' => {
+                in_braces = true;
+                re.push_str("(?:");
+            }
+            ',' if in_braces => re.push('|'),
+            '}' if in_braces => {
+                in_braces = false;
+                re.push(')');
+            }
+            other => re.push_str(&regex::escape(&other.to_string())),
+        }
+    }
+    re.push('$');
+    Regex::new(&re).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "\
+root = true
+
+[*]
+indent_style = space
+indent_size = 2
+insert_final_newline = true
+
+[*.rs]
+indent_style = tab
+tab_width = 8
+
+[{Makefile,*.mk}]
+indent_style = tab
+";
+
+    #[test]
+    fn a_sample_file_resolves_settings_for_a_rust_source() {
+        let settings = resolve(SAMPLE, "src/main.rs");
+        // `[*.rs]` overrides the `[*]` section it follows.
+        assert_eq!(settings.indent_style, Some(IndentStyle::Tabs));
+        assert_eq!(settings.tab_width, Some(8));
+        assert_eq!(settings.insert_final_newline, Some(true));
+        // Nothing in the sample sets these.
+        assert_eq!(settings.end_of_line, None);
+        assert_eq!(settings.trim_trailing_whitespace, None);
+    }
+
+    #[test]
+    fn patterns_match_paths_names_and_brace_lists() {
+        let settings = resolve(SAMPLE, "docs/notes.txt");
+        assert_eq!(settings.indent_style, Some(IndentStyle::Spaces));
+        assert_eq!(settings.tab_width, Some(2));
+        assert_eq!(
+            resolve(SAMPLE, "Makefile").indent_style,
+            Some(IndentStyle::Tabs)
+        );
+        assert_eq!(
+            resolve(SAMPLE, "build/all.mk").indent_style,
+            Some(IndentStyle::Tabs)
+        );
+        // A path-qualified pattern anchors at the `.editorconfig` directory.
+        assert!(section_matches("src/**.rs", "src/app/main.rs"));
+        assert!(!section_matches("src/*.rs", "src/app/main.rs"));
+    }
+
+    #[test]
+    fn lookup_merges_files_up_the_tree_and_stops_at_root() {
+        let base = std::env::temp_dir().join("trust_test_editorconfig");
+        let src = base.join("src");
+        fs::create_dir_all(&src).unwrap();
+        fs::write(
+            base.join(".editorconfig"),
+            "root = true\n[*]\nindent_style = space\nindent_size = 2\nend_of_line = lf\n",
+        )
+        .unwrap();
+        fs::write(src.join(".editorconfig"), "[*.rs]\nindent_size = 4\n").unwrap();
+        let settings = lookup(&src.join("main.rs"));
+        // The inner file wins where it speaks, the outer fills the rest.
+        assert_eq!(settings.tab_width, Some(4));
+        assert_eq!(settings.indent_style, Some(IndentStyle::Spaces));
+        assert_eq!(settings.end_of_line, Some(LineEnding::Lf));
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -0,0 +1,102 @@
+use crate::buffer::TextBuffer;
+use crate::syntax::{Highlighter, TokenKind};
+
+/// The CSS color for a token kind, mirroring the terminal palette the
+/// printer uses so exports look like the editor did.
+fn css_color(kind: TokenKind) -> &'static str {
+    match kind {
+        TokenKind::Keyword => "olive",
+        TokenKind::String => "green",
+        TokenKind::Comment => "gray",
+        TokenKind::Number => "teal",
+    }
+}
+
+/// Append `chars` with the HTML metacharacters escaped, so source text can
+/// never be read as markup.
+fn escape_into(out: &mut String, chars: &[char]) {
+    for &c in chars {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            _ => out.push(c),
+        }
+    }
+}
+
+/// Render the whole buffer as an HTML `<pre>` block: one line per source
+/// line, each highlighted span wrapped in a styled `<span>`. With no
+/// highlighter the output is the escaped text alone.
+pub fn to_html(buffer: &TextBuffer, highlighter: Option<&dyn Highlighter>) -> String {
+    let mut out = String::from("<pre>\n");
+    for line in buffer.lines_iter() {
+        // Spans are char ranges, the same unit the cursor uses, so index
+        // by chars rather than bytes.
+        let chars: Vec<char> = line.chars().collect();
+        let spans = highlighter.map_or_else(Vec::new, |h| h.highlight_line(line));
+        let mut col = 0;
+        for span in spans {
+            if span.start < col || span.end > chars.len() {
+                continue;
+            }
+            escape_into(&mut out, &chars[col..span.start]);
+            out.push_str("<span style=\"color: ");
+            out.push_str(css_color(span.kind));
+            out.push_str("\">");
+            escape_into(&mut out, &chars[span.start..span.end]);
+            out.push_str("</span>");
+            col = span.end;
+        }
+        escape_into(&mut out, &chars[col..]);
+        out.push('\n');
+    }
+    out.push_str("</pre>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::RustHighlighter;
+
+    fn buffer_with(text: &str) -> TextBuffer {
+        let mut buf = TextBuffer::new();
+        buf.paste(text);
+        buf
+    }
+
+    #[test]
+    fn metacharacters_are_escaped() {
+        let buf = buffer_with("a < b && c > d");
+        let html = to_html(&buf, None);
+        assert!(html.contains("a &lt; b &amp;&amp; c &gt; d"), "{html}");
+    }
+
+    #[test]
+    fn keywords_get_a_styled_span() {
+        let buf = buffer_with("fn main() {}");
+        let html = to_html(&buf, Some(&RustHighlighter));
+        assert!(
+            html.contains("<span style=\"color: olive\">fn</span>"),
+            "{html}"
+        );
+    }
+
+    #[test]
+    fn string_contents_are_escaped_inside_their_span() {
+        let buf = buffer_with("let a = \"<>\";");
+        let html = to_html(&buf, Some(&RustHighlighter));
+        assert!(
+            html.contains("<span style=\"color: green\">\"&lt;&gt;\"</span>"),
+            "{html}"
+        );
+    }
+
+    #[test]
+    fn output_is_wrapped_in_a_pre_block() {
+        let html = to_html(&buffer_with("x"), None);
+        assert!(html.starts_with("<pre>\n"));
+        assert!(html.ends_with("</pre>\n"));
+    }
+}
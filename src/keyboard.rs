@@ -0,0 +1,449 @@
+use std::io;
+use std::time::{Duration, Instant};
+
+use crossterm::cursor;
+use crossterm::event::{
+    self, DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture,
+    Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEventKind,
+};
+use crossterm::terminal::{self, LeaveAlternateScreen};
+
+use crate::keymap::KeyMap;
+
+/// Whether typed characters are inserted or replace what is under the cursor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Insert,
+    Overwrite,
+}
+
+/// What the rest of the editor should do in response to a key press.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    InsertChar(char),
+    /// A bracketed paste from the terminal: inserted literally in one edit,
+    /// bypassing auto-indent and auto-pairing.
+    InsertText(String),
+    NewLine,
+    Backspace,
+    Delete,
+    DeleteWordLeft,
+    DeleteWordRight,
+    /// Readline-style Ctrl+K / Ctrl+U: delete to the line's end or start.
+    DeleteToLineEnd,
+    DeleteToLineStart,
+    /// Ctrl+T: swap the grapheme before the cursor with the one under it,
+    /// like Emacs' transpose-chars.
+    TransposeChars,
+    /// Collapse or reopen the indentation block at the cursor's line.
+    ToggleFold,
+    /// Shift+Tab: remove one level of indentation.
+    Unindent,
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    /// Ctrl+Left/Right: move by one word without selecting.
+    MoveWordLeft,
+    MoveWordRight,
+    LineStart,
+    LineEnd,
+    SelectUp,
+    SelectDown,
+    SelectLeft,
+    SelectRight,
+    SelectLineStart,
+    SelectLineEnd,
+    /// Ctrl+Shift+Left/Right: extend the selection by one word.
+    SelectWordLeft,
+    SelectWordRight,
+    /// Alt+Shift+arrows: extend a rectangular (block) selection.
+    SelectBlockUp,
+    SelectBlockDown,
+    SelectBlockLeft,
+    SelectBlockRight,
+    PageUp,
+    PageDown,
+    /// Add a secondary cursor on the line below the lowest cursor.
+    AddCursorBelow,
+    /// Add a secondary cursor at the next occurrence of the selection.
+    AddCursorNextMatch,
+    /// Alt+Left/Right: walk the jump list of positions left by search,
+    /// goto and bracket jumps.
+    JumpBack,
+    JumpForward,
+    DuplicateLine,
+    MatchBracket,
+    /// Select from the cursor's bracket through its matching partner.
+    SelectMatchBracket,
+    /// Select the contents of the nearest enclosing bracket pair.
+    SelectInsideBrackets,
+    ToggleComment,
+    JoinLines,
+    MoveLineUp,
+    MoveLineDown,
+    /// Left click at screen cell (column, row).
+    Click(u16, u16),
+    /// Left drag to screen cell (column, row).
+    Drag(u16, u16),
+    ScrollUp,
+    ScrollDown,
+    Copy,
+    Cut,
+    Paste,
+    /// Paste, shifting the pasted lines so the block matches the current
+    /// line's indentation. Plain [`Paste`](Self::Paste) stays literal.
+    PasteReindent,
+    Undo,
+    Redo,
+    SelectAll,
+    Save,
+    Find,
+    Replace,
+    GotoLine,
+    CommandPalette,
+    /// Esc: back out of the current selection or transient state.
+    Cancel,
+    BufferNext,
+    BufferPrev,
+    /// Move the cursor to the next split pane.
+    FocusNextPane,
+    /// Start or stop recording keystrokes into the macro register.
+    RecordMacro,
+    /// Replay the recorded keystrokes.
+    PlayMacro,
+    ToggleOverwrite,
+    /// F1: show the key-binding help overlay.
+    Help,
+    /// Alt+.: apply the last edit again at the current position.
+    RepeatEdit,
+    Quit,
+    Resize(u16, u16),
+    None,
+}
+
+impl Action {
+    /// Whether this action mutates the buffer, making it a candidate for
+    /// repeat-last-edit. Movement, selection, mouse and app-level actions
+    /// (save, search, quit) are not repeatable.
+    pub fn is_edit(&self) -> bool {
+        matches!(
+            self,
+            Action::InsertChar(_)
+                | Action::InsertText(_)
+                | Action::NewLine
+                | Action::Backspace
+                | Action::Delete
+                | Action::DeleteWordLeft
+                | Action::DeleteWordRight
+                | Action::DeleteToLineEnd
+                | Action::DeleteToLineStart
+                | Action::TransposeChars
+                | Action::Unindent
+                | Action::DuplicateLine
+                | Action::ToggleComment
+                | Action::JoinLines
+                | Action::MoveLineUp
+                | Action::MoveLineDown
+                | Action::Cut
+                | Action::Paste
+                | Action::PasteReindent
+        )
+    }
+}
+
+/// One unit of user input: a raw key press, which the app maps (and can
+/// record into a macro), or an already-mapped action for events that macros
+/// don't capture, like mouse clicks and resizes.
+pub enum Input {
+    Key(KeyEvent),
+    Action(Action),
+}
+
+/// Undo everything the editor changed about the terminal: mouse capture,
+/// the alternate screen, cursor shape and visibility, and raw mode. Safe to call more
+/// than once and from a panic hook, so failures are ignored — there is no
+/// useful way to report them while the terminal is half torn down.
+pub fn restore_terminal() {
+    let _ = crossterm::execute!(
+        io::stdout(),
+        DisableBracketedPaste,
+        DisableMouseCapture,
+        LeaveAlternateScreen,
+        cursor::SetCursorStyle::DefaultUserShape,
+        cursor::Show
+    );
+    let _ = terminal::disable_raw_mode();
+}
+
+/// The terminal-mode setup [`Keyboard::try_new`] performs, behind a trait
+/// so tests can inject a terminal that refuses (as a pipe would).
+trait RawMode {
+    fn enable(&mut self) -> io::Result<()>;
+}
+
+/// The real terminal: raw mode plus mouse capture and bracketed paste.
+struct CrosstermRawMode;
+
+impl RawMode for CrosstermRawMode {
+    fn enable(&mut self) -> io::Result<()> {
+        terminal::enable_raw_mode()?;
+        crossterm::execute!(io::stdout(), EnableMouseCapture, EnableBracketedPaste)
+    }
+}
+
+/// Reads terminal events and translates them into [`Action`]s.
+pub struct Keyboard {
+    mode: Mode,
+    keymap: KeyMap,
+}
+
+impl Keyboard {
+    /// [`try_new`](Self::try_new), panicking when the terminal can't be set
+    /// up. Kept for callers that have nowhere to send the error.
+    #[allow(dead_code)] // the editor itself goes through `try_new`
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self::try_new().expect("failed to enable raw mode")
+    }
+
+    /// Put the terminal in raw mode with mouse capture and bracketed paste.
+    /// Fails instead of panicking when that isn't possible — typically when
+    /// stdin isn't a TTY — so embedders and `App` can report it gracefully.
+    pub fn try_new() -> io::Result<Self> {
+        Self::with_terminal(&mut CrosstermRawMode)
+    }
+
+    /// [`try_new`](Self::try_new) against any [`RawMode`]; the seam tests
+    /// use to exercise the failure path.
+    fn with_terminal(terminal: &mut impl RawMode) -> io::Result<Self> {
+        // A panic would otherwise leave the user's shell in raw mode with
+        // mouse reporting on; restore the terminal before the message prints
+        // so it is actually readable.
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            restore_terminal();
+            default_hook(info);
+        }));
+        if let Err(e) = terminal.enable() {
+            // Raw mode may have stuck even though a later step failed.
+            restore_terminal();
+            return Err(e);
+        }
+        Ok(Keyboard {
+            mode: Mode::Insert,
+            keymap: KeyMap::load(),
+        })
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// The current bindings rendered as help-overlay lines; see
+    /// [`KeyMap::help_lines`].
+    pub fn help_lines(&self) -> Vec<String> {
+        self.keymap.help_lines()
+    }
+
+    /// True when `mods` carries the platform's primary shortcut modifier
+    /// (Ctrl everywhere, plus the Command key on macOS). Terminals disagree
+    /// on whether Command arrives as `META` or `SUPER`, so accept both.
+    fn is_primary(mods: KeyModifiers) -> bool {
+        mods.intersects(KeyModifiers::CONTROL | KeyModifiers::META | KeyModifiers::SUPER)
+    }
+
+    /// Block until the next key press and hand it back unmapped. Used by
+    /// status-line prompts that need raw input.
+    pub fn read_key(&mut self) -> io::Result<KeyEvent> {
+        loop {
+            if let Event::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Release {
+                    return Ok(key);
+                }
+            }
+        }
+    }
+
+    /// Wait up to `timeout` for the next event, or return `None`, so the
+    /// main loop can run periodic work (autosave) while the editor is
+    /// otherwise idle. Key presses come back raw so the app can record
+    /// them; everything else is mapped to an [`Action`] here.
+    pub fn read_timeout(&mut self, timeout: Duration) -> io::Result<Option<Input>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() || !event::poll(remaining)? {
+                return Ok(None);
+            }
+            if let Some(input) = Self::translate(event::read()?) {
+                return Ok(Some(input));
+            }
+        }
+    }
+
+    /// The [`Input`] one terminal event produces, or `None` for events the
+    /// editor ignores (key releases, mouse moves).
+    fn translate(event: Event) -> Option<Input> {
+        match event {
+            Event::Key(key) if key.kind != KeyEventKind::Release => Some(Input::Key(key)),
+            Event::Mouse(mouse) => match mouse.kind {
+                MouseEventKind::Down(MouseButton::Left) => {
+                    Some(Input::Action(Action::Click(mouse.column, mouse.row)))
+                }
+                MouseEventKind::Drag(MouseButton::Left) => {
+                    Some(Input::Action(Action::Drag(mouse.column, mouse.row)))
+                }
+                MouseEventKind::ScrollUp => Some(Input::Action(Action::ScrollUp)),
+                MouseEventKind::ScrollDown => Some(Input::Action(Action::ScrollDown)),
+                _ => None,
+            },
+            // One event for the whole paste, so the block is inserted in
+            // a single edit and drawn in a single frame.
+            Event::Paste(text) => Some(Input::Action(Action::InsertText(text))),
+            Event::Resize(w, h) => Some(Input::Action(Action::Resize(w, h))),
+            _ => None,
+        }
+    }
+
+    /// Translate one key press to an [`Action`], both for live input and for
+    /// macro playback.
+    pub fn map_key(&mut self, key: KeyEvent) -> Action {
+        // Esc cancels; quitting is an explicit Ctrl+Q (or `:q`), so Esc is
+        // free to back out of selections and prompts.
+        if key.code == KeyCode::Esc {
+            return Action::Cancel;
+        }
+
+        if Self::is_primary(key.modifiers) {
+            // Normalize Cmd/Ctrl to CONTROL so the keymap needs one entry
+            // per chord regardless of platform.
+            let mods =
+                KeyModifiers::CONTROL | (key.modifiers & (KeyModifiers::SHIFT | KeyModifiers::ALT));
+            if let Some(action) = self.keymap.lookup(key.code, mods) {
+                return action;
+            }
+            if matches!(key.code, KeyCode::Char(_)) {
+                // Unbound shortcut characters must not insert text.
+                return Action::None;
+            }
+        }
+
+        let shift = key.modifiers.contains(KeyModifiers::SHIFT);
+        let alt = key.modifiers.contains(KeyModifiers::ALT);
+        match key.code {
+            KeyCode::Char('.') if alt => Action::RepeatEdit,
+            KeyCode::Char(c) => Action::InsertChar(c),
+            KeyCode::Enter => Action::NewLine,
+            KeyCode::Tab => Action::InsertChar('\t'),
+            KeyCode::BackTab => Action::Unindent,
+            KeyCode::Backspace if Self::is_primary(key.modifiers) => Action::DeleteWordLeft,
+            KeyCode::Delete if Self::is_primary(key.modifiers) => Action::DeleteWordRight,
+            KeyCode::Backspace => Action::Backspace,
+            KeyCode::Delete => Action::Delete,
+            KeyCode::Up if alt && shift => Action::SelectBlockUp,
+            KeyCode::Down if alt && shift => Action::SelectBlockDown,
+            KeyCode::Left if alt && shift => Action::SelectBlockLeft,
+            KeyCode::Right if alt && shift => Action::SelectBlockRight,
+            KeyCode::Up if alt => Action::MoveLineUp,
+            KeyCode::Down if alt => Action::MoveLineDown,
+            KeyCode::Left if alt => Action::JumpBack,
+            KeyCode::Right if alt => Action::JumpForward,
+            KeyCode::Up if shift => Action::SelectUp,
+            KeyCode::Down if shift => Action::SelectDown,
+            KeyCode::Left if shift => Action::SelectLeft,
+            KeyCode::Right if shift => Action::SelectRight,
+            KeyCode::Home if shift => Action::SelectLineStart,
+            KeyCode::End if shift => Action::SelectLineEnd,
+            KeyCode::Up => Action::MoveUp,
+            KeyCode::Down => Action::MoveDown,
+            KeyCode::Left => Action::MoveLeft,
+            KeyCode::Right => Action::MoveRight,
+            KeyCode::Home => Action::LineStart,
+            KeyCode::End => Action::LineEnd,
+            KeyCode::PageUp => Action::PageUp,
+            KeyCode::PageDown => Action::PageDown,
+            KeyCode::F(1) => Action::Help,
+            KeyCode::Insert => {
+                self.mode = match self.mode {
+                    Mode::Insert => Mode::Overwrite,
+                    Mode::Overwrite => Mode::Insert,
+                };
+                Action::ToggleOverwrite
+            }
+            _ => Action::None,
+        }
+    }
+}
+
+impl Drop for Keyboard {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replayed_keys_map_like_live_ones() {
+        let mut kb = Keyboard {
+            mode: Mode::Insert,
+            keymap: KeyMap::default(),
+        };
+        let keys = [
+            KeyEvent::new(KeyCode::Char('X'), KeyModifiers::SHIFT),
+            KeyEvent::new(KeyCode::Down, KeyModifiers::NONE),
+        ];
+        let actions: Vec<Action> = keys.iter().map(|&k| kb.map_key(k)).collect();
+        assert_eq!(actions, vec![Action::InsertChar('X'), Action::MoveDown]);
+        // Mapping is stateless for these keys, so a second pass (playback)
+        // produces the same actions.
+        let replayed: Vec<Action> = keys.iter().map(|&k| kb.map_key(k)).collect();
+        assert_eq!(replayed, actions);
+    }
+
+    #[test]
+    fn edits_are_repeatable_but_movement_and_app_actions_are_not() {
+        // Repeating a delete-word twice walks over successive words, so it
+        // has to count as an edit; pure movement must not overwrite it.
+        assert!(Action::DeleteWordRight.is_edit());
+        assert!(Action::InsertChar('x').is_edit());
+        assert!(Action::ToggleComment.is_edit());
+        assert!(!Action::MoveDown.is_edit());
+        assert!(!Action::SelectAll.is_edit());
+        assert!(!Action::Save.is_edit());
+        assert!(!Action::RepeatEdit.is_edit());
+    }
+
+    struct FailingRawMode;
+
+    impl RawMode for FailingRawMode {
+        fn enable(&mut self) -> io::Result<()> {
+            Err(io::Error::new(io::ErrorKind::Unsupported, "not a tty"))
+        }
+    }
+
+    #[test]
+    fn a_refusing_terminal_surfaces_an_error_instead_of_panicking() {
+        let err = match Keyboard::with_terminal(&mut FailingRawMode) {
+            Ok(_) => panic!("raw-mode failure must propagate"),
+            Err(e) => e,
+        };
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn all_primary_modifier_spellings_are_recognized() {
+        assert!(Keyboard::is_primary(KeyModifiers::CONTROL));
+        assert!(Keyboard::is_primary(KeyModifiers::META));
+        assert!(Keyboard::is_primary(KeyModifiers::SUPER));
+        assert!(Keyboard::is_primary(
+            KeyModifiers::SUPER | KeyModifiers::SHIFT
+        ));
+        assert!(!Keyboard::is_primary(KeyModifiers::SHIFT));
+        assert!(!Keyboard::is_primary(KeyModifiers::ALT));
+        assert!(!Keyboard::is_primary(KeyModifiers::NONE));
+    }
+}
@@ -0,0 +1,409 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use crossterm::event::{KeyCode, KeyModifiers};
+
+use crate::keyboard::Action;
+
+/// Maps key chords to editor [`Action`]s. Starts from the built-in defaults;
+/// a config file can rebind any of the shortcut actions without code changes.
+#[derive(Debug)]
+pub struct KeyMap {
+    bindings: HashMap<(KeyCode, KeyModifiers), Action>,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        let mut map = KeyMap {
+            bindings: HashMap::new(),
+        };
+        let ctrl = KeyModifiers::CONTROL;
+        map.bind(KeyCode::Char('c'), ctrl, Action::Copy);
+        map.bind(KeyCode::Char('x'), ctrl, Action::Cut);
+        map.bind(KeyCode::Char('v'), ctrl, Action::Paste);
+        map.bind(
+            KeyCode::Char('v'),
+            ctrl | KeyModifiers::SHIFT,
+            Action::PasteReindent,
+        );
+        map.bind(KeyCode::Char('a'), ctrl, Action::SelectAll);
+        map.bind(KeyCode::Char('s'), ctrl, Action::Save);
+        map.bind(KeyCode::Char('f'), ctrl, Action::Find);
+        map.bind(KeyCode::Char('h'), ctrl, Action::Replace);
+        map.bind(KeyCode::Char('g'), ctrl, Action::GotoLine);
+        map.bind(KeyCode::Char('d'), ctrl, Action::AddCursorNextMatch);
+        map.bind(
+            KeyCode::Char('d'),
+            ctrl | KeyModifiers::SHIFT,
+            Action::DuplicateLine,
+        );
+        map.bind(
+            KeyCode::Down,
+            ctrl | KeyModifiers::ALT,
+            Action::AddCursorBelow,
+        );
+        map.bind(KeyCode::Char('p'), ctrl, Action::CommandPalette);
+        map.bind(KeyCode::Char('b'), ctrl, Action::MatchBracket);
+        map.bind(
+            KeyCode::Char('b'),
+            ctrl | KeyModifiers::SHIFT,
+            Action::SelectMatchBracket,
+        );
+        map.bind(
+            KeyCode::Char('u'),
+            ctrl | KeyModifiers::SHIFT,
+            Action::SelectInsideBrackets,
+        );
+        map.bind(KeyCode::Char('k'), ctrl, Action::DeleteToLineEnd);
+        map.bind(KeyCode::Char('u'), ctrl, Action::DeleteToLineStart);
+        map.bind(KeyCode::Char('t'), ctrl, Action::TransposeChars);
+        map.bind(
+            KeyCode::Char('f'),
+            ctrl | KeyModifiers::SHIFT,
+            Action::ToggleFold,
+        );
+        map.bind(KeyCode::Char('/'), ctrl, Action::ToggleComment);
+        map.bind(KeyCode::Char('j'), ctrl, Action::JoinLines);
+        map.bind(KeyCode::Char('o'), ctrl, Action::FocusNextPane);
+        map.bind(KeyCode::Char('r'), ctrl, Action::RecordMacro);
+        map.bind(
+            KeyCode::Char('r'),
+            ctrl | KeyModifiers::SHIFT,
+            Action::PlayMacro,
+        );
+        map.bind(KeyCode::Left, ctrl, Action::MoveWordLeft);
+        map.bind(KeyCode::Right, ctrl, Action::MoveWordRight);
+        map.bind(
+            KeyCode::Left,
+            ctrl | KeyModifiers::SHIFT,
+            Action::SelectWordLeft,
+        );
+        map.bind(
+            KeyCode::Right,
+            ctrl | KeyModifiers::SHIFT,
+            Action::SelectWordRight,
+        );
+        map.bind(KeyCode::Char('q'), ctrl, Action::Quit);
+        map.bind(KeyCode::PageDown, ctrl, Action::BufferNext);
+        map.bind(KeyCode::PageUp, ctrl, Action::BufferPrev);
+        map.bind(KeyCode::Char('z'), ctrl, Action::Undo);
+        map.bind(KeyCode::Char('z'), ctrl | KeyModifiers::SHIFT, Action::Redo);
+        map
+    }
+}
+
+impl KeyMap {
+    /// The defaults overridden by the user's keymap file, if one exists and
+    /// parses. A broken file falls back to the defaults rather than leaving
+    /// the editor without working keys.
+    pub fn load() -> Self {
+        let Some(path) = Self::config_path() else {
+            return KeyMap::default();
+        };
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text).unwrap_or_default(),
+            Err(_) => KeyMap::default(),
+        }
+    }
+
+    /// `$XDG_CONFIG_HOME/trust/keymap.toml`, falling back to
+    /// `~/.config/trust/keymap.toml`.
+    fn config_path() -> Option<PathBuf> {
+        let base = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))?;
+        Some(base.join("trust").join("keymap.toml"))
+    }
+
+    /// Parse `action = "chord"` lines, e.g. `save = "ctrl+w"` or
+    /// `redo = "ctrl+shift+z"`. Each parsed binding replaces the default
+    /// chord for that action. Comments (`#`) and blank lines are skipped.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut map = KeyMap::default();
+        for (idx, raw) in text.lines().enumerate() {
+            let line = raw.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, chord) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected `action = \"chord\"`", idx + 1))?;
+            let action = Self::action_by_name(name.trim())
+                .ok_or_else(|| format!("line {}: unknown action `{}`", idx + 1, name.trim()))?;
+            let chord = chord.trim().trim_matches('"');
+            let (code, mods) = Self::parse_chord(chord)
+                .ok_or_else(|| format!("line {}: cannot parse chord `{chord}`", idx + 1))?;
+            map.rebind(code, mods, action);
+        }
+        Ok(map)
+    }
+
+    /// The action bound to this chord, if any. `code` chars are matched
+    /// case-insensitively.
+    pub fn lookup(&self, code: KeyCode, mods: KeyModifiers) -> Option<Action> {
+        let code = match code {
+            KeyCode::Char(c) => KeyCode::Char(c.to_ascii_lowercase()),
+            other => other,
+        };
+        self.bindings.get(&(code, mods)).cloned()
+    }
+
+    fn bind(&mut self, code: KeyCode, mods: KeyModifiers, action: Action) {
+        self.bindings.insert((code, mods), action);
+    }
+
+    /// Bind `action` to a chord, removing whatever chord it was bound to
+    /// before so remapping doesn't leave the old default behind.
+    fn rebind(&mut self, code: KeyCode, mods: KeyModifiers, action: Action) {
+        self.bindings.retain(|_, a| *a != action);
+        self.bind(code, mods, action);
+    }
+
+    fn action_by_name(name: &str) -> Option<Action> {
+        Some(match name {
+            "copy" => Action::Copy,
+            "cut" => Action::Cut,
+            "paste" => Action::Paste,
+            "paste_reindent" => Action::PasteReindent,
+            "select_all" => Action::SelectAll,
+            "save" => Action::Save,
+            "find" => Action::Find,
+            "replace" => Action::Replace,
+            "goto_line" => Action::GotoLine,
+            "command_palette" => Action::CommandPalette,
+            "undo" => Action::Undo,
+            "redo" => Action::Redo,
+            "duplicate_line" => Action::DuplicateLine,
+            "match_bracket" => Action::MatchBracket,
+            "select_match_bracket" => Action::SelectMatchBracket,
+            "select_inside_brackets" => Action::SelectInsideBrackets,
+            "toggle_comment" => Action::ToggleComment,
+            "join_lines" => Action::JoinLines,
+            "buffer_next" => Action::BufferNext,
+            "buffer_prev" => Action::BufferPrev,
+            "focus_next_pane" => Action::FocusNextPane,
+            "record_macro" => Action::RecordMacro,
+            "play_macro" => Action::PlayMacro,
+            "move_line_up" => Action::MoveLineUp,
+            "move_line_down" => Action::MoveLineDown,
+            "delete_word_left" => Action::DeleteWordLeft,
+            "delete_word_right" => Action::DeleteWordRight,
+            "delete_to_line_end" => Action::DeleteToLineEnd,
+            "delete_to_line_start" => Action::DeleteToLineStart,
+            "transpose_chars" => Action::TransposeChars,
+            "toggle_fold" => Action::ToggleFold,
+            "add_cursor_below" => Action::AddCursorBelow,
+            "add_cursor_next_match" => Action::AddCursorNextMatch,
+            "move_word_left" => Action::MoveWordLeft,
+            "move_word_right" => Action::MoveWordRight,
+            "select_word_left" => Action::SelectWordLeft,
+            "select_word_right" => Action::SelectWordRight,
+            "quit" => Action::Quit,
+            _ => return None,
+        })
+    }
+
+    /// The config-file name of `action`; the inverse of
+    /// [`action_by_name`](Self::action_by_name).
+    fn action_name(action: &Action) -> Option<&'static str> {
+        Some(match action {
+            Action::Copy => "copy",
+            Action::Cut => "cut",
+            Action::Paste => "paste",
+            Action::PasteReindent => "paste_reindent",
+            Action::SelectAll => "select_all",
+            Action::Save => "save",
+            Action::Find => "find",
+            Action::Replace => "replace",
+            Action::GotoLine => "goto_line",
+            Action::CommandPalette => "command_palette",
+            Action::Undo => "undo",
+            Action::Redo => "redo",
+            Action::DuplicateLine => "duplicate_line",
+            Action::MatchBracket => "match_bracket",
+            Action::SelectMatchBracket => "select_match_bracket",
+            Action::SelectInsideBrackets => "select_inside_brackets",
+            Action::ToggleComment => "toggle_comment",
+            Action::JoinLines => "join_lines",
+            Action::BufferNext => "buffer_next",
+            Action::BufferPrev => "buffer_prev",
+            Action::FocusNextPane => "focus_next_pane",
+            Action::RecordMacro => "record_macro",
+            Action::PlayMacro => "play_macro",
+            Action::MoveLineUp => "move_line_up",
+            Action::MoveLineDown => "move_line_down",
+            Action::DeleteWordLeft => "delete_word_left",
+            Action::DeleteWordRight => "delete_word_right",
+            Action::DeleteToLineEnd => "delete_to_line_end",
+            Action::DeleteToLineStart => "delete_to_line_start",
+            Action::TransposeChars => "transpose_chars",
+            Action::ToggleFold => "toggle_fold",
+            Action::AddCursorBelow => "add_cursor_below",
+            Action::AddCursorNextMatch => "add_cursor_next_match",
+            Action::MoveWordLeft => "move_word_left",
+            Action::MoveWordRight => "move_word_right",
+            Action::SelectWordLeft => "select_word_left",
+            Action::SelectWordRight => "select_word_right",
+            Action::Quit => "quit",
+            _ => return None,
+        })
+    }
+
+    /// One line per binding, `action  chord`, sorted by action name. Built
+    /// from the live bindings, so user remaps show up instead of the
+    /// defaults they replaced. Feeds the help overlay.
+    pub fn help_lines(&self) -> Vec<String> {
+        let mut entries: Vec<(&str, String)> = self
+            .bindings
+            .iter()
+            .filter_map(|(&(code, mods), action)| {
+                Some((Self::action_name(action)?, chord_text(code, mods)))
+            })
+            .collect();
+        entries.sort_unstable();
+        let width = entries
+            .iter()
+            .map(|(name, _)| name.len())
+            .max()
+            .unwrap_or(0);
+        entries
+            .into_iter()
+            .map(|(name, chord)| format!("{name:<width$}  {chord}"))
+            .collect()
+    }
+
+    /// `ctrl+shift+z` style chords: any number of modifiers joined with `+`,
+    /// ending in a single character or a named key.
+    fn parse_chord(chord: &str) -> Option<(KeyCode, KeyModifiers)> {
+        let mut mods = KeyModifiers::NONE;
+        let mut code = None;
+        for part in chord.split('+') {
+            match part.trim().to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => mods |= KeyModifiers::CONTROL,
+                "shift" => mods |= KeyModifiers::SHIFT,
+                "alt" => mods |= KeyModifiers::ALT,
+                "enter" => code = Some(KeyCode::Enter),
+                "tab" => code = Some(KeyCode::Tab),
+                "backspace" => code = Some(KeyCode::Backspace),
+                "delete" => code = Some(KeyCode::Delete),
+                "esc" | "escape" => code = Some(KeyCode::Esc),
+                "pageup" => code = Some(KeyCode::PageUp),
+                "pagedown" => code = Some(KeyCode::PageDown),
+                key => {
+                    let mut chars = key.chars();
+                    let c = chars.next()?;
+                    if chars.next().is_some() {
+                        return None;
+                    }
+                    code = Some(KeyCode::Char(c));
+                }
+            }
+        }
+        Some((code?, mods))
+    }
+}
+
+/// Render a chord the way the keymap file spells it, e.g. `ctrl+shift+z`.
+fn chord_text(code: KeyCode, mods: KeyModifiers) -> String {
+    let mut out = String::new();
+    for (flag, name) in [
+        (KeyModifiers::CONTROL, "ctrl"),
+        (KeyModifiers::ALT, "alt"),
+        (KeyModifiers::SHIFT, "shift"),
+    ] {
+        if mods.contains(flag) {
+            out.push_str(name);
+            out.push('+');
+        }
+    }
+    match code {
+        KeyCode::Char(c) => out.push(c),
+        KeyCode::Enter => out.push_str("enter"),
+        KeyCode::Tab => out.push_str("tab"),
+        KeyCode::Backspace => out.push_str("backspace"),
+        KeyCode::Delete => out.push_str("delete"),
+        KeyCode::Esc => out.push_str("esc"),
+        KeyCode::PageUp => out.push_str("pageup"),
+        KeyCode::PageDown => out.push_str("pagedown"),
+        KeyCode::Up => out.push_str("up"),
+        KeyCode::Down => out.push_str("down"),
+        KeyCode::Left => out.push_str("left"),
+        KeyCode::Right => out.push_str("right"),
+        other => out.push_str(&format!("{other:?}").to_lowercase()),
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_resolve_the_standard_shortcuts() {
+        let map = KeyMap::default();
+        let ctrl = KeyModifiers::CONTROL;
+        assert_eq!(map.lookup(KeyCode::Char('s'), ctrl), Some(Action::Save));
+        assert_eq!(
+            map.lookup(KeyCode::Char('z'), ctrl | KeyModifiers::SHIFT),
+            Some(Action::Redo)
+        );
+        assert_eq!(map.lookup(KeyCode::Char('q'), ctrl), Some(Action::Quit));
+    }
+
+    #[test]
+    fn keymap_file_rebinds_an_action() {
+        let map = KeyMap::parse("# my bindings\nsave = \"ctrl+w\"\n").unwrap();
+        let ctrl = KeyModifiers::CONTROL;
+        assert_eq!(map.lookup(KeyCode::Char('w'), ctrl), Some(Action::Save));
+        // The old default is gone; other defaults survive.
+        assert_eq!(map.lookup(KeyCode::Char('s'), ctrl), None);
+        assert_eq!(map.lookup(KeyCode::Char('c'), ctrl), Some(Action::Copy));
+    }
+
+    #[test]
+    fn swapping_undo_and_redo_works() {
+        let map = KeyMap::parse("undo = \"ctrl+shift+z\"\nredo = \"ctrl+z\"\n").unwrap();
+        let ctrl = KeyModifiers::CONTROL;
+        assert_eq!(map.lookup(KeyCode::Char('z'), ctrl), Some(Action::Redo));
+        assert_eq!(
+            map.lookup(KeyCode::Char('z'), ctrl | KeyModifiers::SHIFT),
+            Some(Action::Undo)
+        );
+    }
+
+    #[test]
+    fn bad_lines_are_reported_with_their_number() {
+        let err = KeyMap::parse("save = \"ctrl+s\"\nnonsense\n").unwrap_err();
+        assert!(err.contains("line 2"), "{err}");
+        let err = KeyMap::parse("launch = \"ctrl+l\"").unwrap_err();
+        assert!(err.contains("unknown action"), "{err}");
+    }
+
+    #[test]
+    fn help_lines_reflect_remaps_and_sort_by_action() {
+        let map = KeyMap::parse("save = \"ctrl+w\"").unwrap();
+        let lines = map.help_lines();
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.starts_with("save") && l.ends_with("ctrl+w")),
+            "{lines:?}"
+        );
+        // The default ctrl+s binding was replaced, not merely shadowed.
+        assert!(!lines.iter().any(|l| l.ends_with("ctrl+s")), "{lines:?}");
+        let mut sorted = lines.clone();
+        sorted.sort();
+        assert_eq!(lines, sorted);
+    }
+
+    #[test]
+    fn chords_are_case_insensitive() {
+        let map = KeyMap::parse("save = \"Ctrl+W\"").unwrap();
+        assert_eq!(
+            map.lookup(KeyCode::Char('W'), KeyModifiers::CONTROL),
+            Some(Action::Save)
+        );
+    }
+}
@@ -0,0 +1,25 @@
+mod app;
+mod buffer;
+mod clipboard;
+mod command;
+mod config;
+mod editorconfig;
+mod export;
+mod keyboard;
+mod keymap;
+mod paged;
+mod printer;
+mod recovery;
+mod registry;
+mod session;
+mod syntax;
+
+use std::env;
+
+use app::App;
+
+fn main() -> std::io::Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut app = App::from_args(&args)?;
+    app.run()
+}
@@ -0,0 +1,133 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
+use std::path::Path;
+
+/// Serves document lines by index without holding the whole document in
+/// memory. [`TextBuffer`](crate::buffer::TextBuffer) keeps one behind a
+/// paged buffer and faults lines in as the user scrolls.
+pub trait LineProvider {
+    /// Total number of lines in the document.
+    fn line_count(&self) -> usize;
+
+    /// The lines with indices `start..end` (clamped to the document),
+    /// without their line endings.
+    fn fetch(&mut self, start: usize, end: usize) -> io::Result<Vec<String>>;
+}
+
+/// Byte offsets are remembered every `INDEX_STRIDE` lines; a fetch seeks to
+/// the nearest checkpoint and scans forward from there, keeping the index
+/// small even for files with hundreds of millions of lines.
+const INDEX_STRIDE: usize = 1024;
+
+/// A file served line-by-line: one streaming pass at open builds the
+/// checkpoint index, and each fetch seeks and reads only the lines asked
+/// for. The file's contents are never resident as a whole.
+pub struct PagedFile {
+    file: BufReader<File>,
+    /// Byte offset where line `i * INDEX_STRIDE` starts.
+    checkpoints: Vec<u64>,
+    line_count: usize,
+}
+
+impl PagedFile {
+    /// Open `path` and index it in one pass. Like a regular load, a file
+    /// without content still counts one (empty) line.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut file = BufReader::new(File::open(path)?);
+        let mut checkpoints = vec![0];
+        let mut line_count = 0;
+        let mut offset = 0u64;
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            let read = file.read_until(b'\n', &mut buf)?;
+            if read == 0 {
+                break;
+            }
+            offset += read as u64;
+            line_count += 1;
+            if line_count % INDEX_STRIDE == 0 {
+                checkpoints.push(offset);
+            }
+        }
+        Ok(PagedFile {
+            file,
+            checkpoints,
+            line_count: line_count.max(1),
+        })
+    }
+}
+
+impl LineProvider for PagedFile {
+    fn line_count(&self) -> usize {
+        self.line_count
+    }
+
+    fn fetch(&mut self, start: usize, end: usize) -> io::Result<Vec<String>> {
+        let end = end.min(self.line_count);
+        if start >= end {
+            return Ok(Vec::new());
+        }
+        let checkpoint = start / INDEX_STRIDE;
+        self.file
+            .seek(SeekFrom::Start(self.checkpoints[checkpoint]))?;
+        let mut out = Vec::with_capacity(end - start);
+        let mut buf = Vec::new();
+        for line in checkpoint * INDEX_STRIDE..end {
+            buf.clear();
+            if self.file.read_until(b'\n', &mut buf)? == 0 {
+                break;
+            }
+            if line < start {
+                continue;
+            }
+            if buf.last() == Some(&b'\n') {
+                buf.pop();
+            }
+            if buf.last() == Some(&b'\r') {
+                buf.pop();
+            }
+            out.push(String::from_utf8_lossy(&buf).into_owned());
+        }
+        // An empty file indexes as one line with no bytes behind it; pad
+        // so callers always get the range they asked for.
+        while out.len() < end - start {
+            out.push(String::new());
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn fetches_line_ranges_from_a_file() {
+        let path = std::env::temp_dir().join("trust_test_paged_provider.txt");
+        let text: String = (0..3000).map(|i| format!("line {i}\n")).collect();
+        fs::write(&path, &text).unwrap();
+        let mut paged = PagedFile::open(&path).unwrap();
+        assert_eq!(paged.line_count(), 3000);
+        assert_eq!(paged.fetch(0, 2).unwrap(), vec!["line 0", "line 1"]);
+        // A range past the first checkpoint seeks instead of rescanning.
+        assert_eq!(
+            paged.fetch(2047, 2050).unwrap(),
+            vec!["line 2047", "line 2048", "line 2049"]
+        );
+        // Ranges clamp to the file's end.
+        assert_eq!(paged.fetch(2999, 3010).unwrap(), vec!["line 2999"]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn crlf_and_a_missing_final_newline_are_handled() {
+        let path = std::env::temp_dir().join("trust_test_paged_crlf.txt");
+        fs::write(&path, "a\r\nb\r\nlast").unwrap();
+        let mut paged = PagedFile::open(&path).unwrap();
+        assert_eq!(paged.line_count(), 3);
+        assert_eq!(paged.fetch(0, 3).unwrap(), vec!["a", "b", "last"]);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,1619 @@
+use std::collections::HashMap;
+use std::io::{self, Stdout, Write};
+
+use crossterm::cursor::{MoveTo, SetCursorStyle};
+use crossterm::style::{
+    Attribute, Color, Print, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor,
+};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen};
+use crossterm::QueueableCommand;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::buffer::{ChangeMark, SelectionMode, TextBuffer};
+use crate::keyboard::Mode;
+use crate::syntax::{Highlighter, Span, TokenKind};
+
+/// How the line-number gutter labels each row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumberMode {
+    /// Every line shows its own 1-based number.
+    Absolute,
+    /// Lines show their distance from the cursor; the cursor line shows 0.
+    Relative,
+    /// Like `Relative`, but the cursor line shows its absolute number.
+    Hybrid,
+}
+
+/// The cursor shapes a writing mode can be configured to use; all of them
+/// blink, except for the terminal's own default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorShape {
+    Bar,
+    Block,
+    Underline,
+    /// Whatever shape the terminal was configured with.
+    Default,
+}
+
+/// The terminal cursor style for `mode` under the configured shapes: out of
+/// the box a bar while inserting and a block while overwriting, so the mode
+/// is visible at a glance. Command and search prompts don't come through
+/// here; they switch to an underline directly.
+pub fn cursor_style_for(mode: Mode, insert: CursorShape, overwrite: CursorShape) -> SetCursorStyle {
+    let shape = match mode {
+        Mode::Insert => insert,
+        Mode::Overwrite => overwrite,
+    };
+    match shape {
+        CursorShape::Bar => SetCursorStyle::BlinkingBar,
+        CursorShape::Block => SetCursorStyle::BlinkingBlock,
+        CursorShape::Underline => SetCursorStyle::BlinkingUnderScore,
+        CursorShape::Default => SetCursorStyle::DefaultUserShape,
+    }
+}
+
+/// The gutter label for `line_idx` (0-based) with the cursor on
+/// `cursor_line`, right-aligned to `width - 1` digits plus a trailing space.
+fn gutter_text(line_idx: usize, cursor_line: usize, mode: NumberMode, width: usize) -> String {
+    let number = match mode {
+        NumberMode::Absolute => line_idx + 1,
+        NumberMode::Relative if line_idx == cursor_line => 0,
+        NumberMode::Hybrid if line_idx == cursor_line => line_idx + 1,
+        NumberMode::Relative | NumberMode::Hybrid => line_idx.abs_diff(cursor_line),
+    };
+    format!("{:>width$} ", number, width = width - 1)
+}
+
+/// A rectangle in screen cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+/// Shrink and shift `rect` so it fits entirely on a `term_width` x
+/// `term_height` screen.
+fn clamp_rect(rect: Rect, term_width: u16, term_height: u16) -> Rect {
+    let width = rect.width.min(term_width);
+    let height = rect.height.min(term_height);
+    let x = rect.x.min(term_width - width);
+    let y = rect.y.min(term_height - height);
+    Rect {
+        x,
+        y,
+        width,
+        height,
+    }
+}
+
+/// First visible item of a `visible`-row list window, scrolled the minimum
+/// amount needed to keep `selected` on screen.
+fn list_window_start(item_count: usize, visible: usize, selected: usize) -> usize {
+    if visible == 0 || item_count <= visible {
+        return 0;
+    }
+    let selected = selected.min(item_count - 1);
+    let max_start = item_count - visible;
+    selected.saturating_sub(visible - 1).min(max_start)
+}
+
+/// Replace each tab with spaces out to the next `tab_width` boundary. The
+/// buffer keeps the raw tabs; only rendering expands them.
+fn expand_tabs(line: &str, tab_width: usize) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut col = 0;
+    for c in line.chars() {
+        if c == '\t' {
+            let pad = tab_width - col % tab_width;
+            out.extend(std::iter::repeat_n(' ', pad));
+            col += pad;
+        } else {
+            out.push(c);
+            col += c.width().unwrap_or(0);
+        }
+    }
+    out
+}
+
+/// Like [`expand_tabs`], but render the whitespace visibly: spaces become
+/// `·` and each tab a `→` padded out to its stop. Every substitution keeps
+/// its cell width, so cursor and selection math are unaffected.
+fn expand_tabs_with_markers(line: &str, tab_width: usize) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut col = 0;
+    for c in line.chars() {
+        match c {
+            '\t' => {
+                let pad = tab_width - col % tab_width;
+                out.push('→');
+                out.extend(std::iter::repeat_n(' ', pad - 1));
+                col += pad;
+            }
+            ' ' => {
+                out.push('·');
+                col += 1;
+            }
+            _ => {
+                out.push(c);
+                col += c.width().unwrap_or(0);
+            }
+        }
+    }
+    out
+}
+
+/// The visual-column range of `line`'s trailing whitespace, if any.
+fn trailing_ws_cols(line: &str, tab_width: usize) -> Option<(usize, usize)> {
+    let trimmed = line.trim_end();
+    if trimmed.len() == line.len() {
+        return None;
+    }
+    let from = visual_col(line, trimmed.chars().count(), tab_width);
+    let to = visual_col(line, line.chars().count(), tab_width);
+    Some((from, to))
+}
+
+/// Screen column of character index `col` in `line`, accounting for tab
+/// expansion and double-width characters before it. The buffer's movement
+/// code shares this, so the cursor moves by what is on screen.
+pub fn visual_col(line: &str, col: usize, tab_width: usize) -> usize {
+    let mut vcol = 0;
+    for c in line.chars().take(col) {
+        if c == '\t' {
+            vcol += tab_width - vcol % tab_width;
+        } else {
+            vcol += c.width().unwrap_or(0);
+        }
+    }
+    vcol
+}
+
+/// The part of `line` that falls in screen columns `[skip, skip + take)`.
+/// A double-width character cut in half by the left edge becomes a space so
+/// the columns after it stay aligned.
+fn slice_columns(line: &str, skip: usize, take: usize) -> String {
+    let mut out = String::new();
+    let mut col = 0;
+    for c in line.chars() {
+        let w = c.width().unwrap_or(0);
+        if col + w <= skip {
+            col += w;
+            continue;
+        }
+        if col < skip {
+            out.push(' ');
+            col += w;
+            continue;
+        }
+        if col + w > skip + take {
+            break;
+        }
+        out.push(c);
+        col += w;
+    }
+    out
+}
+
+/// Inverse of [`visual_col`]: the char index of the character occupying
+/// screen column `vcol`, or the line's char count when `vcol` is past the
+/// end. Clicking either cell of a double-width character lands on it.
+pub fn char_col_at(line: &str, vcol: usize, tab_width: usize) -> usize {
+    let mut acc = 0;
+    for (idx, c) in line.chars().enumerate() {
+        let w = if c == '\t' {
+            tab_width - acc % tab_width
+        } else {
+            c.width().unwrap_or(0)
+        };
+        if vcol < acc + w {
+            return idx;
+        }
+        acc += w;
+    }
+    line.chars().count()
+}
+
+/// The terminal columns where ruler guides land: each ruler's visual column
+/// shifted by the horizontal scroll and the gutter, dropping rulers that
+/// fall outside the text window.
+fn ruler_screen_cols(
+    rulers: &[usize],
+    scroll_left: usize,
+    gutter: usize,
+    text_width: usize,
+) -> Vec<usize> {
+    rulers
+        .iter()
+        .filter_map(|&r| r.checked_sub(scroll_left))
+        .filter(|&c| c < text_width)
+        .map(|c| gutter + c)
+        .collect()
+}
+
+/// First visible line after keeping the cursor at least `scroll_off` rows
+/// away from the viewport's top and bottom edges. The margin shrinks near
+/// the ends of the buffer, so the first and last lines can still reach the
+/// edge rows instead of forcing the view past them.
+fn vertical_scroll(
+    scroll_top: usize,
+    cursor_line: usize,
+    rows: usize,
+    scroll_off: usize,
+    line_count: usize,
+) -> usize {
+    // A margin bigger than half the viewport would make the two edges
+    // fight over the cursor; cap it so there is always a stable row.
+    let margin = scroll_off.min(rows.saturating_sub(1) / 2);
+    let above = margin.min(cursor_line);
+    let below = margin.min(line_count.saturating_sub(1).saturating_sub(cursor_line));
+    if cursor_line < scroll_top + above {
+        cursor_line - above
+    } else if cursor_line + below >= scroll_top + rows {
+        cursor_line + below + 1 - rows
+    } else {
+        scroll_top
+    }
+}
+
+/// Visual rows a line of `width` cells occupies when soft-wrapped to
+/// `text_width` columns. Empty lines still occupy one row.
+fn wrap_rows(width: usize, text_width: usize) -> usize {
+    if text_width == 0 {
+        return 1;
+    }
+    width.div_ceil(text_width).max(1)
+}
+
+/// The visual row where the cell at logical line `line` (an index into
+/// `widths`, the visual widths of the lines from the top of the view) and
+/// visual column `vcol` lands once every line is wrapped to `text_width`
+/// columns.
+fn visual_row(widths: &[usize], line: usize, vcol: usize, text_width: usize) -> usize {
+    let above: usize = widths[..line]
+        .iter()
+        .map(|&w| wrap_rows(w, text_width))
+        .sum();
+    above + vcol.checked_div(text_width).unwrap_or(0)
+}
+
+/// New horizontal scroll offset keeping `cursor_vcol` inside a viewport of
+/// `width` cells. Scrolls only as far as needed in either direction.
+fn horizontal_scroll(scroll_left: usize, cursor_vcol: usize, width: usize) -> usize {
+    if cursor_vcol < scroll_left {
+        cursor_vcol
+    } else if width > 0 && cursor_vcol >= scroll_left + width {
+        cursor_vcol + 1 - width
+    } else {
+        scroll_left
+    }
+}
+
+/// The per-frame state every rendered row draws against, computed once per
+/// [`build_frame`](Printer::build_frame) pass.
+struct FrameContext {
+    selection: Option<((usize, usize), (usize, usize))>,
+    block: Option<(usize, usize, usize, usize)>,
+    bracket_pair: Option<[(usize, usize); 2]>,
+    extra_cursors: Vec<(usize, usize)>,
+    gutter: usize,
+    text_width: usize,
+}
+
+/// Everything that determines how one screen row looks. Comparing rows
+/// from the previous frame tells the printer which rows must be repainted.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+struct RenderedRow {
+    gutter: String,
+    gutter_bold: bool,
+    text: String,
+    /// Highlighted visual-column range, if the selection touches this row.
+    selected: Option<(usize, usize)>,
+    /// Syntax spans in visual columns, clipped to the visible window.
+    spans: Vec<Span>,
+    /// Visual columns of bracket-match highlights on this row.
+    brackets: Vec<usize>,
+    /// Visual columns of secondary cursors on this row.
+    cursors: Vec<usize>,
+    /// Visual-column range of trailing whitespace to flag, when enabled.
+    trailing: Option<(usize, usize)>,
+    /// This row shows the cursor's line and gets the background highlight.
+    cursor_line: bool,
+    /// Terminal columns to draw a ruler guide in, pre-filtered to cells
+    /// where nothing else is visible.
+    rulers: Vec<usize>,
+}
+
+/// The row drawn below the last buffer line: a dim `~` in column 0, Vim
+/// style, so the end of the document is distinguishable from real empty
+/// lines.
+fn eof_row() -> RenderedRow {
+    RenderedRow {
+        text: "~".to_string(),
+        spans: vec![Span {
+            start: 0,
+            end: 1,
+            kind: TokenKind::Comment,
+        }],
+        ..RenderedRow::default()
+    }
+}
+
+/// Indices of rows that differ between the previous and the next frame,
+/// including rows that only exist in one of them.
+fn dirty_rows(old: &[RenderedRow], new: &[RenderedRow]) -> Vec<usize> {
+    let rows = old.len().max(new.len());
+    (0..rows).filter(|&i| old.get(i) != new.get(i)).collect()
+}
+
+/// The color a token kind renders in.
+fn color_for(kind: TokenKind) -> Color {
+    match kind {
+        TokenKind::Keyword => Color::Yellow,
+        TokenKind::String => Color::Green,
+        TokenKind::Comment => Color::DarkGrey,
+        TokenKind::Number => Color::Cyan,
+    }
+}
+
+/// The gutter cell for a line's change mark, drawn in the padding column
+/// between the line number and the text.
+fn change_char(mark: ChangeMark) -> Option<char> {
+    match mark {
+        ChangeMark::Unchanged => None,
+        ChangeMark::Added => Some('+'),
+        ChangeMark::Modified => Some('~'),
+        ChangeMark::DeletedAbove => Some('^'),
+    }
+}
+
+/// Gutter columns needed for `line_count` lines: the digits of the largest
+/// number plus one space of padding.
+fn gutter_width_for(line_count: usize) -> usize {
+    line_count.to_string().len() + 1
+}
+
+/// The highlighted char-column range of `line_idx`, if the selection touches
+/// that line. Columns are clamped to the visible part of the line.
+fn selection_cols_on_line(
+    selection: Option<((usize, usize), (usize, usize))>,
+    line_idx: usize,
+    line_len: usize,
+) -> Option<(usize, usize)> {
+    let ((start_line, start_col), (end_line, end_col)) = selection?;
+    if line_idx < start_line || line_idx > end_line {
+        return None;
+    }
+    let from = if line_idx == start_line { start_col } else { 0 };
+    let to = if line_idx == end_line {
+        end_col
+    } else {
+        line_len
+    };
+    let from = from.min(line_len);
+    let to = to.min(line_len);
+    (from < to).then_some((from, to))
+}
+
+/// The highlighted char-column range of `line_idx` for a block selection:
+/// the same column span on every row the rectangle crosses, clipped to the
+/// line's length.
+fn block_cols_on_line(
+    block: Option<(usize, usize, usize, usize)>,
+    line_idx: usize,
+    line_len: usize,
+) -> Option<(usize, usize)> {
+    let (top, bottom, left, right) = block?;
+    if line_idx < top || line_idx > bottom {
+        return None;
+    }
+    let from = left.min(line_len);
+    let to = right.min(line_len);
+    (from < to).then_some((from, to))
+}
+
+/// Everything the status line shows besides the cursor position, handed in
+/// by [`App`](crate::app::App) because the printer doesn't know about modes
+/// or filenames.
+pub struct StatusInfo {
+    pub filename: Option<String>,
+    pub modified: bool,
+    pub overwrite: bool,
+    /// Show `[RO]` after the filename: the app is in read-only mode or the
+    /// buffer itself rejects edits.
+    pub read_only: bool,
+    /// Transient message (search prompt, save result, errors); shown after
+    /// the filename when present.
+    pub message: String,
+}
+
+/// How far through the file the viewport sits, Vim style: `All` when the
+/// whole buffer fits, `Top`/`Bot` at the edges, otherwise a percentage of
+/// the scrollable range.
+fn scroll_indicator(scroll_top: usize, rows: usize, line_count: usize) -> String {
+    if line_count <= rows {
+        return "All".to_string();
+    }
+    if scroll_top == 0 {
+        return "Top".to_string();
+    }
+    if scroll_top + rows >= line_count {
+        return "Bot".to_string();
+    }
+    format!("{}%", scroll_top * 100 / (line_count - rows))
+}
+
+/// Lay out the status line: filename and message on the left, mode,
+/// cursor position, and scroll indicator on the right, truncated to fit
+/// `width` cells.
+fn format_status(info: &StatusInfo, line: usize, col: usize, pos: &str, width: usize) -> String {
+    let right = format!(
+        "{}  Ln {}, Col {}  {pos}",
+        if info.overwrite { "OVR" } else { "INS" },
+        line + 1,
+        col + 1
+    );
+    let mut name = info
+        .filename
+        .clone()
+        .unwrap_or_else(|| "[No Name]".to_string());
+    if info.modified {
+        name.push('*');
+    }
+    if info.read_only {
+        name.push_str(" [RO]");
+    }
+    let left = if info.message.is_empty() {
+        name
+    } else {
+        format!("{name}  {}", info.message)
+    };
+    let right_len = right.chars().count();
+    if width <= right_len + 2 {
+        return right.chars().take(width).collect();
+    }
+    let left_room = width - right_len - 2;
+    let left: String = left.chars().take(left_room).collect();
+    let pad = width - left.chars().count() - right_len;
+    format!("{left}{}{right}", " ".repeat(pad))
+}
+
+/// Owns the output sink — stdout in the editor, any [`Write`] in tests —
+/// and knows how to put a [`TextBuffer`] on the screen.
+pub struct Printer<W: Write = Stdout> {
+    out: W,
+    pub width: u16,
+    pub height: u16,
+    /// How many columns a tab advances to the next multiple of.
+    tab_width: usize,
+    /// Render a line-number gutter on the left.
+    pub show_line_numbers: bool,
+    /// How the gutter numbers the lines when it is shown.
+    number_mode: NumberMode,
+    /// Render spaces as `·` and tabs as `→` instead of blanks.
+    pub show_whitespace: bool,
+    /// Flag trailing whitespace in a distinct color, independently of
+    /// `show_whitespace`.
+    pub show_trailing_whitespace: bool,
+    /// Paint the cursor's row with a subtle background, gutter excluded,
+    /// out to the right edge of the screen.
+    pub cursor_line_highlight: bool,
+    /// Visual columns to draw a faint vertical guide at, e.g. `[80]`.
+    rulers: Vec<usize>,
+    /// Rows of context to keep between the cursor and the viewport's top
+    /// and bottom edges while scrolling.
+    scroll_off: usize,
+    /// Soft-wrap long lines across several rows instead of scrolling
+    /// horizontally. The view still scrolls by logical lines.
+    wrap: bool,
+    /// The rows as they currently appear on screen, indexed by absolute
+    /// screen row; empty after anything (popup, resize) invalidated the
+    /// display.
+    last_frame: Vec<RenderedRow>,
+    /// The status line drawn on each screen row, keyed by row so every pane
+    /// can cache its own.
+    last_status: HashMap<u16, String>,
+    /// The cursor style last sent to the terminal, so mode changes emit the
+    /// escape sequence once instead of on every redraw.
+    cursor_style: Option<SetCursorStyle>,
+    /// Colors the visible lines, when the file's language is recognized.
+    highlighter: Option<Box<dyn Highlighter>>,
+}
+
+impl Printer {
+    /// Switches to the alternate screen so the user's shell scrollback is
+    /// untouched; [`restore_terminal`](crate::keyboard::restore_terminal)
+    /// switches back on exit and on panic.
+    pub fn new() -> io::Result<Self> {
+        let mut out = io::stdout();
+        crossterm::execute!(out, EnterAlternateScreen)?;
+        let (width, height) = terminal::size()?;
+        Ok(Printer {
+            out,
+            width,
+            height,
+            tab_width: 4,
+            show_line_numbers: true,
+            number_mode: NumberMode::Absolute,
+            show_whitespace: false,
+            show_trailing_whitespace: false,
+            cursor_line_highlight: false,
+            rulers: Vec::new(),
+            scroll_off: 0,
+            wrap: false,
+            last_frame: Vec::new(),
+            last_status: HashMap::new(),
+            cursor_style: None,
+            highlighter: None,
+        })
+    }
+}
+
+impl<W: Write> Printer<W> {
+    /// A printer rendering into `sink` instead of the terminal — no
+    /// alternate screen, no size probe — so output can be asserted on
+    /// without a terminal.
+    #[allow(dead_code)] // exercised by tests; the editor renders to stdout
+    pub fn with_sink(sink: W, width: u16, height: u16) -> Printer<W> {
+        Printer {
+            out: sink,
+            width,
+            height,
+            tab_width: 4,
+            show_line_numbers: true,
+            number_mode: NumberMode::Absolute,
+            show_whitespace: false,
+            show_trailing_whitespace: false,
+            cursor_line_highlight: false,
+            rulers: Vec::new(),
+            scroll_off: 0,
+            wrap: false,
+            last_frame: Vec::new(),
+            last_status: HashMap::new(),
+            cursor_style: None,
+            highlighter: None,
+        }
+    }
+
+    pub fn resize(&mut self, width: u16, height: u16) {
+        self.width = width;
+        self.height = height;
+        self.invalidate();
+    }
+
+    /// Swap in the highlighter for the current file (or `None` for plain
+    /// text) and repaint everything.
+    pub fn set_highlighter(&mut self, highlighter: Option<Box<dyn Highlighter>>) {
+        self.highlighter = highlighter;
+        self.invalidate();
+    }
+
+    pub fn tab_width(&self) -> usize {
+        self.tab_width
+    }
+
+    /// Change the tab stop width (minimum 1) and repaint everything.
+    pub fn set_tab_width(&mut self, width: usize) {
+        self.tab_width = width.max(1);
+        self.invalidate();
+    }
+
+    /// Replace the set of ruler columns and repaint everything.
+    pub fn set_rulers(&mut self, rulers: Vec<usize>) {
+        self.rulers = rulers;
+        self.invalidate();
+    }
+
+    /// Set how many rows of context scrolling keeps around the cursor.
+    pub fn set_scroll_off(&mut self, rows: usize) {
+        self.scroll_off = rows;
+    }
+
+    /// Turn soft wrapping on or off and repaint everything.
+    pub fn set_wrap(&mut self, on: bool) {
+        self.wrap = on;
+        self.invalidate();
+    }
+
+    /// Change how the gutter numbers lines and repaint everything.
+    pub fn set_number_mode(&mut self, mode: NumberMode) {
+        self.number_mode = mode;
+        self.invalidate();
+    }
+
+    /// Queue a cursor-style change; a no-op when `style` is already on
+    /// screen, so [`App`](crate::app::App) can call this on every redraw.
+    pub fn set_cursor_style(&mut self, style: SetCursorStyle) -> io::Result<()> {
+        if self.cursor_style != Some(style) {
+            self.out.queue(style)?;
+            self.cursor_style = Some(style);
+        }
+        Ok(())
+    }
+
+    /// Forget what is on screen so the next draw repaints every row.
+    pub fn invalidate(&mut self) {
+        self.last_frame.clear();
+        self.last_status.clear();
+    }
+
+    /// Width of the line-number gutter, adapting to the total line count.
+    /// Zero when line numbers are off.
+    fn gutter_width(&self, buffer: &TextBuffer) -> usize {
+        if !self.show_line_numbers {
+            return 0;
+        }
+        gutter_width_for(buffer.lines.len())
+    }
+
+    /// Columns left for buffer text once the gutter is accounted for.
+    fn text_width(&self, buffer: &TextBuffer) -> usize {
+        (self.width as usize).saturating_sub(self.gutter_width(buffer))
+    }
+
+    /// Keep the cursor inside a viewport of `rows` text rows by adjusting
+    /// the buffer's scroll offset before drawing.
+    fn scroll_to_cursor(&self, buffer: &mut TextBuffer, rows: usize) {
+        let cursor_vcol = visual_col(
+            &buffer.lines[buffer.cursor_line],
+            buffer.cursor_col,
+            self.tab_width,
+        );
+        if self.wrap {
+            // Wrapping replaces horizontal scrolling, and the view still
+            // scrolls by whole logical lines: drop the top line until the
+            // cursor's wrapped row fits.
+            buffer.scroll_left = 0;
+            buffer.scroll_top = buffer.scroll_top.min(buffer.cursor_line);
+            let text_width = self.text_width(buffer).max(1);
+            let widths: Vec<usize> = (buffer.scroll_top..=buffer.cursor_line)
+                .filter(|&l| !buffer.is_line_hidden(l))
+                .map(|l| self.line_visual_width(&buffer.lines[l]))
+                .collect();
+            // The end of a line whose width is an exact multiple of the
+            // viewport sits on its last chunk, not a phantom next row.
+            let chunk =
+                (cursor_vcol / text_width).min(wrap_rows(*widths.last().unwrap(), text_width) - 1);
+            let mut row = visual_row(&widths, widths.len() - 1, 0, text_width) + chunk;
+            let mut skip = 0;
+            while row >= rows && skip + 1 < widths.len() {
+                row -= wrap_rows(widths[skip], text_width);
+                skip += 1;
+            }
+            buffer.scroll_top += skip;
+            return;
+        }
+        buffer.scroll_top = vertical_scroll(
+            buffer.scroll_top,
+            buffer.cursor_line,
+            rows,
+            self.scroll_off,
+            buffer.lines.len(),
+        );
+        buffer.scroll_left =
+            horizontal_scroll(buffer.scroll_left, cursor_vcol, self.text_width(buffer));
+    }
+
+    /// The buffer position under screen cell (`x`, `y`), accounting for the
+    /// gutter, scrolling, tabs and wide characters. Positions past the text
+    /// clamp to the nearest valid spot, like [`TextBuffer::set_cursor`].
+    pub fn buffer_position(&self, buffer: &TextBuffer, x: u16, y: u16) -> (usize, usize) {
+        let cell = (x as usize).saturating_sub(self.gutter_width(buffer));
+        if self.wrap {
+            // Walk the wrapped rows down from the top of the view to find
+            // which logical line (and which of its chunks) was hit.
+            let text_width = self.text_width(buffer).max(1);
+            let mut line = buffer.scroll_top.min(buffer.lines.len() - 1);
+            let mut remaining = y as usize;
+            loop {
+                if buffer.is_line_hidden(line) && line + 1 < buffer.lines.len() {
+                    line += 1;
+                    continue;
+                }
+                let rows = wrap_rows(self.line_visual_width(&buffer.lines[line]), text_width);
+                if remaining < rows || line + 1 >= buffer.lines.len() {
+                    let chunk = remaining.min(rows - 1);
+                    let vcol = chunk * text_width + cell;
+                    return (line, char_col_at(&buffer.lines[line], vcol, self.tab_width));
+                }
+                remaining -= rows;
+                line += 1;
+            }
+        }
+        // Walk down from the top of the view one visible line per row, so
+        // clicks land on the line actually drawn there despite folds.
+        let mut line = buffer.scroll_top.min(buffer.lines.len() - 1);
+        let mut remaining = y as usize;
+        while line + 1 < buffer.lines.len() && (remaining > 0 || buffer.is_line_hidden(line)) {
+            if !buffer.is_line_hidden(line) {
+                remaining -= 1;
+            }
+            line += 1;
+        }
+        // The walk can end on a hidden line when the click is past the last
+        // visible one; snap back to something rendered.
+        while line > 0 && buffer.is_line_hidden(line) {
+            line -= 1;
+        }
+        let vcol = cell.saturating_add(buffer.scroll_left);
+        let col = char_col_at(&buffer.lines[line], vcol, self.tab_width);
+        (line, col)
+    }
+
+    /// The per-frame inputs shared by every row; see
+    /// [`build_frame`](Self::build_frame).
+    fn frame_context(&self, buffer: &TextBuffer) -> FrameContext {
+        // Highlight the cursor's bracket and its partner, when there is one.
+        let cursor_pos = (buffer.cursor_line, buffer.cursor_col);
+        FrameContext {
+            selection: buffer.get_selection(),
+            block: (buffer.selection_mode() == SelectionMode::Block)
+                .then(|| buffer.block_selection())
+                .flatten(),
+            bracket_pair: buffer
+                .matching_bracket(cursor_pos)
+                .map(|partner| [cursor_pos, partner]),
+            extra_cursors: buffer.extra_cursors().to_vec(),
+            gutter: self.gutter_width(buffer),
+            text_width: self.text_width(buffer),
+        }
+    }
+
+    /// Compute what each of `rows` text rows should look like for this
+    /// frame. With wrap off each visible line takes one row, horizontally
+    /// scrolled; with wrap on a long line spills onto the following rows,
+    /// and only its first row carries the line number.
+    fn build_frame(&self, buffer: &TextBuffer, rows: usize) -> Vec<RenderedRow> {
+        let ctx = self.frame_context(buffer);
+        let mut frame = vec![RenderedRow::default(); rows];
+        if self.wrap {
+            let text_width = ctx.text_width.max(1);
+            let mut row = 0;
+            let mut line_idx = buffer.scroll_top;
+            while row < rows && line_idx < buffer.lines.len() {
+                if buffer.is_line_hidden(line_idx) {
+                    line_idx += 1;
+                    continue;
+                }
+                let width = self.line_visual_width(&buffer.lines[line_idx]);
+                for chunk in 0..wrap_rows(width, text_width) {
+                    if row >= rows {
+                        break;
+                    }
+                    frame[row] =
+                        self.render_row(buffer, &ctx, line_idx, chunk * text_width, chunk == 0);
+                    row += 1;
+                }
+                line_idx += 1;
+            }
+            // Only rows past the end of the document get the EOF marker;
+            // rows filled by wrapped continuations do not.
+            if line_idx >= buffer.lines.len() {
+                for slot in frame.iter_mut().skip(row) {
+                    *slot = eof_row();
+                }
+            }
+        } else {
+            // Folds can hide lines inside the window, so the walk may reach
+            // past `scroll_top + rows` before it fills every row.
+            let mut slots = frame.iter_mut();
+            let shown = buffer
+                .lines_in_range(buffer.scroll_top, buffer.lines.len())
+                .len();
+            for line_idx in (buffer.scroll_top..buffer.scroll_top + shown)
+                .filter(|&line| !buffer.is_line_hidden(line))
+            {
+                let Some(slot) = slots.next() else {
+                    break;
+                };
+                *slot = self.render_row(buffer, &ctx, line_idx, buffer.scroll_left, true);
+            }
+            for slot in slots {
+                *slot = eof_row();
+            }
+        }
+        frame
+    }
+
+    /// The visual width of `line` in screen cells, tabs expanded.
+    fn line_visual_width(&self, line: &str) -> usize {
+        visual_col(line, line.chars().count(), self.tab_width)
+    }
+
+    /// Render the window of `line_idx` starting at visual column `offset`
+    /// into one row's plan. `numbered` rows get the gutter label;
+    /// wrap-continuation rows get a blank gutter instead.
+    fn render_row(
+        &self,
+        buffer: &TextBuffer,
+        ctx: &FrameContext,
+        line_idx: usize,
+        offset: usize,
+        numbered: bool,
+    ) -> RenderedRow {
+        let line = &buffer.lines[line_idx];
+        let expanded = if self.show_whitespace {
+            expand_tabs_with_markers(line, self.tab_width)
+        } else {
+            expand_tabs(line, self.tab_width)
+        };
+        let mut visible = slice_columns(&expanded, offset, ctx.text_width);
+        if let Some(hidden) = buffer.fold_at(line_idx) {
+            // The summary still has to fit the pane, so re-clip the row.
+            visible.push_str(&format!(" … ({hidden} lines)"));
+            visible = slice_columns(&visible, 0, ctx.text_width);
+        }
+        let selected = if ctx.block.is_some() {
+            block_cols_on_line(ctx.block, line_idx, line.chars().count())
+        } else {
+            selection_cols_on_line(ctx.selection, line_idx, line.chars().count())
+        };
+        let selected = selected
+            .map(|(from, to)| {
+                (
+                    visual_col(line, from, self.tab_width).saturating_sub(offset),
+                    visual_col(line, to, self.tab_width).saturating_sub(offset),
+                )
+            })
+            .map(|(from, to)| {
+                let len = visible.width();
+                (from.min(len), to.min(len))
+            })
+            .filter(|(from, to)| from < to);
+        let window = visible.width();
+        let spans = self
+            .highlighter
+            .as_deref()
+            .map(|h| h.highlight_line(line))
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|s| {
+                let from = visual_col(line, s.start, self.tab_width)
+                    .saturating_sub(offset)
+                    .min(window);
+                let to = visual_col(line, s.end, self.tab_width)
+                    .saturating_sub(offset)
+                    .min(window);
+                (from < to).then_some(Span {
+                    start: from,
+                    end: to,
+                    kind: s.kind,
+                })
+            })
+            .collect();
+        let brackets = ctx
+            .bracket_pair
+            .iter()
+            .flatten()
+            .filter(|(l, _)| *l == line_idx)
+            .filter_map(|&(_, c)| {
+                let vcol = visual_col(line, c, self.tab_width).saturating_sub(offset);
+                (vcol < window).then_some(vcol)
+            })
+            .collect();
+        let cursors = ctx
+            .extra_cursors
+            .iter()
+            .filter(|(l, _)| *l == line_idx)
+            .filter_map(|&(_, c)| {
+                let vcol = visual_col(line, c, self.tab_width).saturating_sub(offset);
+                (vcol < window).then_some(vcol)
+            })
+            .collect();
+        let trailing = self
+            .show_trailing_whitespace
+            .then(|| trailing_ws_cols(line, self.tab_width))
+            .flatten()
+            .map(|(from, to)| {
+                (
+                    from.saturating_sub(offset).min(window),
+                    to.saturating_sub(offset).min(window),
+                )
+            })
+            .filter(|(from, to)| from < to);
+        // Guides draw "behind" the text: only into cells showing
+        // nothing, and never over the selection's reverse video.
+        let rulers = ruler_screen_cols(&self.rulers, offset, ctx.gutter, ctx.text_width)
+            .into_iter()
+            .filter(|&col| {
+                slice_columns(&visible, col - ctx.gutter, 1)
+                    .trim()
+                    .is_empty()
+            })
+            .filter(|&col| {
+                !selected
+                    .is_some_and(|(from, to)| col - ctx.gutter >= from && col - ctx.gutter < to)
+            })
+            .collect();
+        RenderedRow {
+            gutter: if ctx.gutter == 0 {
+                String::new()
+            } else if numbered {
+                let mut gutter =
+                    gutter_text(line_idx, buffer.cursor_line, self.number_mode, ctx.gutter);
+                if let Some(mark) = change_char(buffer.change_mark(line_idx)) {
+                    gutter.pop();
+                    gutter.push(mark);
+                }
+                gutter
+            } else {
+                " ".repeat(ctx.gutter)
+            },
+            gutter_bold: numbered && line_idx == buffer.cursor_line,
+            text: visible,
+            selected,
+            spans,
+            brackets,
+            cursors,
+            trailing,
+            cursor_line: self.cursor_line_highlight && line_idx == buffer.cursor_line,
+            rulers,
+        }
+    }
+
+    /// Repaint one row from its render plan.
+    fn paint_row(&mut self, row: usize, rendered: &RenderedRow) -> io::Result<()> {
+        self.out.queue(MoveTo(0, row as u16))?;
+        self.out.queue(Clear(ClearType::UntilNewLine))?;
+        if !rendered.gutter.is_empty() {
+            if rendered.gutter_bold {
+                self.out.queue(SetAttribute(Attribute::Bold))?;
+                self.out.queue(Print(&rendered.gutter))?;
+                self.out.queue(SetAttribute(Attribute::Reset))?;
+            } else {
+                self.out.queue(Print(&rendered.gutter))?;
+            }
+        }
+        // Cut the row wherever the selection or a syntax span starts or
+        // ends, then paint each homogeneous segment in one go.
+        let width = rendered.text.width();
+        let mut cuts = vec![0, width];
+        if let Some((from, to)) = rendered.selected {
+            cuts.push(from.min(width));
+            cuts.push(to.min(width));
+        }
+        for span in &rendered.spans {
+            cuts.push(span.start.min(width));
+            cuts.push(span.end.min(width));
+        }
+        for &col in &rendered.brackets {
+            cuts.push(col.min(width));
+            cuts.push((col + 1).min(width));
+        }
+        for &col in &rendered.cursors {
+            cuts.push(col.min(width));
+            cuts.push((col + 1).min(width));
+        }
+        if let Some((from, to)) = rendered.trailing {
+            cuts.push(from.min(width));
+            cuts.push(to.min(width));
+        }
+        cuts.sort_unstable();
+        cuts.dedup();
+        for pair in cuts.windows(2) {
+            let (from, to) = (pair[0], pair[1]);
+            let segment = slice_columns(&rendered.text, from, to - from);
+            let selected = rendered.selected.is_some_and(|(s, e)| from >= s && to <= e)
+                || rendered.brackets.iter().any(|&b| from == b && to == b + 1)
+                || rendered.cursors.iter().any(|&c| from == c && to == c + 1);
+            let color = rendered
+                .spans
+                .iter()
+                .find(|span| from >= span.start && to <= span.end)
+                .map(|span| color_for(span.kind));
+            // The cells are usually blank, so flag trailing whitespace with a
+            // background color rather than a foreground one.
+            let trailing = rendered.trailing.is_some_and(|(s, e)| from >= s && to <= e);
+            // Reverse video and the red flag already stand out on their
+            // own; the cursor-line wash only tints the plain cells.
+            let washed = rendered.cursor_line && !selected && !trailing;
+            if selected {
+                self.out.queue(SetAttribute(Attribute::Reverse))?;
+            }
+            if trailing {
+                self.out.queue(SetBackgroundColor(Color::Red))?;
+            } else if washed {
+                self.out.queue(SetBackgroundColor(Color::DarkGrey))?;
+            }
+            if let Some(color) = color {
+                self.out.queue(SetForegroundColor(color))?;
+            }
+            self.out.queue(Print(segment))?;
+            if selected {
+                self.out.queue(SetAttribute(Attribute::Reset))?;
+            }
+            if color.is_some() || trailing || washed {
+                self.out.queue(ResetColor)?;
+            }
+        }
+        if rendered.cursor_line {
+            // Carry the wash past the text to the screen's right edge.
+            let used = rendered.gutter.width() + width;
+            let pad = (self.width as usize).saturating_sub(used);
+            if pad > 0 {
+                self.out.queue(SetBackgroundColor(Color::DarkGrey))?;
+                self.out.queue(Print(" ".repeat(pad)))?;
+                self.out.queue(ResetColor)?;
+            }
+        }
+        for &col in &rendered.rulers {
+            self.out.queue(MoveTo(col as u16, row as u16))?;
+            self.out.queue(SetForegroundColor(Color::DarkGrey))?;
+            self.out.queue(Print('│'))?;
+            self.out.queue(ResetColor)?;
+        }
+        Ok(())
+    }
+
+    /// Draw a buffer into the vertical strip of `height` rows starting at
+    /// screen row `top`, with its own status line on the strip's bottom row.
+    /// Only the `focused` region gets the terminal cursor, so the focused
+    /// pane must be drawn last.
+    pub fn draw_region(
+        &mut self,
+        buffer: &mut TextBuffer,
+        info: &StatusInfo,
+        top: u16,
+        height: u16,
+        focused: bool,
+    ) -> io::Result<()> {
+        if height == 0 {
+            return Ok(());
+        }
+        let rows = height as usize - 1;
+        self.scroll_to_cursor(buffer, rows);
+        let frame = self.build_frame(buffer, rows);
+        let first = top as usize;
+        let shown = self.last_frame.get(first..first + rows).unwrap_or(&[]);
+        let dirty = dirty_rows(shown, &frame);
+        for row in dirty {
+            let rendered = frame.get(row).cloned().unwrap_or_default();
+            self.paint_row(first + row, &rendered)?;
+        }
+        let status_row = top + height - 1;
+        let pos = scroll_indicator(buffer.scroll_top, rows, buffer.lines.len());
+        let status = format_status(
+            info,
+            buffer.cursor_line,
+            buffer.cursor_col,
+            &pos,
+            self.width as usize,
+        );
+        if self.last_status.get(&status_row) != Some(&status) {
+            self.out.queue(MoveTo(0, status_row))?;
+            self.out.queue(Clear(ClearType::UntilNewLine))?;
+            self.out.queue(Print(&status))?;
+            self.last_status.insert(status_row, status);
+        }
+        if self.last_frame.len() < first + rows {
+            self.last_frame.resize(first + rows, RenderedRow::default());
+        }
+        self.last_frame[first..first + rows].clone_from_slice(&frame);
+        if focused {
+            let gutter = self.gutter_width(buffer);
+            let cursor_vcol = visual_col(
+                &buffer.lines[buffer.cursor_line],
+                buffer.cursor_col,
+                self.tab_width,
+            );
+            if self.wrap {
+                let text_width = self.text_width(buffer).max(1);
+                let widths: Vec<usize> = (buffer.scroll_top..=buffer.cursor_line)
+                    .filter(|&l| !buffer.is_line_hidden(l))
+                    .map(|l| self.line_visual_width(&buffer.lines[l]))
+                    .collect();
+                let chunk = (cursor_vcol / text_width)
+                    .min(wrap_rows(*widths.last().unwrap(), text_width) - 1);
+                let row = (visual_row(&widths, widths.len() - 1, 0, text_width) + chunk)
+                    .min(rows.saturating_sub(1));
+                self.out.queue(MoveTo(
+                    (gutter + cursor_vcol - chunk * text_width) as u16,
+                    top + row as u16,
+                ))?;
+            } else {
+                let hidden = buffer.hidden_lines_between(buffer.scroll_top, buffer.cursor_line);
+                let cursor_row = top + (buffer.cursor_line - buffer.scroll_top - hidden) as u16;
+                self.out.queue(MoveTo(
+                    (gutter + cursor_vcol - buffer.scroll_left) as u16,
+                    cursor_row,
+                ))?;
+            }
+        }
+        self.out.flush()
+    }
+
+    /// Draw a bordered popup over the buffer: a title in the top border and a
+    /// scrollable list with `selected` shown highlighted.
+    ///
+    /// Nothing is saved or restored here; drawing a popup invalidates the
+    /// diff renderer's frame, so the next [`draw_region`](Self::draw_region)
+    /// call repaints the rows underneath and removes the popup again.
+    pub fn draw_popup(
+        &mut self,
+        rect: Rect,
+        title: &str,
+        lines: &[String],
+        selected: Option<usize>,
+    ) -> io::Result<()> {
+        self.invalidate();
+        let rect = clamp_rect(rect, self.width, self.height);
+        if rect.width < 2 || rect.height < 2 {
+            return Ok(());
+        }
+        let inner_width = rect.width as usize - 2;
+        let inner_height = rect.height as usize - 2;
+
+        let mut top = String::from("┌");
+        let title_fit: String = title.chars().take(inner_width).collect();
+        top.push_str(&title_fit);
+        top.extend(std::iter::repeat_n(
+            '─',
+            inner_width - title_fit.chars().count(),
+        ));
+        top.push('┐');
+        self.out.queue(MoveTo(rect.x, rect.y))?;
+        self.out.queue(Print(top))?;
+
+        let start = list_window_start(lines.len(), inner_height, selected.unwrap_or(0));
+        for row in 0..inner_height {
+            let item_idx = start + row;
+            let content: String = lines
+                .get(item_idx)
+                .map(|l| l.chars().take(inner_width).collect())
+                .unwrap_or_default();
+            let padded = format!("{content:<inner_width$}");
+            self.out.queue(MoveTo(rect.x, rect.y + 1 + row as u16))?;
+            self.out.queue(Print("│"))?;
+            if selected == Some(item_idx) && item_idx < lines.len() {
+                self.out.queue(SetAttribute(Attribute::Reverse))?;
+                self.out.queue(Print(padded))?;
+                self.out.queue(SetAttribute(Attribute::Reset))?;
+            } else {
+                self.out.queue(Print(padded))?;
+            }
+            self.out.queue(Print("│"))?;
+        }
+
+        let mut bottom = String::from("└");
+        bottom.extend(std::iter::repeat_n('─', inner_width));
+        bottom.push('┘');
+        self.out.queue(MoveTo(rect.x, rect.y + rect.height - 1))?;
+        self.out.queue(Print(bottom))?;
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn whitespace_markers_keep_the_visual_width() {
+        let line = "\tfn x() {  ";
+        assert_eq!(
+            expand_tabs_with_markers(line, 4).width(),
+            expand_tabs(line, 4).width()
+        );
+        assert_eq!(expand_tabs_with_markers("a b\tc", 4), "a·b→c");
+        assert_eq!(expand_tabs_with_markers("\tx", 8), "→       x");
+    }
+
+    #[test]
+    fn trailing_whitespace_range_is_found() {
+        assert_eq!(trailing_ws_cols("code  ", 4), Some((4, 6)));
+        assert_eq!(trailing_ws_cols("a\t", 4), Some((1, 4)));
+        assert_eq!(trailing_ws_cols("  indented", 4), None);
+        assert_eq!(trailing_ws_cols("", 4), None);
+    }
+
+    #[test]
+    fn ruler_columns_respect_scroll_and_gutter() {
+        // Gutter of 4 shifts everything right; no scroll.
+        assert_eq!(ruler_screen_cols(&[80, 100], 0, 4, 120), vec![84, 104]);
+        // Scrolled 30 columns in: the guides move left with the text.
+        assert_eq!(ruler_screen_cols(&[80, 100], 30, 4, 120), vec![54, 74]);
+        // Rulers scrolled off either edge are dropped.
+        assert_eq!(ruler_screen_cols(&[80], 90, 4, 120), Vec::<usize>::new());
+        assert_eq!(ruler_screen_cols(&[80], 0, 4, 60), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn absolute_gutter_numbers_every_line() {
+        let texts: Vec<String> = (3..6)
+            .map(|line| gutter_text(line, 4, NumberMode::Absolute, 4))
+            .collect();
+        assert_eq!(texts, vec!["  4 ", "  5 ", "  6 "]);
+    }
+
+    #[test]
+    fn relative_gutter_counts_distance_from_the_cursor() {
+        let texts: Vec<String> = (3..6)
+            .map(|line| gutter_text(line, 4, NumberMode::Relative, 4))
+            .collect();
+        assert_eq!(texts, vec!["  1 ", "  0 ", "  1 "]);
+    }
+
+    #[test]
+    fn hybrid_gutter_keeps_the_cursor_line_absolute() {
+        let texts: Vec<String> = (3..6)
+            .map(|line| gutter_text(line, 4, NumberMode::Hybrid, 4))
+            .collect();
+        assert_eq!(texts, vec!["  1 ", "  5 ", "  1 "]);
+    }
+
+    #[test]
+    fn rect_already_inside_is_unchanged() {
+        let r = Rect {
+            x: 2,
+            y: 1,
+            width: 10,
+            height: 5,
+        };
+        assert_eq!(clamp_rect(r, 80, 24), r);
+    }
+
+    #[test]
+    fn oversized_rect_shrinks_to_terminal() {
+        let r = Rect {
+            x: 0,
+            y: 0,
+            width: 200,
+            height: 100,
+        };
+        let c = clamp_rect(r, 80, 24);
+        assert_eq!((c.width, c.height), (80, 24));
+        assert_eq!((c.x, c.y), (0, 0));
+    }
+
+    #[test]
+    fn offscreen_rect_is_shifted_back() {
+        let r = Rect {
+            x: 75,
+            y: 20,
+            width: 10,
+            height: 8,
+        };
+        let c = clamp_rect(r, 80, 24);
+        assert_eq!((c.x, c.y), (70, 16));
+        assert_eq!((c.width, c.height), (10, 8));
+    }
+
+    fn row(text: &str) -> RenderedRow {
+        RenderedRow {
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn only_changed_rows_are_dirty() {
+        let old = vec![row("fn main() {"), row("    body"), row("}")];
+        let mut new = old.clone();
+        new[1] = row("    edited");
+        assert_eq!(dirty_rows(&old, &new), vec![1]);
+        assert_eq!(dirty_rows(&old, &old.clone()), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn selection_change_alone_dirties_the_row() {
+        let old = vec![row("hello")];
+        let mut new = old.clone();
+        new[0].selected = Some((0, 3));
+        assert_eq!(dirty_rows(&old, &new), vec![0]);
+    }
+
+    #[test]
+    fn rows_missing_from_either_frame_are_dirty() {
+        let old = vec![row("a"), row("b")];
+        let new = vec![row("a")];
+        assert_eq!(dirty_rows(&old, &new), vec![1]);
+        assert_eq!(dirty_rows(&new, &old), vec![1]);
+    }
+
+    /// A printer rendering into a byte sink, for exercising frame
+    /// construction and output without a terminal.
+    fn test_printer() -> Printer<Vec<u8>> {
+        let mut printer = Printer::with_sink(Vec::new(), 40, 10);
+        printer.show_line_numbers = false;
+        printer
+    }
+
+    #[test]
+    fn rendering_into_a_sink_emits_the_visible_lines() {
+        let mut printer = Printer::with_sink(Vec::new(), 40, 5);
+        let mut buf = TextBuffer::from_str("alpha\nbeta");
+        let info = info(Some("t.txt"), false, "");
+        printer.draw_region(&mut buf, &info, 0, 5, false).unwrap();
+        let out = String::from_utf8_lossy(&printer.out);
+        // The cursor line's gutter label is bolded, so an escape sequence
+        // separates "1 " from its text; the other row prints contiguously.
+        assert!(out.contains("alpha"), "{out}");
+        assert!(out.contains("2 beta"), "{out}");
+        // The status line names the file.
+        assert!(out.contains("t.txt"), "{out}");
+    }
+
+    #[test]
+    fn rows_below_the_buffer_get_the_eof_marker() {
+        let printer = test_printer();
+        let mut buf = TextBuffer::new();
+        buf.paste("one\ntwo\nthree");
+        buf.set_cursor(0, 0);
+        let frame = printer.build_frame(&buf, 10);
+        let eof_rows = frame.iter().filter(|&r| *r == eof_row()).count();
+        assert_eq!(eof_rows, 7);
+        // A wrapped line that fills the screen leaves no room for markers.
+        let mut printer = test_printer();
+        printer.set_wrap(true);
+        let mut buf = TextBuffer::new();
+        buf.paste(&"x".repeat(500));
+        buf.set_cursor(0, 0);
+        let frame = printer.build_frame(&buf, 10);
+        assert!(!frame.iter().any(|r| *r == eof_row()));
+    }
+
+    #[test]
+    fn the_cursor_row_is_the_one_flagged_for_highlighting() {
+        let mut printer = test_printer();
+        printer.cursor_line_highlight = true;
+        let mut buf = TextBuffer::new();
+        buf.paste("a\nb\nc");
+        buf.set_cursor(1, 0);
+        let flags: Vec<bool> = printer
+            .build_frame(&buf, 3)
+            .iter()
+            .map(|r| r.cursor_line)
+            .collect();
+        assert_eq!(flags, vec![false, true, false]);
+        // Scrolling shifts the highlighted row with the text.
+        buf.scroll_top = 1;
+        let flags: Vec<bool> = printer
+            .build_frame(&buf, 2)
+            .iter()
+            .map(|r| r.cursor_line)
+            .collect();
+        assert_eq!(flags, vec![true, false]);
+        // With the option off no row is flagged.
+        printer.cursor_line_highlight = false;
+        assert!(printer.build_frame(&buf, 2).iter().all(|r| !r.cursor_line));
+    }
+
+    #[test]
+    fn gutter_width_grows_with_line_count() {
+        assert_eq!(gutter_width_for(9), 2);
+        assert_eq!(gutter_width_for(10), 3);
+        assert_eq!(gutter_width_for(1000), 5);
+    }
+
+    #[test]
+    fn tabs_expand_to_the_next_stop() {
+        assert_eq!(expand_tabs("\tfoo\tbar", 4), "    foo bar");
+        assert_eq!(expand_tabs("ab\tc", 4), "ab  c");
+    }
+
+    #[test]
+    fn cursor_screen_column_accounts_for_tabs() {
+        let line = "\tfoo\tbar";
+        assert_eq!(visual_col(line, 0, 4), 0);
+        assert_eq!(visual_col(line, 1, 4), 4);
+        assert_eq!(visual_col(line, 4, 4), 7);
+        assert_eq!(visual_col(line, 5, 4), 8);
+        assert_eq!(visual_col(line, 8, 4), 11);
+    }
+
+    #[test]
+    fn wide_characters_take_two_columns() {
+        // Three double-width kana then two ASCII letters.
+        let line = "\u{3042}\u{3044}\u{3046}cd";
+        assert_eq!(visual_col(line, 3, 4), 6);
+        assert_eq!(visual_col(line, 4, 4), 7);
+        assert_eq!(visual_col(line, 5, 4), 8);
+    }
+
+    #[test]
+    fn column_slice_pads_a_split_wide_character() {
+        let line = "\u{3042}\u{3044}cd";
+        assert_eq!(slice_columns(line, 0, 4), "\u{3042}\u{3044}");
+        // Slicing through the middle of the second kana leaves a space.
+        assert_eq!(slice_columns(line, 3, 3), " cd");
+        assert_eq!(slice_columns(line, 4, 10), "cd");
+    }
+
+    #[test]
+    fn click_column_maps_back_to_char_index() {
+        assert_eq!(char_col_at("abc", 1, 4), 1);
+        assert_eq!(char_col_at("abc", 10, 4), 3);
+        // Both cells of a wide character select it.
+        let line = "\u{3042}\u{3044}cd";
+        assert_eq!(char_col_at(line, 2, 4), 1);
+        assert_eq!(char_col_at(line, 3, 4), 1);
+        assert_eq!(char_col_at(line, 4, 4), 2);
+        // A click anywhere in an expanded tab selects the tab.
+        assert_eq!(char_col_at("\tx", 3, 4), 0);
+        assert_eq!(char_col_at("\tx", 4, 4), 1);
+    }
+
+    #[test]
+    fn wrapped_lines_take_one_row_per_viewport_width() {
+        assert_eq!(wrap_rows(0, 80), 1);
+        assert_eq!(wrap_rows(80, 80), 1);
+        assert_eq!(wrap_rows(81, 80), 2);
+        assert_eq!(wrap_rows(200, 80), 3);
+    }
+
+    #[test]
+    fn visual_row_accounts_for_the_wrapped_lines_above() {
+        // Three lines of widths 5, 25 and 12 wrapped to 10 columns: they
+        // start on visual rows 0, 1 and 4.
+        let widths = [5, 25, 12];
+        assert_eq!(visual_row(&widths, 0, 0, 10), 0);
+        assert_eq!(visual_row(&widths, 1, 0, 10), 1);
+        assert_eq!(visual_row(&widths, 2, 0, 10), 4);
+        // Within a line, every ten columns drop down one row.
+        assert_eq!(visual_row(&widths, 1, 9, 10), 1);
+        assert_eq!(visual_row(&widths, 1, 10, 10), 2);
+        assert_eq!(visual_row(&widths, 1, 24, 10), 3);
+        assert_eq!(visual_row(&widths, 2, 11, 10), 5);
+    }
+
+    #[test]
+    fn scroll_margin_keeps_context_around_the_cursor() {
+        // 10 visible rows, 3 rows of margin, 100-line buffer.
+        // Cursor inside the margins: the view stays put.
+        assert_eq!(vertical_scroll(20, 25, 10, 3, 100), 20);
+        // Cursor on the last unpadded row: one more line scrolls in.
+        assert_eq!(vertical_scroll(20, 26, 10, 3, 100), 20);
+        assert_eq!(vertical_scroll(20, 27, 10, 3, 100), 21);
+        // And symmetrically at the top edge.
+        assert_eq!(vertical_scroll(20, 23, 10, 3, 100), 20);
+        assert_eq!(vertical_scroll(20, 22, 10, 3, 100), 19);
+    }
+
+    #[test]
+    fn scroll_margin_collapses_at_the_buffer_ends() {
+        // The cursor on the last line must not force the view past the end:
+        // the bottom margin shrinks to the lines that actually exist below.
+        assert_eq!(vertical_scroll(89, 99, 10, 3, 100), 90);
+        assert_eq!(vertical_scroll(90, 97, 10, 3, 100), 90);
+        // Same for the first lines and the top margin.
+        assert_eq!(vertical_scroll(1, 0, 10, 3, 100), 0);
+        // A margin larger than half the viewport is capped, not a tug of war.
+        assert_eq!(vertical_scroll(20, 25, 10, 50, 100), 20);
+        assert_eq!(vertical_scroll(20, 26, 10, 50, 100), 21);
+    }
+
+    #[test]
+    fn horizontal_offset_advances_past_right_edge() {
+        // 80-wide viewport: column 79 is the last visible cell.
+        assert_eq!(horizontal_scroll(0, 79, 80), 0);
+        assert_eq!(horizontal_scroll(0, 80, 80), 1);
+        assert_eq!(horizontal_scroll(0, 120, 80), 41);
+    }
+
+    #[test]
+    fn horizontal_offset_snaps_back_when_moving_left() {
+        assert_eq!(horizontal_scroll(41, 40, 80), 40);
+        assert_eq!(horizontal_scroll(41, 0, 80), 0);
+        // Cursor already visible: offset is untouched.
+        assert_eq!(horizontal_scroll(41, 60, 80), 41);
+    }
+
+    fn info(filename: Option<&str>, modified: bool, message: &str) -> StatusInfo {
+        StatusInfo {
+            filename: filename.map(str::to_string),
+            modified,
+            overwrite: false,
+            read_only: false,
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn read_only_buffers_show_an_ro_tag() {
+        let mut status = info(Some("app.log"), false, "");
+        status.read_only = true;
+        let s = format_status(&status, 0, 0, "All", 40);
+        assert!(s.starts_with("app.log [RO]"), "{s:?}");
+    }
+
+    #[test]
+    fn status_shows_name_mode_and_position() {
+        let s = format_status(&info(Some("src/main.rs"), false, ""), 9, 4, "All", 40);
+        assert_eq!(s.chars().count(), 40);
+        assert!(s.starts_with("src/main.rs"));
+        assert!(s.ends_with("INS  Ln 10, Col 5  All"));
+    }
+
+    #[test]
+    fn unnamed_modified_buffers_show_a_star() {
+        let s = format_status(&info(None, true, ""), 0, 0, "All", 40);
+        assert!(s.starts_with("[No Name]*"));
+    }
+
+    #[test]
+    fn long_filenames_are_truncated_to_fit() {
+        let long = "a/".repeat(40);
+        let s = format_status(&info(Some(&long), false, ""), 0, 0, "Top", 30);
+        assert_eq!(s.chars().count(), 30);
+        assert!(s.ends_with("Ln 1, Col 1  Top"));
+    }
+
+    #[test]
+    fn messages_follow_the_filename() {
+        let s = format_status(&info(Some("f.rs"), false, "Saved f.rs"), 0, 0, "All", 60);
+        assert!(s.contains("f.rs  Saved f.rs"));
+    }
+
+    #[test]
+    fn each_mode_maps_to_its_configured_cursor_style() {
+        assert_eq!(
+            cursor_style_for(Mode::Insert, CursorShape::Bar, CursorShape::Block),
+            SetCursorStyle::BlinkingBar
+        );
+        assert_eq!(
+            cursor_style_for(Mode::Overwrite, CursorShape::Bar, CursorShape::Block),
+            SetCursorStyle::BlinkingBlock
+        );
+        // The config can swap in any shape, including the terminal default.
+        assert_eq!(
+            cursor_style_for(Mode::Insert, CursorShape::Underline, CursorShape::Block),
+            SetCursorStyle::BlinkingUnderScore
+        );
+        assert_eq!(
+            cursor_style_for(Mode::Overwrite, CursorShape::Bar, CursorShape::Default),
+            SetCursorStyle::DefaultUserShape
+        );
+    }
+
+    #[test]
+    fn repeating_a_cursor_style_emits_the_escape_once() {
+        let mut printer = test_printer();
+        printer
+            .set_cursor_style(SetCursorStyle::BlinkingBar)
+            .unwrap();
+        printer
+            .set_cursor_style(SetCursorStyle::BlinkingBar)
+            .unwrap();
+        printer
+            .set_cursor_style(SetCursorStyle::BlinkingBlock)
+            .unwrap();
+        let output = String::from_utf8(printer.out).unwrap();
+        assert_eq!(output.matches("\x1b[5 q").count(), 1);
+        assert_eq!(output.matches("\x1b[1 q").count(), 1);
+    }
+
+    #[test]
+    fn the_scroll_indicator_covers_every_viewport_state() {
+        // The whole buffer fits on screen.
+        assert_eq!(scroll_indicator(0, 20, 10), "All");
+        assert_eq!(scroll_indicator(0, 20, 20), "All");
+        // Scrolled all the way up or down.
+        assert_eq!(scroll_indicator(0, 20, 100), "Top");
+        assert_eq!(scroll_indicator(80, 20, 100), "Bot");
+        assert_eq!(scroll_indicator(85, 20, 100), "Bot");
+        // Somewhere in the middle: percentage of the scrollable range.
+        assert_eq!(scroll_indicator(40, 20, 100), "50%");
+        assert_eq!(scroll_indicator(1, 20, 100), "1%");
+        assert_eq!(scroll_indicator(79, 20, 100), "98%");
+    }
+
+    #[test]
+    fn short_list_never_scrolls() {
+        assert_eq!(list_window_start(3, 10, 2), 0);
+    }
+
+    #[test]
+    fn selection_below_window_scrolls_down() {
+        // 20 items, 5 rows: selecting item 7 puts it on the last row.
+        assert_eq!(list_window_start(20, 5, 7), 3);
+        // Selecting the last item pins the window to the end.
+        assert_eq!(list_window_start(20, 5, 19), 15);
+    }
+
+    #[test]
+    fn selection_in_first_window_stays_at_top() {
+        assert_eq!(list_window_start(20, 5, 0), 0);
+        assert_eq!(list_window_start(20, 5, 4), 0);
+    }
+}
@@ -0,0 +1,76 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// The swap-file path for `path`: a dot-prefixed sibling with a `.swp`
+/// suffix, so `notes.txt` autosaves to `.notes.txt.swp` in the same
+/// directory and never collides with a real file the user edits.
+pub fn swap_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("buffer");
+    path.with_file_name(format!(".{name}.swp"))
+}
+
+/// Whether a swap file modified at `swap` holds edits worth offering, given
+/// the original file's mtime. Only a swap strictly newer than the file can
+/// contain anything the file doesn't; a swap whose original has vanished is
+/// all that's left of the text, so it always qualifies.
+pub fn wants_recovery(swap: Option<SystemTime>, file: Option<SystemTime>) -> bool {
+    match (swap, file) {
+        (Some(swap), Some(file)) => swap > file,
+        (Some(_), None) => true,
+        (None, _) => false,
+    }
+}
+
+/// The swap file to offer recovery from when opening `path`, if one exists
+/// and is newer than the file itself.
+pub fn check(path: &Path) -> Option<PathBuf> {
+    let mtime = |p: &Path| fs::metadata(p).and_then(|m| m.modified()).ok();
+    let swap = swap_path(path);
+    wants_recovery(mtime(&swap), mtime(path)).then_some(swap)
+}
+
+/// Autosave the buffer's unsaved contents to its swap file.
+pub fn write_swap(path: &Path, contents: &str) -> io::Result<()> {
+    fs::write(swap_path(path), contents)
+}
+
+/// Delete the swap file after a clean save or exit. A swap that was never
+/// written is not an error.
+pub fn remove_swap(path: &Path) {
+    let _ = fs::remove_file(swap_path(path));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn swap_files_are_hidden_siblings() {
+        assert_eq!(
+            swap_path(Path::new("/home/me/notes.txt")),
+            PathBuf::from("/home/me/.notes.txt.swp")
+        );
+        assert_eq!(swap_path(Path::new("plain")), PathBuf::from(".plain.swp"));
+    }
+
+    #[test]
+    fn only_a_newer_swap_triggers_recovery() {
+        let earlier = SystemTime::UNIX_EPOCH;
+        let later = earlier + Duration::from_secs(60);
+        assert!(wants_recovery(Some(later), Some(earlier)));
+        // A swap older than the file predates the last clean save.
+        assert!(!wants_recovery(Some(earlier), Some(later)));
+        assert!(!wants_recovery(Some(earlier), Some(earlier)));
+        // The original is gone: the swap is all that's left.
+        assert!(wants_recovery(Some(earlier), None));
+        // No swap, nothing to recover.
+        assert!(!wants_recovery(None, Some(earlier)));
+        assert!(!wants_recovery(None, None));
+    }
+}
@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::app::App;
+
+/// A registered command body. It runs with exclusive access to the editor;
+/// an `Err` becomes a status-line message.
+pub type CommandFn<T> = Rc<dyn Fn(&mut T) -> Result<(), String>>;
+
+/// Named commands invokable from command mode by bare name, the editor's
+/// extension seam: external code registers a closure here and built-in
+/// argument-less commands go through the same table, so the mechanism is
+/// exercised on every `:quit`.
+///
+/// Generic over the state the commands mutate so tests can drive it with a
+/// plain buffer; the editor instantiates it at the default, [`App`].
+pub struct CommandRegistry<T = App> {
+    commands: HashMap<String, CommandFn<T>>,
+}
+
+impl<T> CommandRegistry<T> {
+    pub fn new() -> Self {
+        CommandRegistry {
+            commands: HashMap::new(),
+        }
+    }
+
+    /// Register `run` under `name`, replacing any previous holder of the
+    /// name.
+    pub fn register(&mut self, name: &str, run: impl Fn(&mut T) -> Result<(), String> + 'static) {
+        self.commands.insert(name.to_string(), Rc::new(run));
+    }
+
+    /// Look up a command. The handler comes back as its own `Rc` clone, so
+    /// the borrow of the registry — and of whatever holds it — ends before
+    /// the handler runs: the `&mut T` it receives is then the only live
+    /// access to that state, which is what makes handing `&mut App` to
+    /// arbitrary registered code sound.
+    pub fn get(&self, name: &str) -> Option<CommandFn<T>> {
+        self.commands.get(name).cloned()
+    }
+}
+
+impl<T> Default for CommandRegistry<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::TextBuffer;
+
+    #[test]
+    fn a_registered_command_runs_against_the_passed_state() {
+        let mut registry: CommandRegistry<TextBuffer> = CommandRegistry::new();
+        registry.register("greet", |buf| {
+            buf.paste("hello");
+            Ok(())
+        });
+        let mut buf = TextBuffer::new();
+        let run = registry.get("greet").expect("registered above");
+        run(&mut buf).unwrap();
+        assert_eq!(buf.lines, vec!["hello"]);
+        assert!(registry.get("missing").is_none());
+    }
+
+    #[test]
+    fn re_registering_a_name_replaces_the_old_command() {
+        let mut registry: CommandRegistry<TextBuffer> = CommandRegistry::new();
+        registry.register("cmd", |_| Err("old".to_string()));
+        registry.register("cmd", |_| Err("new".to_string()));
+        let run = registry.get("cmd").expect("registered above");
+        assert_eq!(run(&mut TextBuffer::new()), Err("new".to_string()));
+    }
+}
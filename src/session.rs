@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Remembered per-file editor state, so reopening a file lands where the
+/// last session left off. Each entry keys the file's absolute path to its
+/// `(cursor line, cursor column, first visible line)` tuple; positions are
+/// clamped on restore, so a file that shrank in the meantime is harmless.
+#[derive(Debug, Default)]
+pub struct Session {
+    entries: HashMap<PathBuf, (usize, usize, usize)>,
+}
+
+impl Session {
+    /// The saved session from the state file, or an empty one if there is
+    /// no file or it cannot be read. A corrupt line is skipped rather than
+    /// discarding the rest.
+    pub fn load() -> Session {
+        match Self::state_path().map(fs::read_to_string) {
+            Some(Ok(text)) => Self::parse(&text),
+            _ => Session::default(),
+        }
+    }
+
+    /// Write the session back to the state file, creating the cache
+    /// directory on first use.
+    pub fn save(&self) -> io::Result<()> {
+        let Some(path) = Self::state_path() else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.serialize())
+    }
+
+    /// `$XDG_CACHE_HOME/trust/session`, falling back to
+    /// `~/.cache/trust/session`.
+    fn state_path() -> Option<PathBuf> {
+        let base = env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".cache")))?;
+        Some(base.join("trust").join("session"))
+    }
+
+    /// One entry per line: the three numbers, then the path, which may
+    /// itself contain spaces and so comes last.
+    pub fn parse(text: &str) -> Session {
+        let mut entries = HashMap::new();
+        for line in text.lines() {
+            let mut parts = line.splitn(4, ' ');
+            let Some(((line, col), top)) = parts
+                .next()
+                .and_then(|n| n.parse().ok())
+                .zip(parts.next().and_then(|n| n.parse().ok()))
+                .zip(parts.next().and_then(|n| n.parse().ok()))
+            else {
+                continue;
+            };
+            let Some(path) = parts.next().filter(|p| !p.is_empty()) else {
+                continue;
+            };
+            entries.insert(PathBuf::from(path), (line, col, top));
+        }
+        Session { entries }
+    }
+
+    /// The state-file text for this session. Entries come out sorted so the
+    /// file diffs cleanly between runs.
+    pub fn serialize(&self) -> String {
+        let mut entries: Vec<_> = self.entries.iter().collect();
+        entries.sort();
+        let mut out = String::new();
+        for (path, (line, col, top)) in entries {
+            out.push_str(&format!("{line} {col} {top} {}\n", path.display()));
+        }
+        out
+    }
+
+    /// The remembered state for `path`, if any.
+    pub fn get(&self, path: &Path) -> Option<(usize, usize, usize)> {
+        self.entries.get(&absolute(path)).copied()
+    }
+
+    /// Record (or replace) the state for `path`.
+    pub fn remember(&mut self, path: &Path, state: (usize, usize, usize)) {
+        self.entries.insert(absolute(path), state);
+    }
+}
+
+/// The canonical absolute form of `path`, so `foo.txt` opened from two
+/// working directories shares one entry. Falls back to joining the current
+/// directory when the file doesn't exist yet.
+fn absolute(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| {
+        if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            env::current_dir()
+                .map(|dir| dir.join(path))
+                .unwrap_or_else(|_| path.to_path_buf())
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_state_round_trips_through_the_file_format() {
+        let mut session = Session::default();
+        session.remember(Path::new("/tmp/notes.txt"), (12, 4, 8));
+        session.remember(Path::new("/tmp/with space.txt"), (0, 0, 0));
+        let reloaded = Session::parse(&session.serialize());
+        assert_eq!(reloaded.get(Path::new("/tmp/notes.txt")), Some((12, 4, 8)));
+        assert_eq!(
+            reloaded.get(Path::new("/tmp/with space.txt")),
+            Some((0, 0, 0))
+        );
+    }
+
+    #[test]
+    fn unknown_files_have_no_entry() {
+        let session = Session::parse("3 1 0 /tmp/a.txt\n");
+        assert_eq!(session.get(Path::new("/tmp/b.txt")), None);
+    }
+
+    #[test]
+    fn corrupt_lines_are_skipped() {
+        let text = "3 1 0 /tmp/a.txt\nnot numbers here\n7 0 2\n5 2 1 /tmp/b.txt\n";
+        let session = Session::parse(text);
+        assert_eq!(session.get(Path::new("/tmp/a.txt")), Some((3, 1, 0)));
+        assert_eq!(session.get(Path::new("/tmp/b.txt")), Some((5, 2, 1)));
+    }
+}
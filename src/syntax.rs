@@ -0,0 +1,183 @@
+use std::path::Path;
+
+/// What a highlighted region of text is, mapped to a color by the printer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Keyword,
+    String,
+    Comment,
+    Number,
+}
+
+/// A highlighted region of one line. `start..end` are char columns into the
+/// raw line, the same unit the cursor uses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub kind: TokenKind,
+}
+
+/// Produces highlight spans for single lines. Operating per line keeps
+/// highlighting cheap — only the visible window is ever tokenized — at the
+/// cost of constructs that span lines, like block comments.
+pub trait Highlighter {
+    fn highlight_line(&self, line: &str) -> Vec<Span>;
+}
+
+/// The highlighter for `path`, or `None` for extensions we don't know,
+/// which render as plain text.
+pub fn for_path(path: &Path) -> Option<Box<dyn Highlighter>> {
+    match path.extension()?.to_str()? {
+        "rs" => Some(Box::new(RustHighlighter)),
+        _ => None,
+    }
+}
+
+/// The line-comment prefix for `path`'s language, if we know it.
+pub fn comment_prefix(path: &Path) -> Option<&'static str> {
+    match path.extension()?.to_str()? {
+        "rs" | "c" | "h" | "cpp" | "js" | "ts" | "go" => Some("//"),
+        "py" | "sh" | "rb" | "toml" | "yaml" | "yml" => Some("#"),
+        _ => None,
+    }
+}
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+    "unsafe", "use", "where", "while",
+];
+
+/// A small hand-rolled tokenizer for Rust: keywords, `//` comments, string
+/// and char literals, and numbers. Good enough to make code scannable
+/// without pulling in a grammar engine.
+pub struct RustHighlighter;
+
+impl Highlighter for RustHighlighter {
+    fn highlight_line(&self, line: &str) -> Vec<Span> {
+        let chars: Vec<char> = line.chars().collect();
+        let mut spans = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            if c == '/' && chars.get(i + 1) == Some(&'/') {
+                spans.push(Span {
+                    start: i,
+                    end: chars.len(),
+                    kind: TokenKind::Comment,
+                });
+                break;
+            }
+            if c == '"' {
+                let start = i;
+                i += 1;
+                while i < chars.len() {
+                    match chars[i] {
+                        '\\' => i += 2,
+                        '"' => {
+                            i += 1;
+                            break;
+                        }
+                        _ => i += 1,
+                    }
+                }
+                spans.push(Span {
+                    start,
+                    end: i.min(chars.len()),
+                    kind: TokenKind::String,
+                });
+                continue;
+            }
+            if c.is_ascii_digit() {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                spans.push(Span {
+                    start,
+                    end: i,
+                    kind: TokenKind::Number,
+                });
+                continue;
+            }
+            if c.is_alphabetic() || c == '_' {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                if RUST_KEYWORDS.contains(&word.as_str()) {
+                    spans.push(Span {
+                        start,
+                        end: i,
+                        kind: TokenKind::Keyword,
+                    });
+                }
+                continue;
+            }
+            i += 1;
+        }
+        spans
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(start: usize, end: usize, kind: TokenKind) -> Span {
+        Span { start, end, kind }
+    }
+
+    #[test]
+    fn statement_with_string_and_comment() {
+        let spans = RustHighlighter.highlight_line("let x = \"hi\"; // c");
+        assert_eq!(
+            spans,
+            vec![
+                span(0, 3, TokenKind::Keyword),
+                span(8, 12, TokenKind::String),
+                span(14, 18, TokenKind::Comment),
+            ]
+        );
+    }
+
+    #[test]
+    fn escaped_quotes_stay_inside_the_string() {
+        let spans = RustHighlighter.highlight_line("\"a\\\"b\" x");
+        assert_eq!(spans, vec![span(0, 6, TokenKind::String)]);
+    }
+
+    #[test]
+    fn slashes_inside_strings_do_not_start_comments() {
+        let spans = RustHighlighter.highlight_line("\"http://x\"");
+        assert_eq!(spans, vec![span(0, 10, TokenKind::String)]);
+    }
+
+    #[test]
+    fn numbers_and_identifiers_are_told_apart() {
+        let spans = RustHighlighter.highlight_line("foo(42, 0xff)");
+        assert_eq!(
+            spans,
+            vec![
+                span(4, 6, TokenKind::Number),
+                span(8, 12, TokenKind::Number)
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_extensions_get_no_highlighter() {
+        assert!(for_path(Path::new("notes.txt")).is_none());
+        assert!(for_path(Path::new("main.rs")).is_some());
+    }
+
+    #[test]
+    fn comment_prefixes_follow_the_language() {
+        assert_eq!(comment_prefix(Path::new("main.rs")), Some("//"));
+        assert_eq!(comment_prefix(Path::new("setup.py")), Some("#"));
+        assert_eq!(comment_prefix(Path::new("notes.txt")), None);
+    }
+}